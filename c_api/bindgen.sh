@@ -11,4 +11,6 @@ bindgen --use-core --no-layout-tests  \
   --whitelist-type set_context \
   --whitelist-type protect \
   --whitelist-type recover \
+  --whitelist-type error_code \
+  --whitelist-var "KYNC_ERROR_.*" \
   kync.h
\ No newline at end of file
@@ -93,4 +93,24 @@ typedef const char* (*protect)(write_t* sink, const slice_t* data, const slice_t
 typedef const char* (*recover)(write_t* sink, const slice_t* data, const slice_t* auth);
 
 
+/// The error codes (API v2)
+#define KYNC_ERROR_UNKNOWN 0
+#define KYNC_ERROR_AUTH_REQUIRED 1
+#define KYNC_ERROR_AUTH_FAILED 2
+#define KYNC_ERROR_RETRIES_EXHAUSTED 3
+#define KYNC_ERROR_INVALID_CONFIG 4
+#define KYNC_ERROR_INVALID_CAPSULE 5
+#define KYNC_ERROR_DEVICE_UNAVAILABLE 6
+#define KYNC_ERROR_CANCELLED 7
+#define KYNC_ERROR_INTERNAL 8
+
+
+/// Maps an error pointer returned by an API call to an error code (API v2 only)
+///
+/// \param error The error pointer returned by an API call
+/// \return The error code or `KYNC_ERROR_UNKNOWN` if the pointer was not created by the plugin (e.g.
+///         because it is a propagated callback error)
+typedef uint8_t (*error_code)(const char* error);
+
+
 #endif //KYNC_H
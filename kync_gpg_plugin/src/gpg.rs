@@ -0,0 +1,142 @@
+use kync_plugin::{ Error, error };
+use std::{
+	io::{ self, Write },
+	path::Path, thread,
+	process::{ Command, Output, Stdio }
+};
+use zeroize::Zeroizing;
+
+
+/// The GnuPG executable
+const GPG: &str = "gpg";
+
+
+/// The result of a `gpg` invocation
+struct Invocation {
+	/// Whether `gpg` exited successfully
+	success: bool,
+	/// The data written to stdout
+	stdout: Zeroizing<Vec<u8>>,
+	/// The `[GNUPG:]` status keywords written to stderr
+	status: Vec<String>
+}
+impl Invocation {
+	/// Checks if the status keyword `keyword` has been emitted
+	fn has(&self, keyword: &str) -> bool {
+		self.status.iter().any(|s| s == keyword)
+	}
+}
+
+
+/// Runs `gpg` with `args` in `home` (or the default home) and writes `input` to its stdin
+fn run(home: Option<&Path>, args: &[&str], input: Zeroizing<Vec<u8>>) -> Result<Invocation, Error> {
+	// Create the command
+	let mut command = Command::new(GPG);
+	if let Some(home) = home {
+		command.arg("--homedir").arg(home);
+	}
+	command.args(["--batch", "--no-tty", "--quiet", "--status-fd", "2"]).args(args)
+		.stdin(Stdio::piped()).stdout(Stdio::piped()).stderr(Stdio::piped());
+	let mut child = command.spawn().map_err(|e| match e.kind() {
+		io::ErrorKind::NotFound => error!(DeviceUnavailable, "GnuPG is not installed"),
+		_ => error!(Internal, "Failed to start GnuPG")
+	})?;
+	
+	// Write the input in the background to avoid a deadlock with the output pipes
+	let mut stdin = child.stdin.take().unwrap();
+	let writer = thread::spawn(move || {
+		// `gpg` may exit early without reading everything, so a broken pipe is not an error
+		let _ = stdin.write_all(&input);
+	});
+	let Output { status, stdout, stderr } = child.wait_with_output()
+		.map_err(|_| error!(Internal, "Failed to communicate with GnuPG"))?;
+	let _ = writer.join();
+	
+	// Collect the status keywords
+	let status_lines = String::from_utf8_lossy(&stderr);
+	let keywords = status_lines.lines()
+		.filter_map(|line| line.strip_prefix("[GNUPG:] "))
+		.filter_map(|line| line.split_whitespace().next())
+		.map(str::to_string)
+		.collect();
+	Ok(Invocation { success: status.success(), stdout: Zeroizing::new(stdout), status: keywords })
+}
+
+
+/// Lists the fingerprints of the primary keys that have a secret key and can encrypt
+pub fn encryption_keys(home: Option<&Path>) -> Result<Vec<Vec<u8>>, Error> {
+	let args = ["--with-colons", "--fixed-list-mode", "--list-secret-keys"];
+	let result = run(home, &args, Zeroizing::new(Vec::new()))?;
+	if !result.success {
+		return Err(error!(DeviceUnavailable, "Failed to list the GnuPG keys"))
+	}
+	
+	// Parse the colon listing (a `sec` record is followed by its `fpr` record)
+	let (mut keys, mut usable) = (Vec::new(), false);
+	for line in String::from_utf8_lossy(&result.stdout).lines() {
+		let fields: Vec<&str> = line.split(':').collect();
+		let field = |index: usize| fields.get(index).copied().unwrap_or_default();
+		match field(0) {
+			"sec" => usable = !["i", "d", "r", "e"].contains(&field(1)) && field(11).contains('E'),
+			"fpr" if usable => {
+				keys.push(field(9).as_bytes().to_vec());
+				usable = false;
+			},
+			"fpr" => (),
+			_ => usable = false
+		}
+	}
+	Ok(keys)
+}
+
+
+/// Encrypts `data` to the key with `fingerprint`
+pub fn encrypt(home: Option<&Path>, data: &[u8], fingerprint: &str) -> Result<Vec<u8>, Error> {
+	let args = ["--trust-model", "always", "--encrypt", "--recipient", fingerprint, "--output",
+		"-"];
+	let result = run(home, &args, Zeroizing::new(data.to_vec()))?;
+	match result.success {
+		true => Ok(result.stdout.to_vec()),
+		false if result.has("INV_RECP") =>
+			Err(error!(InvalidConfig, "The key does not exist or cannot encrypt")),
+		false => Err(error!(Internal, "GnuPG failed to encrypt the secret"))
+	}
+}
+
+
+/// Decrypts `capsule` through the gpg-agent, either with pinentry or with the loopback `passphrase`
+pub fn decrypt(home: Option<&Path>, capsule: &[u8], passphrase: Option<&[u8]>)
+	-> Result<Zeroizing<Vec<u8>>, Error>
+{
+	// The loopback passphrase is passed as first line on stdin and followed by the capsule
+	let (mut args, mut input) = (vec!["--decrypt"], Zeroizing::new(Vec::new()));
+	if let Some(passphrase) = passphrase {
+		if passphrase.contains(&b'\n') {
+			return Err(error!(AuthFailed, "The passphrase must not contain a line break"))
+		}
+		args = vec!["--pinentry-mode", "loopback", "--passphrase-fd", "0", "--decrypt"];
+		input.extend_from_slice(passphrase);
+		input.push(b'\n');
+	}
+	input.extend_from_slice(capsule);
+	
+	// Run gpg and map the status to an error (gpg also succeeds for unencrypted messages, so the
+	// output is only accepted if it has actually been decrypted)
+	let result = run(home, &args, input)?;
+	match result.success {
+		true if result.has("DECRYPTION_OKAY") => Ok(result.stdout),
+		true => Err(error!(InvalidCapsule, "The OpenPGP message is not encrypted")),
+		false if result.has("NODATA") => Err(error!(InvalidCapsule, "Invalid OpenPGP message")),
+		false if result.has("CANCELED_BY_USER") =>
+			Err(error!(Cancelled, "The passphrase entry has been cancelled")),
+		false if result.has("BAD_PASSPHRASE") => Err(error!(AuthFailed, "Invalid passphrase")),
+		false if result.has("NO_SECKEY") =>
+			Err(error!(DeviceUnavailable, "The secret key is not available")),
+		// gpg 2.2 only reports a failed decryption if the passphrase is wrong
+		false if result.has("DECRYPTION_FAILED") => match passphrase {
+			Some(_) => Err(error!(AuthFailed, "Invalid passphrase")),
+			None => Err(error!(AuthRequired, "The gpg-agent could not obtain the passphrase"))
+		},
+		false => Err(error!(Internal, "GnuPG failed to decrypt the capsule"))
+	}
+}
@@ -0,0 +1,92 @@
+/// A thin wrapper around the `gpg` command-line tool
+mod gpg;
+
+use kync_plugin::{ Error, Plugin, Sink, error, export_plugin };
+use std::{ path::PathBuf, sync::Mutex };
+
+
+const UID: &[u8] = b"GpgCapsuleFormat.4783A415-199E-4388-8314-95759018DE3E";
+
+
+/// Converts the raw path `bytes` into a path
+fn to_path(bytes: &[u8]) -> Result<PathBuf, Error> {
+	#[cfg(unix)]
+	return Ok(<std::ffi::OsStr as std::os::unix::ffi::OsStrExt>::from_bytes(bytes).into());
+	#[cfg(not(unix))]
+	return std::str::from_utf8(bytes).map(PathBuf::from)
+		.map_err(|_| error!(InvalidConfig, "The GnuPG home is not UTF-8"));
+}
+/// Validates that `config` is a hex key fingerprint
+fn fingerprint(config: &[u8]) -> Result<&str, Error> {
+	let is_fingerprint = [40, 64].contains(&config.len())
+		&& config.iter().all(|b| b.is_ascii_hexdigit());
+	match is_fingerprint {
+		true => Ok(std::str::from_utf8(config).unwrap()),
+		false => Err(error!(InvalidConfig, "Invalid configuration (expected a key fingerprint)"))
+	}
+}
+
+
+/// A plugin that protects secrets with GnuPG keys
+///
+/// The configs are the fingerprints of the local keys that can encrypt and have a secret key; the
+/// capsule is a plain OpenPGP message. The secret key is unlocked by the gpg-agent, either with its
+/// pinentry or – if an authentication is passed – with the authentication as loopback
+/// passphrase. The GnuPG home can be set with `set_context`; by default, `$GNUPGHOME` or `~/.gnupg`
+/// is used.
+pub struct GpgPlugin {
+	home: Mutex<Option<PathBuf>>
+}
+impl GpgPlugin {
+	/// The GnuPG home set with `set_context`
+	fn home(&self) -> Option<PathBuf> {
+		self.home.lock().unwrap_or_else(|e| e.into_inner()).clone()
+	}
+}
+impl Plugin for GpgPlugin {
+	fn init(_log_level: u8) -> Result<Self, Error> {
+		Ok(Self { home: Mutex::new(None) })
+	}
+	
+	fn id(&self) -> &[u8] {
+		UID
+	}
+	
+	fn configs(&self) -> Result<Vec<Vec<u8>>, Error> {
+		gpg::encryption_keys(self.home().as_deref())
+	}
+	
+	fn set_context(&self, context: &[u8]) -> Result<(), Error> {
+		let home = to_path(context)?;
+		if !home.is_dir() {
+			return Err(error!(DeviceUnavailable, "The GnuPG home does not exist"))
+		}
+		*self.home.lock().unwrap_or_else(|e| e.into_inner()) = Some(home);
+		Ok(())
+	}
+	
+	fn auth_info_protect(&self, config: &[u8]) -> Result<(bool, u64), Error> {
+		// Encryption only needs the public key
+		fingerprint(config)?;
+		Ok((false, u64::MAX))
+	}
+	
+	fn auth_info_recover(&self, config: &[u8]) -> Result<(bool, u64), Error> {
+		// The passphrase is optional because the gpg-agent can ask for it itself
+		fingerprint(config)?;
+		Ok((false, u64::MAX))
+	}
+	
+	fn protect(&self, sink: &mut Sink, data: &[u8], config: &[u8], _auth: Option<&[u8]>)
+		-> Result<(), Error>
+	{
+		let capsule = gpg::encrypt(self.home().as_deref(), data, fingerprint(config)?)?;
+		sink.write(capsule)
+	}
+	
+	fn recover(&self, sink: &mut Sink, data: &[u8], auth: Option<&[u8]>) -> Result<(), Error> {
+		let plaintext = gpg::decrypt(self.home().as_deref(), data, auth)?;
+		sink.write(plaintext.as_slice())
+	}
+}
+export_plugin!(GpgPlugin);
@@ -0,0 +1,228 @@
+use blake2::{
+	Blake2b, Digest,
+	digest::consts::{ U16, U32 }
+};
+use chacha20poly1305::{
+	Key, XChaCha20Poly1305, XNonce,
+	aead::{ Aead, KeyInit, Payload }
+};
+use kync_plugin::{ Error, Plugin, Sink, error, export_plugin };
+use std::{
+	env, fs, io::ErrorKind, path::PathBuf, sync::Mutex,
+	convert::TryInto
+};
+use zeroize::Zeroizing;
+
+
+const UID: &[u8] = b"KeyfileCapsuleFormat.4D3C0E1A-6B7F-4E52-9A0D-2F8C5B1E7A93";
+
+/// The capsule format version
+const VERSION: u8 = 1;
+/// The minimum length of a keyfile
+const MIN_KEYFILE_LEN: usize = 32;
+/// The domain separators for the key and the key ID derived from a keyfile
+const KEY_DOMAIN: &[u8] = b"KyNc keyfile key\0";
+const ID_DOMAIN: &[u8] = b"KyNc keyfile ID\0";
+
+const ID_LEN: usize = 16;
+const NONCE_LEN: usize = 24;
+
+
+/// Converts the raw path `bytes` into a path
+fn to_path(bytes: &[u8]) -> Result<PathBuf, Error> {
+	#[cfg(unix)]
+	return Ok(<std::ffi::OsStr as std::os::unix::ffi::OsStrExt>::from_bytes(bytes).into());
+	#[cfg(not(unix))]
+	return std::str::from_utf8(bytes).map(PathBuf::from)
+		.map_err(|_| error!(InvalidConfig, "The keyfile path is not UTF-8"));
+}
+
+
+/// A key loaded from a keyfile
+struct Keyfile {
+	key: Zeroizing<Vec<u8>>,
+	id: Vec<u8>
+}
+impl Keyfile {
+	/// Loads the keyfile at `path` and derives the key and the key ID
+	fn load(path: &[u8]) -> Result<Self, Error> {
+		let path = to_path(path)?;
+		let metadata = fs::metadata(&path).map_err(|e| match e.kind() {
+			ErrorKind::NotFound => error!(DeviceUnavailable, "The keyfile does not exist"),
+			ErrorKind::PermissionDenied => error!(DeviceUnavailable, "The keyfile is not readable"),
+			_ => error!(DeviceUnavailable, "Failed to access the keyfile")
+		})?;
+		if !metadata.is_file() {
+			return Err(error!(InvalidConfig, "The keyfile is not a regular file"))
+		}
+		#[cfg(unix)]
+		{
+			use std::os::unix::fs::MetadataExt;
+			if metadata.mode() & 0o077 != 0 {
+				return Err(error!(InvalidConfig,
+					"The keyfile is accessible by other users (use `chmod 600` or stricter)"))
+			}
+		}
+		
+		// Read the keyfile and derive the key
+		let contents = fs::read(&path).map(Zeroizing::new)
+			.map_err(|_| error!(DeviceUnavailable, "Failed to read the keyfile"))?;
+		if contents.len() < MIN_KEYFILE_LEN {
+			return Err(error!(InvalidConfig, "The keyfile must contain at least 32 bytes"))
+		}
+		let key = Blake2b::<U32>::new_with_prefix(KEY_DOMAIN).chain_update(&contents).finalize();
+		let id = Blake2b::<U16>::new_with_prefix(ID_DOMAIN).chain_update(&contents).finalize();
+		Ok(Self { key: Zeroizing::new(key.to_vec()), id: id.to_vec() })
+	}
+	
+	/// Creates the cipher
+	fn cipher(&self) -> XChaCha20Poly1305 {
+		XChaCha20Poly1305::new(Key::from_slice(&self.key))
+	}
+}
+
+
+/// The parsed header of a capsule
+struct Header<'a> {
+	path: &'a [u8],
+	id: &'a [u8],
+	nonce: &'a [u8],
+	/// The raw header which is authenticated as associated data
+	raw: &'a [u8]
+}
+impl<'a> Header<'a> {
+	/// Parses the header and returns it together with the ciphertext
+	fn parse(capsule: &'a [u8]) -> Result<(Self, &'a [u8]), Error> {
+		const INVALID: Error = error!(InvalidCapsule, "Invalid or truncated capsule");
+		if capsule.len() < 3 || capsule[0] != VERSION {
+			return Err(INVALID)
+		}
+		
+		let path_len = u16::from_be_bytes(capsule[1..3].try_into().unwrap()) as usize;
+		let len = 3 + path_len + ID_LEN + NONCE_LEN;
+		if capsule.len() < len {
+			return Err(INVALID)
+		}
+		let (raw, ciphertext) = capsule.split_at(len);
+		let (path, rest) = raw[3..].split_at(path_len);
+		let (id, nonce) = rest.split_at(ID_LEN);
+		Ok((Self { path, id, nonce, raw }, ciphertext))
+	}
+	/// Serializes a header for `path`, `id` and `nonce`
+	fn serialize(path: &[u8], id: &[u8], nonce: &[u8]) -> Result<Vec<u8>, Error> {
+		let path_len: u16 = path.len().try_into()
+			.map_err(|_| error!(InvalidConfig, "The keyfile path is too long"))?;
+		let mut bytes = vec![VERSION];
+		bytes.extend_from_slice(&path_len.to_be_bytes());
+		bytes.extend_from_slice(path);
+		bytes.extend_from_slice(id);
+		bytes.extend_from_slice(nonce);
+		Ok(bytes)
+	}
+}
+
+
+/// A plugin that protects secrets with a key stored in a local keyfile
+///
+/// The configs are keyfile paths. `set_context` sets the keyfile path which is then offered as
+/// config and used for recovery instead of the path recorded in the capsule (an empty context
+/// resets it). Keyfiles must contain at least 32 random bytes and must not be accessible by other
+/// users.
+pub struct KeyfilePlugin {
+	context: Mutex<Option<Vec<u8>>>
+}
+impl KeyfilePlugin {
+	/// The keyfile path set by `set_context`
+	fn context(&self) -> Option<Vec<u8>> {
+		self.context.lock().unwrap_or_else(|e| e.into_inner()).clone()
+	}
+	/// The default keyfile path (`$XDG_CONFIG_HOME/kync/keyfile` or `$HOME/.config/kync/keyfile`)
+	fn default_path() -> Result<Vec<u8>, Error> {
+		let config = env::var_os("XDG_CONFIG_HOME").map(PathBuf::from)
+			.or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+			.ok_or(error!(InvalidConfig, "No keyfile has been set and there is no default"))?;
+		let path = config.join("kync").join("keyfile");
+		#[cfg(unix)]
+		return Ok(std::os::unix::ffi::OsStringExt::into_vec(path.into_os_string()));
+		#[cfg(not(unix))]
+		return path.into_os_string().into_string()
+			.map(String::into_bytes)
+			.map_err(|_| error!(InvalidConfig, "The keyfile path is not UTF-8"));
+	}
+	/// Validates the config
+	fn check_config(config: &[u8]) -> Result<(), Error> {
+		match config.is_empty() {
+			true => Err(error!(InvalidConfig, "The keyfile path must not be empty")),
+			false => Ok(())
+		}
+	}
+}
+impl Plugin for KeyfilePlugin {
+	fn init(_log_level: u8) -> Result<Self, Error> {
+		Ok(Self { context: Mutex::new(None) })
+	}
+	
+	fn id(&self) -> &[u8] {
+		UID
+	}
+	
+	fn configs(&self) -> Result<Vec<Vec<u8>>, Error> {
+		match self.context() {
+			Some(path) => Ok(vec![path]),
+			None => Ok(vec![Self::default_path()?])
+		}
+	}
+	
+	fn set_context(&self, context: &[u8]) -> Result<(), Error> {
+		let context = match context.is_empty() {
+			true => None,
+			false => Some(context.to_vec())
+		};
+		*self.context.lock().unwrap_or_else(|e| e.into_inner()) = context;
+		Ok(())
+	}
+	
+	fn auth_info_protect(&self, config: &[u8]) -> Result<(bool, u64), Error> {
+		Self::check_config(config)?;
+		Ok((false, u64::MAX))
+	}
+	
+	fn auth_info_recover(&self, config: &[u8]) -> Result<(bool, u64), Error> {
+		Self::check_config(config)?;
+		Ok((false, u64::MAX))
+	}
+	
+	fn protect(&self, sink: &mut Sink, data: &[u8], config: &[u8], _auth: Option<&[u8]>)
+		-> Result<(), Error>
+	{
+		Self::check_config(config)?;
+		let keyfile = Keyfile::load(config)?;
+		let mut nonce = [0; NONCE_LEN];
+		getrandom::getrandom(&mut nonce)
+			.map_err(|_| error!(Internal, "Failed to gather randomness"))?;
+		
+		// Seal the data and authenticate the header
+		let mut capsule = Header::serialize(config, &keyfile.id, &nonce)?;
+		let payload = Payload { msg: data, aad: &capsule };
+		let ciphertext = keyfile.cipher().encrypt(XNonce::from_slice(&nonce), payload)
+			.map_err(|_| error!(Internal, "Failed to seal the secret"))?;
+		capsule.extend_from_slice(&ciphertext);
+		sink.write(capsule)
+	}
+	
+	fn recover(&self, sink: &mut Sink, data: &[u8], _auth: Option<&[u8]>) -> Result<(), Error> {
+		let (header, ciphertext) = Header::parse(data)?;
+		let path = self.context().unwrap_or_else(|| header.path.to_vec());
+		let keyfile = Keyfile::load(&path)?;
+		if keyfile.id.as_slice() != header.id {
+			return Err(error!(AuthFailed, "The keyfile does not match the capsule"))
+		}
+		
+		// Open the data
+		let payload = Payload { msg: ciphertext, aad: header.raw };
+		let plaintext = keyfile.cipher().decrypt(XNonce::from_slice(header.nonce), payload)
+			.map_err(|_| error!(InvalidCapsule, "The capsule is corrupt"))?;
+		sink.write(Zeroizing::new(plaintext).as_slice())
+	}
+}
+export_plugin!(KeyfilePlugin);
@@ -0,0 +1,87 @@
+use kync_plugin::{ Error, error };
+use std::{ io, ffi::CStr, os::raw::c_long };
+use zeroize::Zeroizing;
+
+
+/// A key or keyring serial number
+pub type Serial = i32;
+
+/// The type of the keys used by this plugin
+const KEY_TYPE: &[u8] = b"user\0";
+
+
+/// Maps the last OS error to a plugin error
+fn last_error() -> Error {
+	match io::Error::last_os_error().raw_os_error().unwrap_or_default() {
+		libc::ENOKEY | libc::EKEYEXPIRED | libc::EKEYREVOKED =>
+			error!(DeviceUnavailable, "The key is not available in any keyring"),
+		libc::EACCES | libc::EPERM => error!(AuthFailed, "Access to the key has been denied"),
+		libc::EDQUOT => error!(DeviceUnavailable, "The keyring quota has been exceeded"),
+		libc::ENOSYS | libc::EOPNOTSUPP =>
+			error!(DeviceUnavailable, "The kernel keyring is not supported"),
+		_ => error!(Internal, "A keyring operation failed")
+	}
+}
+/// Checks the result of a syscall
+fn check(result: c_long) -> Result<c_long, Error> {
+	match result < 0 {
+		true => Err(last_error()),
+		false => Ok(result)
+	}
+}
+
+
+/// Resolves the keyring `name` (`session`, `user` or `persistent`)
+pub fn keyring(name: &[u8]) -> Result<Serial, Error> {
+	let spec = match name {
+		b"session" => libc::KEY_SPEC_SESSION_KEYRING,
+		b"user" => libc::KEY_SPEC_USER_KEYRING,
+		b"persistent" => {
+			// Get the persistent keyring of the current user and link it to the session keyring
+			let (uid, session) = (-1 as c_long, libc::KEY_SPEC_SESSION_KEYRING as c_long);
+			let result = unsafe {
+				libc::syscall(libc::SYS_keyctl, libc::KEYCTL_GET_PERSISTENT as c_long, uid, session)
+			};
+			return check(result).map(|serial| serial as Serial)
+		},
+		_ => return Err(error!(InvalidConfig, "Invalid keyring"))
+	};
+	let result = unsafe {
+		libc::syscall(libc::SYS_keyctl, libc::KEYCTL_GET_KEYRING_ID as c_long, spec as c_long,
+			1 as c_long)
+	};
+	check(result).map(|serial| serial as Serial)
+}
+
+
+/// Searches `keyring` and its linked keyrings for a key with `description`
+pub fn search(keyring: Serial, description: &CStr) -> Result<Option<Serial>, Error> {
+	let result = unsafe {
+		libc::syscall(libc::SYS_keyctl, libc::KEYCTL_SEARCH as c_long, keyring as c_long,
+			KEY_TYPE.as_ptr(), description.as_ptr(), 0 as c_long)
+	};
+	if result < 0 && io::Error::last_os_error().raw_os_error() == Some(libc::ENOKEY) {
+		return Ok(None)
+	}
+	check(result).map(|key| Some(key as Serial))
+}
+
+
+/// Reads the payload of `key`
+pub fn read(key: Serial) -> Result<Zeroizing<Vec<u8>>, Error> {
+	let mut payload = Zeroizing::new(vec![0; 64]);
+	loop {
+		let result = unsafe {
+			libc::syscall(libc::SYS_keyctl, libc::KEYCTL_READ as c_long, key as c_long,
+				payload.as_mut_ptr(), payload.len())
+		};
+		match check(result)? as usize {
+			len if len <= payload.len() => {
+				payload.truncate(len);
+				return Ok(payload)
+			},
+			// Grow the buffer (replacing it zeroizes the old buffer)
+			len => payload = Zeroizing::new(vec![0; len])
+		}
+	}
+}
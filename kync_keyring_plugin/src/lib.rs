@@ -0,0 +1,147 @@
+//! A KyNc plugin that protects secrets with keys held in the Linux kernel keyring
+//!
+//! _Note: The kernel keyring only exists on Linux; on other platforms this library is empty._
+#![cfg(target_os = "linux")]
+
+/// Thin wrappers around the kernel's key management syscalls
+mod keyctl;
+
+use chacha20poly1305::{
+	Key, XChaCha20Poly1305, XNonce,
+	aead::{ Aead, KeyInit, Payload }
+};
+use kync_plugin::{ Error, Plugin, Sink, error, export_plugin };
+use std::ffi::CString;
+use zeroize::Zeroizing;
+
+
+const UID: &[u8] = b"KeyringCapsuleFormat.6D5C8187-3066-4DDB-8D33-F1567F24F9FC";
+/// The supported keyrings in the order they are searched during recovery
+const KEYRINGS: &[&[u8]] = &[b"session", b"user", b"persistent"];
+
+/// The capsule format version
+const VERSION: u8 = 1;
+/// The description of the key that is used if the config only names a keyring
+const DEFAULT_DESCRIPTION: &[u8] = b"kync:default";
+
+const KEY_LEN: usize = 32;
+const NONCE_LEN: usize = 24;
+
+
+/// A plugin that protects secrets with keys held in the kernel keyring
+///
+/// The config is either `<keyring>` or `<keyring>:<description>` and selects an existing `user`
+/// key with `KEY_LEN` bytes that has been provisioned by the operator (`kync:default` if no
+/// description is given). The capsule only contains the key description, the nonce and the
+/// ciphertext; during recovery, the key is searched in all keyrings.
+pub struct KeyringPlugin;
+impl KeyringPlugin {
+	/// Parses `config` into the keyring name and the key description
+	fn parse_config(config: &[u8]) -> Result<(&[u8], CString), Error> {
+		let mut parts = config.splitn(2, |b| *b == b':');
+		let keyring = parts.next().unwrap_or_default();
+		let description = parts.next().unwrap_or(DEFAULT_DESCRIPTION);
+		if !KEYRINGS.contains(&keyring) {
+			return Err(error!(InvalidConfig, "Invalid keyring"))
+		}
+		
+		// The description must fit into the capsule header
+		match description.len() {
+			1..=255 => CString::new(description).map(|description| (keyring, description))
+				.map_err(|_| error!(InvalidConfig, "Invalid key description")),
+			_ => Err(error!(InvalidConfig, "Invalid key description"))
+		}
+	}
+	/// Creates the cipher for `key` if `key` has a valid length
+	fn cipher(key: &[u8]) -> Option<XChaCha20Poly1305> {
+		match key.len() {
+			KEY_LEN => Some(XChaCha20Poly1305::new(Key::from_slice(key))),
+			_ => None
+		}
+	}
+}
+impl Plugin for KeyringPlugin {
+	fn init(_log_level: u8) -> Result<Self, Error> {
+		Ok(Self)
+	}
+	
+	fn id(&self) -> &[u8] {
+		UID
+	}
+	
+	fn configs(&self) -> Result<Vec<Vec<u8>>, Error> {
+		let configs: Vec<Vec<u8>> = KEYRINGS.iter()
+			.filter(|name| keyctl::keyring(name).is_ok())
+			.map(|name| name.to_vec())
+			.collect();
+		match configs.is_empty() {
+			true => Err(error!(DeviceUnavailable, "No keyring is available")),
+			false => Ok(configs)
+		}
+	}
+	
+	fn auth_info_protect(&self, config: &[u8]) -> Result<(bool, u64), Error> {
+		Self::parse_config(config)?;
+		Ok((false, u64::MAX))
+	}
+	
+	fn auth_info_recover(&self, config: &[u8]) -> Result<(bool, u64), Error> {
+		Self::parse_config(config)?;
+		Ok((false, u64::MAX))
+	}
+	
+	fn protect(&self, sink: &mut Sink, data: &[u8], config: &[u8], _auth: Option<&[u8]>)
+		-> Result<(), Error>
+	{
+		// Look up the provisioned key
+		let (keyring, description) = Self::parse_config(config)?;
+		let key = keyctl::search(keyctl::keyring(keyring)?, &description)?
+			.ok_or(error!(DeviceUnavailable, "The configured key is not in the keyring"))?;
+		let cipher = Self::cipher(&keyctl::read(key)?)
+			.ok_or(error!(InvalidConfig, "The configured key has an invalid length"))?;
+		
+		// Create the header
+		let mut nonce = [0; NONCE_LEN];
+		getrandom::getrandom(&mut nonce)
+			.map_err(|_| error!(Internal, "Failed to gather randomness"))?;
+		let mut capsule = vec![VERSION, description.as_bytes().len() as u8];
+		capsule.extend_from_slice(description.as_bytes());
+		capsule.extend_from_slice(&nonce);
+		
+		// Seal the data
+		let payload = Payload { msg: data, aad: &capsule };
+		let ciphertext = cipher.encrypt(XNonce::from_slice(&nonce), payload)
+			.map_err(|_| error!(Internal, "Failed to seal the secret"))?;
+		capsule.extend_from_slice(&ciphertext);
+		sink.write(capsule)
+	}
+	
+	fn recover(&self, sink: &mut Sink, data: &[u8], _auth: Option<&[u8]>) -> Result<(), Error> {
+		// Parse the header
+		const INVALID: Error = error!(InvalidCapsule, "Invalid or truncated capsule");
+		if data.len() < 2 || data[0] != VERSION {
+			return Err(INVALID)
+		}
+		let header_len = 2 + data[1] as usize + NONCE_LEN;
+		if data.len() < header_len {
+			return Err(INVALID)
+		}
+		let (header, ciphertext) = data.split_at(header_len);
+		let description = CString::new(&header[2..header_len - NONCE_LEN]).map_err(|_| INVALID)?;
+		
+		// Find the key in the available keyrings
+		let mut keyrings = KEYRINGS.iter().filter_map(|name| keyctl::keyring(name).ok());
+		let key = keyrings.find_map(|keyring| keyctl::search(keyring, &description).transpose())
+			.unwrap_or(Err(error!(DeviceUnavailable, "The key is not available in any keyring")))?;
+		let cipher = Self::cipher(&keyctl::read(key)?)
+			.ok_or(error!(InvalidCapsule, "The key in the keyring has an invalid length"))?;
+		
+		// Open the data
+		let payload = Payload { msg: ciphertext, aad: header };
+		let nonce = XNonce::from_slice(&header[header_len - NONCE_LEN..]);
+		let plaintext = cipher.decrypt(nonce, payload)
+			.map_err(|_| error!(InvalidCapsule, "The capsule is corrupt"))?;
+		sink.write(Zeroizing::new(plaintext).as_slice())
+	}
+}
+export_plugin!(KeyringPlugin);
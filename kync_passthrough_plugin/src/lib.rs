@@ -0,0 +1,197 @@
+use blake2::{ Blake2b, Digest, digest::consts::U32 };
+use kync_plugin::{ Error, Plugin, Sink, error, export_plugin };
+use std::{
+	collections::HashMap, env, ffi::OsString, sync::Mutex,
+	convert::TryFrom
+};
+use zeroize::Zeroizing;
+
+
+const UID: &[u8] = b"PassthroughCapsuleFormat.4CF7CA6E-B833-40D2-A64F-462FAD47FF62";
+
+/// The capsule format version
+const VERSION: u8 = 1;
+/// The prefix of the environment variables that are offered as configs
+const ENV_CONFIG_PREFIX: &str = "KYNC_SECRET";
+/// The domain separator for the fingerprint
+const FINGERPRINT_DOMAIN: &[u8] = b"KyNc passthrough fingerprint\0";
+
+const SALT_LEN: usize = 16;
+const FINGERPRINT_LEN: usize = 32;
+
+
+/// A file identified by its device and inode number
+type FileId = (u64, u64);
+
+
+/// Computes the salted fingerprint of `secret`
+fn fingerprint(salt: &[u8], secret: &[u8]) -> Vec<u8> {
+	let fingerprint = Blake2b::<U32>::new_with_prefix(FINGERPRINT_DOMAIN)
+		.chain_update(salt).chain_update(secret).finalize();
+	fingerprint.to_vec()
+}
+/// Compares `a` and `b` in constant time
+fn ct_eq(a: &[u8], b: &[u8]) -> bool {
+	a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (a, b)| acc | (a ^ b)) == 0
+}
+
+
+/// A source of an injected secret
+enum Source {
+	/// An environment variable (`env:NAME`)
+	Env(OsString),
+	/// A file descriptor that is read until EOF (`fd:N`)
+	Fd(i32)
+}
+impl Source {
+	/// Parses a source config
+	fn parse(config: &[u8]) -> Result<Self, Error> {
+		const INVALID: Error =
+			error!(InvalidConfig, "Invalid source (expected `env:NAME` or `fd:N`)");
+		let config = std::str::from_utf8(config).map_err(|_| INVALID)?;
+		match config.find(':').map(|index| config.split_at(index + 1)) {
+			Some(("env:", name)) if !name.is_empty() && !name.contains('=') =>
+				Ok(Source::Env(name.into())),
+			Some(("fd:", fd)) if cfg!(unix) => fd.parse().map(Source::Fd).map_err(|_| INVALID),
+			_ => Err(INVALID)
+		}
+	}
+}
+
+
+/// A plugin that passes through secrets injected by the environment (e.g. by a container
+/// orchestrator)
+///
+/// The configs are sources: `env:NAME` reads the environment variable `NAME`, `fd:N` reads the
+/// file descriptor `N` until EOF. The secret is used as-is, so a trailing newline is part of it.
+/// `protect` checks that the source currently injects `data` and only stores the source and a
+/// salted fingerprint of the secret; `recover` reads the source and returns the injected secret if
+/// it matches the fingerprint. Environment variables that start with `KYNC_SECRET` are offered as
+/// configs.
+pub struct PassthroughPlugin {
+	/// The contents of the files behind the file descriptors that have already been read
+	files: Mutex<HashMap<FileId, Zeroizing<Vec<u8>>>>
+}
+impl PassthroughPlugin {
+	/// Reads the secret from `source`
+	fn read(&self, source: &Source) -> Result<Zeroizing<Vec<u8>>, Error> {
+		match source {
+			Source::Env(name) => {
+				let value = env::var_os(name)
+					.ok_or(error!(DeviceUnavailable, "The environment variable is not set"))?;
+				#[cfg(unix)]
+				return Ok(Zeroizing::new(std::os::unix::ffi::OsStringExt::into_vec(value)));
+				#[cfg(not(unix))]
+				return value.into_string().map(|value| Zeroizing::new(value.into_bytes()))
+					.map_err(|_| error!(InvalidConfig, "The environment variable is not UTF-8"));
+			},
+			#[cfg(unix)]
+			Source::Fd(fd) => self.read_fd(*fd),
+			#[cfg(not(unix))]
+			Source::Fd(_) => Err(error!(InvalidConfig, "File descriptors are not supported"))
+		}
+	}
+	/// Reads the file descriptor `fd` until EOF without closing it
+	#[cfg(unix)]
+	fn read_fd(&self, fd: i32) -> Result<Zeroizing<Vec<u8>>, Error> {
+		use std::{
+			fs::File, io::Read, mem::ManuallyDrop,
+			os::unix::{ fs::MetadataExt, io::FromRawFd }
+		};
+		const UNREADABLE: Error = error!(DeviceUnavailable, "Failed to read the file descriptor");
+		if fd < 0 {
+			return Err(error!(InvalidConfig, "Invalid file descriptor"))
+		}
+		
+		// The file descriptor is owned by the application, so it must not be closed
+		let mut file = ManuallyDrop::new(unsafe { File::from_raw_fd(fd) });
+		let metadata = file.metadata().map_err(|_| UNREADABLE)?;
+		
+		// Pipes can only be read once, so the contents are kept for later recoveries; they are keyed
+		// by the file instead of the descriptor number since closed numbers are reused
+		let mut files = self.files.lock().unwrap_or_else(|e| e.into_inner());
+		let key = (metadata.dev(), metadata.ino());
+		if let Some(contents) = files.get(&key) {
+			return Ok(contents.clone())
+		}
+		let mut contents = Zeroizing::new(Vec::new());
+		file.read_to_end(&mut contents).map_err(|_| UNREADABLE)?;
+		files.insert(key, contents.clone());
+		Ok(contents)
+	}
+}
+impl Plugin for PassthroughPlugin {
+	fn init(_log_level: u8) -> Result<Self, Error> {
+		Ok(Self { files: Mutex::new(HashMap::new()) })
+	}
+	
+	fn id(&self) -> &[u8] {
+		UID
+	}
+	
+	fn configs(&self) -> Result<Vec<Vec<u8>>, Error> {
+		let mut configs: Vec<Vec<u8>> = env::vars_os()
+			.filter_map(|(name, _)| name.into_string().ok())
+			.filter(|name| name.starts_with(ENV_CONFIG_PREFIX))
+			.map(|name| format!("env:{}", name).into_bytes())
+			.collect();
+		configs.sort();
+		Ok(configs)
+	}
+	
+	fn auth_info_protect(&self, config: &[u8]) -> Result<(bool, u64), Error> {
+		Source::parse(config)?;
+		Ok((false, u64::MAX))
+	}
+	
+	fn auth_info_recover(&self, config: &[u8]) -> Result<(bool, u64), Error> {
+		Source::parse(config)?;
+		Ok((false, u64::MAX))
+	}
+	
+	fn protect(&self, sink: &mut Sink, data: &[u8], config: &[u8], _auth: Option<&[u8]>)
+		-> Result<(), Error>
+	{
+		let source = Source::parse(config)?;
+		let config_len = u8::try_from(config.len())
+			.map_err(|_| error!(InvalidConfig, "The source is too long"))?;
+		
+		// Detect a misconfigured source now instead of at the first recovery
+		if !ct_eq(&self.read(&source)?, data) {
+			return Err(error!(InvalidConfig, "The injected secret does not match the data"))
+		}
+		let mut salt = [0; SALT_LEN];
+		getrandom::getrandom(&mut salt)
+			.map_err(|_| error!(Internal, "Failed to gather randomness"))?;
+		
+		// Store the source and the fingerprint
+		let mut capsule = vec![VERSION, config_len];
+		capsule.extend_from_slice(config);
+		capsule.extend_from_slice(&salt);
+		capsule.extend_from_slice(&fingerprint(&salt, data));
+		sink.write(capsule)
+	}
+	
+	fn recover(&self, sink: &mut Sink, data: &[u8], _auth: Option<&[u8]>) -> Result<(), Error> {
+		// Parse the capsule
+		const INVALID: Error = error!(InvalidCapsule, "Invalid or truncated capsule");
+		if data.len() < 2 || data[0] != VERSION {
+			return Err(INVALID)
+		}
+		let config_len = data[1] as usize;
+		if data.len() != 2 + config_len + SALT_LEN + FINGERPRINT_LEN {
+			return Err(INVALID)
+		}
+		let (config, rest) = data[2..].split_at(config_len);
+		let (salt, expected) = rest.split_at(SALT_LEN);
+		let source = Source::parse(config).map_err(|_| INVALID)?;
+		
+		// Read and check the injected secret
+		let secret = self.read(&source)?;
+		if !ct_eq(&fingerprint(salt, &secret), expected) {
+			return Err(error!(AuthFailed, "The injected secret does not match the capsule"))
+		}
+		sink.write(secret.as_slice())
+	}
+}
+export_plugin!(PassthroughPlugin);
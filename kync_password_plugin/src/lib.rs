@@ -0,0 +1,169 @@
+use argon2::{ Algorithm, Argon2, Params, Version };
+use chacha20poly1305::{
+	Key, XChaCha20Poly1305, XNonce,
+	aead::{ Aead, KeyInit, Payload }
+};
+use kync_plugin::{ Error, Plugin, Sink, error, export_plugin };
+use std::convert::TryInto;
+use zeroize::Zeroizing;
+
+
+const UID: &[u8] = b"PasswordCapsuleFormat.983A67F8-5151-480E-B5A4-3D6D07C4B993";
+/// The configs and their Argon2id cost presets as `(config, memory in KiB, passes)`
+const PRESETS: &[(&[u8], u32, u32)] = &[
+	(b"interactive", 64 * 1024, 2),
+	(b"moderate", 256 * 1024, 3),
+	(b"sensitive", 1024 * 1024, 4)
+];
+
+/// The capsule format version
+const VERSION: u8 = 1;
+/// The degree of parallelism used for new capsules
+const LANES: u32 = 1;
+/// The upper bounds for the KDF parameters accepted during recovery as `(memory in KiB, passes,
+/// lanes)` to prevent a crafted capsule from exhausting the host
+///
+/// These are the parameters of the strongest preset (`sensitive`) since we never emit more.
+const MAX_PARAMS: (u32, u32, u32) = (1024 * 1024, 4, LANES);
+
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 24;
+/// The length of the header (version, memory, passes, lanes, salt and nonce) which is
+/// authenticated as associated data
+const HEADER_LEN: usize = 1 + 3 * 4 + SALT_LEN + NONCE_LEN;
+
+
+/// The Argon2id parameters and randomness of a capsule
+struct Header {
+	memory: u32,
+	passes: u32,
+	lanes: u32,
+	salt: [u8; SALT_LEN],
+	nonce: [u8; NONCE_LEN]
+}
+impl Header {
+	/// Creates a new header with fresh randomness for the preset `config`
+	fn new(config: &[u8]) -> Result<Self, Error> {
+		let (_, memory, passes) = PRESETS.iter().find(|(c, _, _)| *c == config)
+			.ok_or(error!(InvalidConfig, "Invalid configuration"))?;
+		let (mut salt, mut nonce) = ([0; SALT_LEN], [0; NONCE_LEN]);
+		getrandom::getrandom(&mut salt)
+			.and_then(|_| getrandom::getrandom(&mut nonce))
+			.map_err(|_| error!(Internal, "Failed to gather randomness"))?;
+		Ok(Self { memory: *memory, passes: *passes, lanes: LANES, salt, nonce })
+	}
+	/// Parses the header from `capsule`
+	fn parse(capsule: &[u8]) -> Result<Self, Error> {
+		const INVALID: Error = error!(InvalidCapsule, "Invalid or truncated capsule");
+		if capsule.len() < HEADER_LEN || capsule[0] != VERSION {
+			return Err(INVALID)
+		}
+		
+		let u32_at = |at: usize| u32::from_be_bytes(capsule[at..at + 4].try_into().unwrap());
+		let (memory, passes, lanes) = (u32_at(1), u32_at(5), u32_at(9));
+		if memory > MAX_PARAMS.0 || passes > MAX_PARAMS.1 || lanes > MAX_PARAMS.2 {
+			return Err(error!(InvalidCapsule, "The capsule's KDF parameters exceed the limits"))
+		}
+		let salt = capsule[13..13 + SALT_LEN].try_into().unwrap();
+		let nonce = capsule[13 + SALT_LEN..HEADER_LEN].try_into().unwrap();
+		Ok(Self { memory, passes, lanes, salt, nonce })
+	}
+	
+	/// Serializes the header
+	fn to_bytes(&self) -> Vec<u8> {
+		let mut bytes = Vec::with_capacity(HEADER_LEN);
+		bytes.push(VERSION);
+		bytes.extend_from_slice(&self.memory.to_be_bytes());
+		bytes.extend_from_slice(&self.passes.to_be_bytes());
+		bytes.extend_from_slice(&self.lanes.to_be_bytes());
+		bytes.extend_from_slice(&self.salt);
+		bytes.extend_from_slice(&self.nonce);
+		bytes
+	}
+	/// Derives the key from `passphrase` and creates the cipher
+	fn cipher(&self, passphrase: &[u8]) -> Result<XChaCha20Poly1305, Error> {
+		let params = Params::new(self.memory, self.passes, self.lanes, Some(32))
+			.map_err(|_| error!(InvalidCapsule, "Invalid KDF parameters"))?;
+		let mut key = Zeroizing::new([0; 32]);
+		Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+			.hash_password_into(passphrase, &self.salt, key.as_mut())
+			.map_err(|_| error!(Internal, "Failed to derive the key"))?;
+		Ok(XChaCha20Poly1305::new(Key::from_slice(key.as_ref())))
+	}
+}
+
+
+/// A plugin that protects secrets with a passphrase
+///
+/// The key is derived from the passphrase with Argon2id and the secret is sealed with
+/// XChaCha20-Poly1305; the configs select the Argon2id cost preset.
+pub struct PasswordPlugin;
+impl PasswordPlugin {
+	/// Gets the passphrase
+	fn passphrase(auth: Option<&[u8]>) -> Result<&[u8], Error> {
+		match auth {
+			Some(auth) if !auth.is_empty() => Ok(auth),
+			Some(_) => Err(error!(AuthFailed, "The passphrase must not be empty")),
+			None => Err(error!(AuthRequired, "Missing passphrase"))
+		}
+	}
+	/// Validates the config
+	fn check_config(config: &[u8]) -> Result<(), Error> {
+		match PRESETS.iter().any(|(c, _, _)| *c == config) {
+			true => Ok(()),
+			false => Err(error!(InvalidConfig, "Invalid configuration"))
+		}
+	}
+}
+impl Plugin for PasswordPlugin {
+	fn init(_log_level: u8) -> Result<Self, Error> {
+		Ok(Self)
+	}
+	
+	fn id(&self) -> &[u8] {
+		UID
+	}
+	
+	fn configs(&self) -> Result<Vec<Vec<u8>>, Error> {
+		Ok(PRESETS.iter().map(|(c, _, _)| c.to_vec()).collect())
+	}
+	
+	fn auth_info_protect(&self, config: &[u8]) -> Result<(bool, u64), Error> {
+		Self::check_config(config)?;
+		Ok((true, u64::MAX))
+	}
+	
+	fn auth_info_recover(&self, config: &[u8]) -> Result<(bool, u64), Error> {
+		Self::check_config(config)?;
+		Ok((true, u64::MAX))
+	}
+	
+	fn protect(&self, sink: &mut Sink, data: &[u8], config: &[u8], auth: Option<&[u8]>)
+		-> Result<(), Error>
+	{
+		let passphrase = Self::passphrase(auth)?;
+		let header = Header::new(config)?;
+		let mut capsule = header.to_bytes();
+		
+		// Seal the data and authenticate the header
+		let payload = Payload { msg: data, aad: &capsule };
+		let nonce = XNonce::from_slice(&header.nonce);
+		let ciphertext = header.cipher(passphrase)?.encrypt(nonce, payload)
+			.map_err(|_| error!(Internal, "Failed to seal the secret"))?;
+		capsule.extend_from_slice(&ciphertext);
+		sink.write(capsule)
+	}
+	
+	fn recover(&self, sink: &mut Sink, data: &[u8], auth: Option<&[u8]>) -> Result<(), Error> {
+		let passphrase = Self::passphrase(auth)?;
+		let header = Header::parse(data)?;
+		
+		// Open the data; a wrong passphrase cannot be distinguished from a modified capsule
+		let payload = Payload { msg: &data[HEADER_LEN..], aad: &data[..HEADER_LEN] };
+		let nonce = XNonce::from_slice(&header.nonce);
+		let plaintext = header.cipher(passphrase)?.decrypt(nonce, payload)
+			.map_err(|_| error!(AuthFailed, "Invalid passphrase or corrupt capsule"))?;
+		sink.write(Zeroizing::new(plaintext).as_slice())
+	}
+}
+export_plugin!(PasswordPlugin);
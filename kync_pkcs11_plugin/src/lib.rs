@@ -0,0 +1,204 @@
+/// Minimal PKCS#11 bindings and a safe wrapper around them
+mod pkcs11;
+
+use crate::pkcs11::{ CK_OBJECT_HANDLE, Module, Session, Token };
+use kync_plugin::{ Error, ErrorCode, Plugin, Sink, error, export_plugin };
+use std::{ env, convert::TryFrom, path::PathBuf, sync::Mutex };
+
+
+const UID: &[u8] = b"Pkcs11CapsuleFormat.0E1F0A4A-64E1-4288-B4BC-998092EA2512";
+/// The environment variable that contains the path of the PKCS#11 module to use by default
+const MODULE_ENV: &str = "KYNC_PKCS11_MODULE";
+
+/// The capsule format version
+const VERSION: u8 = 1;
+/// The length of the AES-GCM IV
+const IV_LEN: usize = 12;
+
+
+/// Converts the raw path `bytes` into a path
+fn to_path(bytes: &[u8]) -> Result<PathBuf, Error> {
+	#[cfg(unix)]
+	return Ok(<std::ffi::OsStr as std::os::unix::ffi::OsStrExt>::from_bytes(bytes).into());
+	#[cfg(not(unix))]
+	return std::str::from_utf8(bytes).map(PathBuf::from)
+		.map_err(|_| error!(InvalidConfig, "The module path is not UTF-8"));
+}
+/// Splits a `<token label>/<key label>` config
+fn split_config(config: &[u8]) -> Result<(&[u8], &[u8]), Error> {
+	let index = config.iter().position(|b| *b == b'/')
+		.ok_or(error!(InvalidConfig, "Invalid configuration (expected `<token>/<key>`)"))?;
+	Ok((&config[..index], &config[index + 1..]))
+}
+
+
+/// The parsed header of a capsule
+struct Header<'a> {
+	token: &'a [u8],
+	key: &'a [u8],
+	iv: &'a [u8],
+	/// The raw header which is authenticated as associated data
+	raw: &'a [u8]
+}
+impl<'a> Header<'a> {
+	/// Parses the header and returns it together with the ciphertext
+	fn parse(capsule: &'a [u8]) -> Result<(Self, &'a [u8]), Error> {
+		const INVALID: Error = error!(InvalidCapsule, "Invalid or truncated capsule");
+		let field = |offset: usize| -> Result<&'a [u8], Error> {
+			let len = *capsule.get(offset).ok_or(INVALID)? as usize;
+			capsule.get(offset + 1..offset + 1 + len).ok_or(INVALID)
+		};
+		if capsule.first() != Some(&VERSION) {
+			return Err(INVALID)
+		}
+		
+		let token = field(1)?;
+		let key = field(2 + token.len())?;
+		let iv_offset = 3 + token.len() + key.len();
+		let iv = capsule.get(iv_offset..iv_offset + IV_LEN).ok_or(INVALID)?;
+		let (raw, ciphertext) = capsule.split_at(iv_offset + IV_LEN);
+		Ok((Self { token, key, iv, raw }, ciphertext))
+	}
+	/// Serializes a header for `token`, `key` and `iv`
+	fn serialize(token: &[u8], key: &[u8], iv: &[u8]) -> Result<Vec<u8>, Error> {
+		const TOO_LONG: Error = error!(InvalidConfig, "The token or key label is too long");
+		let mut bytes = vec![VERSION];
+		for field in [token, key].iter() {
+			bytes.push(u8::try_from(field.len()).map_err(|_| TOO_LONG)?);
+			bytes.extend_from_slice(field);
+		}
+		bytes.extend_from_slice(iv);
+		Ok(bytes)
+	}
+}
+
+
+/// A plugin that protects secrets with AES keys stored on PKCS#11 tokens
+///
+/// The PKCS#11 module is loaded from the path set with `set_context` or from `$KYNC_PKCS11_MODULE`.
+/// The configs are `<token label>/<key label>` pairs; tokens that hide their keys until the user
+/// logs in are offered as `<token label>/`, which selects the first AES key found after the login.
+/// The authentication is the user PIN. Since PKCS#11 does not report the exact number of PIN
+/// retries, `auth_info_*` reports `u64::MAX` (unknown) unless the PIN is locked; a low count is
+/// reported in the error message of a failed login.
+pub struct Pkcs11Plugin {
+	module: Mutex<Option<Module>>
+}
+impl Pkcs11Plugin {
+	/// Runs `f` with the loaded module
+	fn with_module<T>(&self, f: impl FnOnce(&Module) -> Result<T, Error>) -> Result<T, Error> {
+		let module = self.module.lock().unwrap_or_else(|e| e.into_inner());
+		match module.as_ref() {
+			Some(module) => f(module),
+			None => Err(error!(DeviceUnavailable, "No PKCS#11 module has been set"))
+		}
+	}
+	/// Opens a session with `token` and logs in with `pin` if necessary
+	fn login<'a>(module: &'a Module, token: &Token, pin: Option<&[u8]>)
+		-> Result<Session<'a>, Error>
+	{
+		let mut session = module.open(token.slot)?;
+		match (token.login_required(), pin) {
+			(false, None) => (),
+			(true, None) if token.pin_required() =>
+				return Err(error!(AuthRequired, "Missing PIN")),
+			(_, pin) => match session.login(pin) {
+				// Query the token again since a failed login may have lowered the retry count
+				Err(e) if e.code() == ErrorCode::AuthFailed => return Err(module.token(&token.label)
+					.map(|token| token.pin_incorrect()).unwrap_or(e)),
+				result => result?
+			}
+		}
+		Ok(session)
+	}
+	/// Finds the key with `label` or the first key if `label` is empty
+	fn find_key(session: &Session<'_>, label: &[u8])
+		-> Result<Option<(CK_OBJECT_HANDLE, Vec<u8>)>, Error>
+	{
+		let mut keys = session.aes_keys()?.into_iter();
+		Ok(keys.find(|(_, l)| label.is_empty() || l.as_slice() == label))
+	}
+}
+impl Plugin for Pkcs11Plugin {
+	fn init(_log_level: u8) -> Result<Self, Error> {
+		// A broken default module is reported once the plugin is used
+		let module = env::var_os(MODULE_ENV).and_then(|path| Module::load(path.as_ref()).ok());
+		Ok(Self { module: Mutex::new(module) })
+	}
+	
+	fn id(&self) -> &[u8] {
+		UID
+	}
+	
+	fn configs(&self) -> Result<Vec<Vec<u8>>, Error> {
+		self.with_module(|module| {
+			let mut configs = Vec::new();
+			for token in module.tokens()? {
+				let session = module.open(token.slot)?;
+				let keys = session.aes_keys()?;
+				if keys.is_empty() && token.login_required() {
+					configs.push([token.label.as_slice(), b"/"].concat());
+				}
+				for (_, key) in keys.into_iter().filter(|(_, key)| !key.is_empty()) {
+					configs.push([token.label.as_slice(), b"/", &key].concat());
+				}
+			}
+			Ok(configs)
+		})
+	}
+	
+	fn set_context(&self, context: &[u8]) -> Result<(), Error> {
+		let mut module = Module::load(&to_path(context)?)?;
+		let mut current = self.module.lock().unwrap_or_else(|e| e.into_inner());
+		if let Some(previous) = current.as_mut() {
+			module.replace(previous);
+		}
+		*current = Some(module);
+		Ok(())
+	}
+	
+	fn auth_info_protect(&self, config: &[u8]) -> Result<(bool, u64), Error> {
+		let (token, _) = split_config(config)?;
+		self.with_module(|module| {
+			let token = module.token(token)?;
+			Ok((token.pin_required(), token.retries()))
+		})
+	}
+	
+	fn auth_info_recover(&self, config: &[u8]) -> Result<(bool, u64), Error> {
+		self.auth_info_protect(config)
+	}
+	
+	fn protect(&self, sink: &mut Sink, data: &[u8], config: &[u8], auth: Option<&[u8]>)
+		-> Result<(), Error>
+	{
+		let (token, key) = split_config(config)?;
+		let mut iv = [0; IV_LEN];
+		getrandom::getrandom(&mut iv).map_err(|_| error!(Internal, "Failed to gather randomness"))?;
+		
+		let capsule = self.with_module(|module| {
+			let session = Self::login(module, &module.token(token)?, auth)?;
+			let (handle, key) = Self::find_key(&session, key)?
+				.ok_or(error!(InvalidConfig, "The key does not exist on the token"))?;
+			
+			// Record the actual key label and seal the data
+			let mut capsule = Header::serialize(token, &key, &iv)?;
+			let ciphertext = session.encrypt(handle, &iv, &capsule, data)?;
+			capsule.extend_from_slice(&ciphertext);
+			Ok(capsule)
+		})?;
+		sink.write(capsule)
+	}
+	
+	fn recover(&self, sink: &mut Sink, data: &[u8], auth: Option<&[u8]>) -> Result<(), Error> {
+		let (header, ciphertext) = Header::parse(data)?;
+		let plaintext = self.with_module(|module| {
+			let session = Self::login(module, &module.token(header.token)?, auth)?;
+			let (handle, _) = Self::find_key(&session, header.key)?
+				.ok_or(error!(DeviceUnavailable, "The key does not exist on the token"))?;
+			session.decrypt(handle, header.iv, header.raw, ciphertext)
+		})?;
+		sink.write(plaintext.as_slice())
+	}
+}
+export_plugin!(Pkcs11Plugin);
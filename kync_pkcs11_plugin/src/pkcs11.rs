@@ -0,0 +1,465 @@
+#![allow(non_camel_case_types, non_snake_case)]
+use kync_plugin::{ Error, error };
+use libloading::Library;
+use std::{
+	mem, ptr, path::Path,
+	os::raw::{ c_uchar, c_ulong, c_void }
+};
+use zeroize::Zeroizing;
+
+
+pub type CK_ULONG = c_ulong;
+pub type CK_RV = CK_ULONG;
+pub type CK_SLOT_ID = CK_ULONG;
+pub type CK_SESSION_HANDLE = CK_ULONG;
+pub type CK_OBJECT_HANDLE = CK_ULONG;
+
+const CKR_OK: CK_RV = 0x000;
+const CKR_SLOT_ID_INVALID: CK_RV = 0x003;
+const CKR_DEVICE_REMOVED: CK_RV = 0x032;
+const CKR_ENCRYPTED_DATA_INVALID: CK_RV = 0x040;
+const CKR_ENCRYPTED_DATA_LEN_RANGE: CK_RV = 0x041;
+const CKR_FUNCTION_CANCELED: CK_RV = 0x050;
+const CKR_KEY_HANDLE_INVALID: CK_RV = 0x060;
+const CKR_KEY_TYPE_INCONSISTENT: CK_RV = 0x063;
+const CKR_KEY_FUNCTION_NOT_PERMITTED: CK_RV = 0x068;
+const CKR_MECHANISM_INVALID: CK_RV = 0x070;
+const CKR_MECHANISM_PARAM_INVALID: CK_RV = 0x071;
+const CKR_PIN_INCORRECT: CK_RV = 0x0A0;
+const CKR_PIN_INVALID: CK_RV = 0x0A1;
+const CKR_PIN_LEN_RANGE: CK_RV = 0x0A2;
+const CKR_PIN_LOCKED: CK_RV = 0x0A4;
+const CKR_TOKEN_NOT_PRESENT: CK_RV = 0x0E0;
+const CKR_USER_ALREADY_LOGGED_IN: CK_RV = 0x100;
+const CKR_USER_NOT_LOGGED_IN: CK_RV = 0x101;
+const CKR_CRYPTOKI_ALREADY_INITIALIZED: CK_RV = 0x191;
+
+const CKF_RW_SESSION: CK_ULONG = 0x0002;
+const CKF_SERIAL_SESSION: CK_ULONG = 0x0004;
+const CKF_OS_LOCKING_OK: CK_ULONG = 0x0002;
+const CKF_LOGIN_REQUIRED: CK_ULONG = 0x0004;
+const CKF_PROTECTED_AUTHENTICATION_PATH: CK_ULONG = 0x0100;
+const CKF_USER_PIN_COUNT_LOW: CK_ULONG = 0x0001_0000;
+const CKF_USER_PIN_FINAL_TRY: CK_ULONG = 0x0002_0000;
+const CKF_USER_PIN_LOCKED: CK_ULONG = 0x0004_0000;
+
+const CK_UNAVAILABLE_INFORMATION: CK_ULONG = !0;
+const CKU_USER: CK_ULONG = 1;
+const CKA_CLASS: CK_ULONG = 0x000;
+const CKA_LABEL: CK_ULONG = 0x003;
+const CKA_KEY_TYPE: CK_ULONG = 0x100;
+const CKO_SECRET_KEY: CK_ULONG = 4;
+const CKK_AES: CK_ULONG = 0x1F;
+const CKM_AES_GCM: CK_ULONG = 0x1087;
+
+/// The length of the AES-GCM tag
+pub const TAG_LEN: usize = 16;
+
+
+#[repr(C)]
+#[derive(Default)]
+struct CK_VERSION {
+	major: c_uchar,
+	minor: c_uchar
+}
+
+#[repr(C)]
+#[derive(Default)]
+struct CK_TOKEN_INFO {
+	label: [c_uchar; 32],
+	manufacturerID: [c_uchar; 32],
+	model: [c_uchar; 16],
+	serialNumber: [c_uchar; 16],
+	flags: CK_ULONG,
+	ulMaxSessionCount: CK_ULONG,
+	ulSessionCount: CK_ULONG,
+	ulMaxRwSessionCount: CK_ULONG,
+	ulRwSessionCount: CK_ULONG,
+	ulMaxPinLen: CK_ULONG,
+	ulMinPinLen: CK_ULONG,
+	ulTotalPublicMemory: CK_ULONG,
+	ulFreePublicMemory: CK_ULONG,
+	ulTotalPrivateMemory: CK_ULONG,
+	ulFreePrivateMemory: CK_ULONG,
+	hardwareVersion: CK_VERSION,
+	firmwareVersion: CK_VERSION,
+	utcTime: [c_uchar; 16]
+}
+
+#[repr(C)]
+struct CK_ATTRIBUTE {
+	type_: CK_ULONG,
+	pValue: *mut c_void,
+	ulValueLen: CK_ULONG
+}
+
+#[repr(C)]
+struct CK_MECHANISM {
+	mechanism: CK_ULONG,
+	pParameter: *mut c_void,
+	ulParameterLen: CK_ULONG
+}
+
+#[repr(C)]
+struct CK_GCM_PARAMS {
+	pIv: *mut c_uchar,
+	ulIvLen: CK_ULONG,
+	ulIvBits: CK_ULONG,
+	pAAD: *mut c_uchar,
+	ulAADLen: CK_ULONG,
+	ulTagBits: CK_ULONG
+}
+
+#[repr(C)]
+struct CK_C_INITIALIZE_ARGS {
+	CreateMutex: Unused,
+	DestroyMutex: Unused,
+	LockMutex: Unused,
+	UnlockMutex: Unused,
+	flags: CK_ULONG,
+	pReserved: *mut c_void
+}
+
+
+/// A function pointer that is not used by this plugin
+type Unused = Option<unsafe extern "C" fn()>;
+
+/// The function list of a PKCS#11 v2 module (up to `C_Decrypt`)
+#[repr(C)]
+struct CK_FUNCTION_LIST {
+	version: CK_VERSION,
+	C_Initialize: unsafe extern "C" fn(*mut c_void) -> CK_RV,
+	C_Finalize: unsafe extern "C" fn(*mut c_void) -> CK_RV,
+	C_GetInfo: Unused,
+	C_GetFunctionList: Unused,
+	C_GetSlotList: unsafe extern "C" fn(c_uchar, *mut CK_SLOT_ID, *mut CK_ULONG) -> CK_RV,
+	C_GetSlotInfo: Unused,
+	C_GetTokenInfo: unsafe extern "C" fn(CK_SLOT_ID, *mut CK_TOKEN_INFO) -> CK_RV,
+	C_GetMechanismList: Unused,
+	C_GetMechanismInfo: Unused,
+	C_InitToken: Unused,
+	C_InitPIN: Unused,
+	C_SetPIN: Unused,
+	C_OpenSession: unsafe extern "C" fn(CK_SLOT_ID, CK_ULONG, *mut c_void, Unused,
+		*mut CK_SESSION_HANDLE) -> CK_RV,
+	C_CloseSession: unsafe extern "C" fn(CK_SESSION_HANDLE) -> CK_RV,
+	C_CloseAllSessions: Unused,
+	C_GetSessionInfo: Unused,
+	C_GetOperationState: Unused,
+	C_SetOperationState: Unused,
+	C_Login: unsafe extern "C" fn(CK_SESSION_HANDLE, CK_ULONG, *const c_uchar, CK_ULONG) -> CK_RV,
+	C_Logout: unsafe extern "C" fn(CK_SESSION_HANDLE) -> CK_RV,
+	C_CreateObject: Unused,
+	C_CopyObject: Unused,
+	C_DestroyObject: Unused,
+	C_GetObjectSize: Unused,
+	C_GetAttributeValue: unsafe extern "C" fn(CK_SESSION_HANDLE, CK_OBJECT_HANDLE,
+		*mut CK_ATTRIBUTE, CK_ULONG) -> CK_RV,
+	C_SetAttributeValue: Unused,
+	C_FindObjectsInit: unsafe extern "C" fn(CK_SESSION_HANDLE, *mut CK_ATTRIBUTE, CK_ULONG)
+		-> CK_RV,
+	C_FindObjects: unsafe extern "C" fn(CK_SESSION_HANDLE, *mut CK_OBJECT_HANDLE, CK_ULONG,
+		*mut CK_ULONG) -> CK_RV,
+	C_FindObjectsFinal: unsafe extern "C" fn(CK_SESSION_HANDLE) -> CK_RV,
+	C_EncryptInit: unsafe extern "C" fn(CK_SESSION_HANDLE, *mut CK_MECHANISM, CK_OBJECT_HANDLE)
+		-> CK_RV,
+	C_Encrypt: unsafe extern "C" fn(CK_SESSION_HANDLE, *const c_uchar, CK_ULONG, *mut c_uchar,
+		*mut CK_ULONG) -> CK_RV,
+	C_EncryptUpdate: Unused,
+	C_EncryptFinal: Unused,
+	C_DecryptInit: unsafe extern "C" fn(CK_SESSION_HANDLE, *mut CK_MECHANISM, CK_OBJECT_HANDLE)
+		-> CK_RV,
+	C_Decrypt: unsafe extern "C" fn(CK_SESSION_HANDLE, *const c_uchar, CK_ULONG, *mut c_uchar,
+		*mut CK_ULONG) -> CK_RV
+}
+type C_GetFunctionList = unsafe extern "C" fn(*mut *const CK_FUNCTION_LIST) -> CK_RV;
+
+
+/// Maps a PKCS#11 return value to a plugin error
+fn check(rv: CK_RV) -> Result<(), Error> {
+	match rv {
+		CKR_OK => Ok(()),
+		CKR_PIN_INCORRECT | CKR_PIN_INVALID | CKR_PIN_LEN_RANGE =>
+			Err(error!(AuthFailed, "The PIN is incorrect")),
+		CKR_PIN_LOCKED => Err(error!(RetriesExhausted, "The PIN is locked")),
+		CKR_USER_NOT_LOGGED_IN => Err(error!(AuthRequired, "The token requires a login")),
+		CKR_TOKEN_NOT_PRESENT | CKR_DEVICE_REMOVED | CKR_SLOT_ID_INVALID =>
+			Err(error!(DeviceUnavailable, "The token is not present")),
+		CKR_ENCRYPTED_DATA_INVALID | CKR_ENCRYPTED_DATA_LEN_RANGE =>
+			Err(error!(InvalidCapsule, "The capsule is corrupt")),
+		CKR_KEY_HANDLE_INVALID | CKR_KEY_TYPE_INCONSISTENT | CKR_KEY_FUNCTION_NOT_PERMITTED
+			| CKR_MECHANISM_INVALID | CKR_MECHANISM_PARAM_INVALID =>
+			Err(error!(InvalidConfig, "The key cannot be used with AES-GCM")),
+		CKR_FUNCTION_CANCELED => Err(error!(Cancelled, "The operation has been cancelled")),
+		_ => Err(error!(Internal, "A PKCS#11 operation failed"))
+	}
+}
+/// Removes the blank padding from a fixed-size PKCS#11 string
+fn unpad(s: &[u8]) -> Vec<u8> {
+	let len = s.iter().rposition(|b| *b != b' ' && *b != 0).map(|i| i + 1).unwrap_or(0);
+	s[..len].to_vec()
+}
+
+
+/// A present token
+pub struct Token {
+	/// The slot that contains the token
+	pub slot: CK_SLOT_ID,
+	/// The token label
+	pub label: Vec<u8>,
+	flags: CK_ULONG
+}
+impl Token {
+	/// Whether a login is required (and cannot be done through a protected authentication path)
+	pub fn pin_required(&self) -> bool {
+		self.flags & CKF_LOGIN_REQUIRED != 0 && self.flags & CKF_PROTECTED_AUTHENTICATION_PATH == 0
+	}
+	/// Whether a login is required
+	pub fn login_required(&self) -> bool {
+		self.flags & CKF_LOGIN_REQUIRED != 0
+	}
+	/// The PIN retries left
+	///
+	/// PKCS#11 does not expose the actual count, so this is `0` for a locked PIN and `u64::MAX`
+	/// (unknown) otherwise; a low count is reported by `pin_incorrect` instead.
+	pub fn retries(&self) -> u64 {
+		match self.flags & CKF_USER_PIN_LOCKED {
+			0 => u64::MAX,
+			_ => 0
+		}
+	}
+	/// The error for an incorrect PIN which warns if the token reports a low retry count
+	pub fn pin_incorrect(&self) -> Error {
+		match self.flags {
+			flags if flags & CKF_USER_PIN_FINAL_TRY != 0 =>
+				error!(AuthFailed, "The PIN is incorrect (the next failed login locks the PIN)"),
+			flags if flags & CKF_USER_PIN_COUNT_LOW != 0 =>
+				error!(AuthFailed, "The PIN is incorrect (only a few retries are left)"),
+			_ => error!(AuthFailed, "The PIN is incorrect")
+		}
+	}
+}
+
+
+/// A loaded and initialized PKCS#11 module
+///
+/// The module is only finalized on drop if this instance initialized it; if the library has already
+/// been initialized (e.g. by a previous instance for the same library), it is left alone.
+pub struct Module {
+	functions: *const CK_FUNCTION_LIST,
+	initialized: bool,
+	_library: Library
+}
+impl Module {
+	/// Loads and initializes the module at `path`
+	pub fn load(path: &Path) -> Result<Self, Error> {
+		const LOAD_ERROR: Error = error!(DeviceUnavailable, "Failed to load the PKCS#11 module");
+		let library = Library::new(path).map_err(|_| LOAD_ERROR)?;
+		let get_function_list: C_GetFunctionList = unsafe {
+			*library.get::<C_GetFunctionList>(b"C_GetFunctionList\0").map_err(|_| LOAD_ERROR)?
+		};
+		let mut functions = ptr::null();
+		check(unsafe{ get_function_list(&mut functions) })?;
+		if functions.is_null() {
+			return Err(LOAD_ERROR)
+		}
+		
+		// Initialize the module for multi-threaded use
+		let mut args = CK_C_INITIALIZE_ARGS {
+			CreateMutex: None, DestroyMutex: None, LockMutex: None, UnlockMutex: None,
+			flags: CKF_OS_LOCKING_OK, pReserved: ptr::null_mut()
+		};
+		let rv = unsafe{ ((*functions).C_Initialize)((&mut args as *mut CK_C_INITIALIZE_ARGS).cast()) };
+		let initialized = match rv {
+			CKR_CRYPTOKI_ALREADY_INITIALIZED => false,
+			rv => check(rv).map(|_| true)?
+		};
+		Ok(Self { functions, initialized, _library: library })
+	}
+	/// Takes over the responsibility to finalize the library from `previous` if both instances use
+	/// the same library (so that `self` remains usable once `previous` is dropped)
+	pub fn replace(&mut self, previous: &mut Self) {
+		if ptr::eq(self.functions, previous.functions) {
+			self.initialized |= mem::replace(&mut previous.initialized, false);
+		}
+	}
+	
+	/// The function list
+	fn f(&self) -> &CK_FUNCTION_LIST {
+		unsafe{ &*self.functions }
+	}
+	
+	/// All present tokens
+	pub fn tokens(&self) -> Result<Vec<Token>, Error> {
+		let mut count = 0;
+		check(unsafe{ (self.f().C_GetSlotList)(1, ptr::null_mut(), &mut count) })?;
+		let mut slots = vec![0; count as usize];
+		check(unsafe{ (self.f().C_GetSlotList)(1, slots.as_mut_ptr(), &mut count) })?;
+		slots.truncate(count as usize);
+		
+		let mut tokens = Vec::new();
+		for slot in slots {
+			let mut info = CK_TOKEN_INFO::default();
+			check(unsafe{ (self.f().C_GetTokenInfo)(slot, &mut info) })?;
+			tokens.push(Token { slot, label: unpad(&info.label), flags: info.flags });
+		}
+		Ok(tokens)
+	}
+	/// The first present token with `label`
+	pub fn token(&self, label: &[u8]) -> Result<Token, Error> {
+		self.tokens()?.into_iter().find(|t| t.label == label)
+			.ok_or(error!(DeviceUnavailable, "The token is not present"))
+	}
+	
+	/// Opens a read-write session with the token in `slot`
+	pub fn open(&self, slot: CK_SLOT_ID) -> Result<Session<'_>, Error> {
+		let (flags, mut handle) = (CKF_SERIAL_SESSION | CKF_RW_SESSION, 0);
+		let rv = unsafe {
+			(self.f().C_OpenSession)(slot, flags, ptr::null_mut(), None, &mut handle)
+		};
+		check(rv)?;
+		Ok(Session { module: self, handle, logged_in: false })
+	}
+}
+impl Drop for Module {
+	fn drop(&mut self) {
+		if self.initialized {
+			unsafe{ (self.f().C_Finalize)(ptr::null_mut()) };
+		}
+	}
+}
+unsafe impl Send for Module {}
+
+
+/// A session with a token
+pub struct Session<'a> {
+	module: &'a Module,
+	handle: CK_SESSION_HANDLE,
+	logged_in: bool
+}
+impl<'a> Session<'a> {
+	/// Logs the user in (`None` uses the token's protected authentication path)
+	pub fn login(&mut self, pin: Option<&[u8]>) -> Result<(), Error> {
+		let (ptr, len) = pin.map(|p| (p.as_ptr(), p.len())).unwrap_or((ptr::null(), 0));
+		let rv = unsafe{ (self.module.f().C_Login)(self.handle, CKU_USER, ptr, len as CK_ULONG) };
+		match rv {
+			CKR_USER_ALREADY_LOGGED_IN => Ok(()),
+			rv => {
+				check(rv)?;
+				self.logged_in = true;
+				Ok(())
+			}
+		}
+	}
+	
+	/// Finds all AES keys and their labels
+	pub fn aes_keys(&self) -> Result<Vec<(CK_OBJECT_HANDLE, Vec<u8>)>, Error> {
+		let (mut class, mut key_type) = (CKO_SECRET_KEY, CKK_AES);
+		let mut template = [
+			CK_ATTRIBUTE {
+				type_: CKA_CLASS, pValue: (&mut class as *mut CK_ULONG).cast(),
+				ulValueLen: mem::size_of::<CK_ULONG>() as CK_ULONG
+			},
+			CK_ATTRIBUTE {
+				type_: CKA_KEY_TYPE, pValue: (&mut key_type as *mut CK_ULONG).cast(),
+				ulValueLen: mem::size_of::<CK_ULONG>() as CK_ULONG
+			}
+		];
+		let f = self.module.f();
+		check(unsafe{ (f.C_FindObjectsInit)(self.handle, template.as_mut_ptr(), 2) })?;
+		
+		// Collect the handles
+		let (mut handles, mut chunk, mut count) = (Vec::new(), [0; 32], 0);
+		let result = loop {
+			let rv = unsafe {
+				(f.C_FindObjects)(self.handle, chunk.as_mut_ptr(), chunk.len() as CK_ULONG, &mut count)
+			};
+			match check(rv) {
+				Ok(_) if count == 0 => break Ok(()),
+				Ok(_) => handles.extend_from_slice(&chunk[..count as usize]),
+				Err(e) => break Err(e)
+			}
+		};
+		unsafe{ (f.C_FindObjectsFinal)(self.handle) };
+		result?;
+		
+		// Get the labels
+		let mut keys = Vec::new();
+		for handle in handles {
+			let mut label = CK_ATTRIBUTE { type_: CKA_LABEL, pValue: ptr::null_mut(), ulValueLen: 0 };
+			let rv = unsafe{ (f.C_GetAttributeValue)(self.handle, handle, &mut label, 1) };
+			if label.ulValueLen == CK_UNAVAILABLE_INFORMATION {
+				// Skip keys whose label is sensitive or missing
+				continue
+			}
+			check(rv)?;
+			let mut value = vec![0u8; label.ulValueLen as usize];
+			label.pValue = value.as_mut_ptr().cast();
+			check(unsafe{ (f.C_GetAttributeValue)(self.handle, handle, &mut label, 1) })?;
+			keys.push((handle, value));
+		}
+		Ok(keys)
+	}
+	
+	/// Encrypts `data` with `key` using AES-GCM and returns the ciphertext with the tag appended
+	pub fn encrypt(&self, key: CK_OBJECT_HANDLE, iv: &[u8], aad: &[u8], data: &[u8])
+		-> Result<Vec<u8>, Error>
+	{
+		let (mut iv, mut aad) = (iv.to_vec(), aad.to_vec());
+		let mut params = gcm_params(&mut iv, &mut aad);
+		let mut mechanism = gcm_mechanism(&mut params);
+		check(unsafe{ (self.module.f().C_EncryptInit)(self.handle, &mut mechanism, key) })?;
+		
+		let mut ciphertext = vec![0; data.len() + TAG_LEN];
+		let mut len = ciphertext.len() as CK_ULONG;
+		check(unsafe {
+			(self.module.f().C_Encrypt)(self.handle, data.as_ptr(), data.len() as CK_ULONG,
+				ciphertext.as_mut_ptr(), &mut len)
+		})?;
+		ciphertext.truncate(len as usize);
+		Ok(ciphertext)
+	}
+	/// Decrypts and verifies `data` with `key` using AES-GCM
+	pub fn decrypt(&self, key: CK_OBJECT_HANDLE, iv: &[u8], aad: &[u8], data: &[u8])
+		-> Result<Zeroizing<Vec<u8>>, Error>
+	{
+		let (mut iv, mut aad) = (iv.to_vec(), aad.to_vec());
+		let mut params = gcm_params(&mut iv, &mut aad);
+		let mut mechanism = gcm_mechanism(&mut params);
+		check(unsafe{ (self.module.f().C_DecryptInit)(self.handle, &mut mechanism, key) })?;
+		
+		let mut plaintext = Zeroizing::new(vec![0; data.len()]);
+		let mut len = plaintext.len() as CK_ULONG;
+		check(unsafe {
+			(self.module.f().C_Decrypt)(self.handle, data.as_ptr(), data.len() as CK_ULONG,
+				plaintext.as_mut_ptr(), &mut len)
+		})?;
+		plaintext.truncate(len as usize);
+		Ok(plaintext)
+	}
+}
+impl<'a> Drop for Session<'a> {
+	fn drop(&mut self) {
+		if self.logged_in {
+			unsafe{ (self.module.f().C_Logout)(self.handle) };
+		}
+		unsafe{ (self.module.f().C_CloseSession)(self.handle) };
+	}
+}
+
+
+/// Creates the AES-GCM parameters with a 128 bit tag
+fn gcm_params(iv: &mut [u8], aad: &mut [u8]) -> CK_GCM_PARAMS {
+	CK_GCM_PARAMS {
+		pIv: iv.as_mut_ptr(), ulIvLen: iv.len() as CK_ULONG, ulIvBits: (iv.len() * 8) as CK_ULONG,
+		pAAD: aad.as_mut_ptr(), ulAADLen: aad.len() as CK_ULONG,
+		ulTagBits: (TAG_LEN * 8) as CK_ULONG
+	}
+}
+/// Creates the AES-GCM mechanism for `params`
+fn gcm_mechanism(params: &mut CK_GCM_PARAMS) -> CK_MECHANISM {
+	CK_MECHANISM {
+		mechanism: CKM_AES_GCM, pParameter: (params as *mut CK_GCM_PARAMS).cast(),
+		ulParameterLen: mem::size_of::<CK_GCM_PARAMS>() as CK_ULONG
+	}
+}
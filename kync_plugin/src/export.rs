@@ -0,0 +1,154 @@
+use crate::{
+	API_VERSION, API_VERSION_V2, Error, ErrorCode, LOG_LEVEL, Plugin, log,
+	ffi::{ MutPtrExt, SliceTExt, Sink, sys }
+};
+use std::{
+	ptr, collections::BTreeSet, os::raw::c_char,
+	sync::{ Mutex, OnceLock, atomic::Ordering },
+	panic::{ self, AssertUnwindSafe }
+};
+
+
+/// The global storage for a plugin instance
+pub struct Instance<P: Plugin>(OnceLock<P>);
+impl<P: Plugin> Instance<P> {
+	/// Creates a new uninitialized instance
+	#[allow(clippy::new_without_default)]
+	pub const fn new() -> Self {
+		Self(OnceLock::new())
+	}
+	
+	/// Gets the plugin instance or an error if the plugin has not been initialized
+	fn get(&self) -> Result<&P, Error> {
+		self.0.get().ok_or(Error::UNINITIALIZED)
+	}
+}
+
+
+/// The `ErrorDesc` pointers that have been returned so far
+///
+/// The codes are stored in front of the descriptions; this set only ensures that `error_code` never
+/// reads in front of foreign pointers (e.g. propagated callback errors).
+static KNOWN_DESCS: Mutex<BTreeSet<usize>> = Mutex::new(BTreeSet::new());
+
+
+/// Converts a `Result<(), Error>` to a nullable error pointer and catches panics
+///
+/// _Note: Panics can only be caught if the plugin is built with `panic = "unwind"`; otherwise the
+/// process aborts._
+fn try_catch(f: impl FnOnce() -> Result<(), Error>) -> *const c_char {
+	let result = panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or(Err(Error::PANIC));
+	match result {
+		Ok(_) => ptr::null(),
+		Err(e) => {
+			log(e.description().to_string_lossy());
+			if e.has_desc() {
+				let mut known = KNOWN_DESCS.lock().unwrap_or_else(|e| e.into_inner());
+				known.insert(e.as_ptr() as usize);
+			}
+			e.as_ptr()
+		}
+	}
+}
+
+
+/// Initializes the library with a specific API version and a logging level
+pub fn init<P: Plugin>(instance: &Instance<P>, api: u16, log_level: u8) -> *const c_char {
+	try_catch(|| {
+		if api != API_VERSION && api != API_VERSION_V2 {
+			Err(Error::UNSUPPORTED_API)?
+		}
+		LOG_LEVEL.store(log_level, Ordering::SeqCst);
+		
+		// Create the instance if necessary
+		if instance.0.get().is_none() {
+			let _ = instance.0.set(P::init(log_level)?);
+		}
+		Ok(())
+	})
+}
+
+
+/// Queries the plugin/format ID
+pub fn id<P: Plugin>(instance: &Instance<P>, sink: *mut sys::write_t) -> *const c_char {
+	try_catch(|| {
+		let (plugin, mut sink) = (instance.get()?, Sink::new(sink)?);
+		plugin.write_id(&mut sink)
+	})
+}
+
+
+/// Queries all possible configs and writes them as separate segments
+pub fn configs<P: Plugin>(instance: &Instance<P>, sink: *mut sys::write_t) -> *const c_char {
+	try_catch(|| {
+		let (plugin, mut sink) = (instance.get()?, Sink::new(sink)?);
+		plugin.configs()?.iter().try_for_each(|c| sink.write(c))
+	})
+}
+
+
+/// Sets an optional application specific context if supported
+pub fn set_context<P: Plugin>(instance: &Instance<P>, context: *const sys::slice_t)
+	-> *const c_char
+{
+	try_catch(|| instance.get()?.set_context(context.checked_slice()?))
+}
+
+
+/// Queries the authentication requirements to protect a secret for a specific config
+pub fn auth_info_protect<P: Plugin>(instance: &Instance<P>, is_required: *mut u8, retries: *mut u64,
+	config: *const sys::slice_t) -> *const c_char
+{
+	try_catch(|| {
+		let (required, left) = instance.get()?.auth_info_protect(config.checked_slice()?)?;
+		is_required.checked_set(required as u8)?;
+		retries.checked_set(left)
+	})
+}
+
+
+/// Queries the authentication requirements to recover a secret for a specific config
+pub fn auth_info_recover<P: Plugin>(instance: &Instance<P>, is_required: *mut u8, retries: *mut u64,
+	config: *const sys::slice_t) -> *const c_char
+{
+	try_catch(|| {
+		let (required, left) = instance.get()?.auth_info_recover(config.checked_slice()?)?;
+		is_required.checked_set(required as u8)?;
+		retries.checked_set(left)
+	})
+}
+
+
+/// Protects some data
+pub fn protect<P: Plugin>(instance: &Instance<P>, sink: *mut sys::write_t,
+	data: *const sys::slice_t, config: *const sys::slice_t, auth: *const sys::slice_t)
+	-> *const c_char
+{
+	try_catch(|| {
+		let (plugin, mut sink) = (instance.get()?, Sink::new(sink)?);
+		plugin.protect(&mut sink, data.checked_slice()?, config.checked_slice()?,
+			auth.checked_option()?)
+	})
+}
+
+
+/// Opens `data` to `sink` using `auth`
+pub fn recover<P: Plugin>(instance: &Instance<P>, sink: *mut sys::write_t,
+	data: *const sys::slice_t, auth: *const sys::slice_t) -> *const c_char
+{
+	try_catch(|| {
+		let (plugin, mut sink) = (instance.get()?, Sink::new(sink)?);
+		plugin.recover(&mut sink, data.checked_slice()?, auth.checked_option()?)
+	})
+}
+
+
+/// Maps an error pointer returned by this plugin to a machine-readable error code
+pub fn error_code(error: *const c_char) -> u8 {
+	let known = KNOWN_DESCS.lock().unwrap_or_else(|e| e.into_inner());
+	match known.contains(&(error as usize)) {
+		// The pointer points to the `desc` field of an `ErrorDesc`, so the code is the byte before
+		true => unsafe{ *error.cast::<u8>().sub(1) },
+		false => ErrorCode::Unknown as u8
+	}
+}
@@ -0,0 +1,74 @@
+#![allow(non_camel_case_types)]
+use crate::Error;
+use std::slice;
+
+
+/// The sys bindings
+pub mod sys {
+	#![allow(unused)]
+	include!("sys.rs");
+}
+
+
+/// An extension to check and assign to a mutable pointer
+pub trait MutPtrExt<T: Copy> {
+	/// Checks and assigns a value to a `*mut T`
+	fn checked_set(self, v: T) -> Result<(), Error>;
+}
+impl<T: Copy> MutPtrExt<T> for *mut T {
+	fn checked_set(self, v: T) -> Result<(), Error> {
+		let this = unsafe{ self.as_mut() }.ok_or(Error::NULLPTR)?;
+		*this = v;
+		Ok(())
+	}
+}
+
+
+/// An extension to check and deref the slice type
+pub trait SliceTExt {
+	/// Checks and wraps a `*const sys::slice_t`
+	fn checked_slice<'a>(self) -> Result<&'a[u8], Error>;
+	/// Checks and wraps a nullable `*const sys::slice_t`
+	fn checked_option<'a>(self) -> Result<Option<&'a[u8]>, Error>;
+}
+impl SliceTExt for *const sys::slice_t {
+	fn checked_slice<'a>(self) -> Result<&'a[u8], Error> {
+		let this = unsafe{ self.as_ref() }.ok_or(Error::NULLPTR)?;
+		match this.ptr.is_null() {
+			false => Ok(unsafe{ slice::from_raw_parts(this.ptr, this.len) }),
+			true => Err(Error::NULLPTR)
+		}
+	}
+	fn checked_option<'a>(self) -> Result<Option<&'a[u8]>, Error> {
+		match self.is_null() {
+			true => Ok(None),
+			false => self.checked_slice().map(Some)
+		}
+	}
+}
+
+
+/// A checked wrapper around a `sys::write_t` callback
+pub struct Sink<'a>(&'a mut sys::write_t);
+impl<'a> Sink<'a> {
+	/// Checks and wraps a `*mut sys::write_t`
+	pub(crate) fn new(sink: *mut sys::write_t) -> Result<Self, Error> {
+		let this = unsafe{ sink.as_mut() }.ok_or(Error::NULLPTR)?;
+		match (this.handle.is_null(), this.write.is_some()) {
+			(false, true) => Ok(Self(this)),
+			_ => Err(Error::NULLPTR)
+		}
+	}
+	
+	/// Writes a segment to the sink; a callback error is returned as-is so that it can be
+	/// propagated
+	pub fn write(&mut self, data: impl AsRef<[u8]>) -> Result<(), Error> {
+		let data = data.as_ref();
+		let slice = sys::slice_t{ ptr: data.as_ptr(), len: data.len() };
+		let error = unsafe{ self.0.write.unwrap()(self.0.handle, &slice) };
+		match error.is_null() {
+			true => Ok(()),
+			false => Err(Error::from_ptr(error))
+		}
+	}
+}
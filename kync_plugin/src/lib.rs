@@ -0,0 +1,289 @@
+//! This crate is an SDK to implement KyNc plugins (see
+//! [Kync.asciidoc](https://github.com/KizzyCode/kync/blob/master/Kync.asciidoc)) in safe Rust
+//!
+//! Implement `Plugin` for your type and export it with `export_plugin!(YourType)` in the root of
+//! your `cdylib`-crate.
+
+/// Some FFI helpers
+mod ffi;
+/// The generic implementations behind the functions generated by `export_plugin!`
+#[doc(hidden)]
+pub mod export;
+
+use std::{
+	fmt::{ self, Debug, Formatter }, ffi::CStr, os::raw::c_char,
+	sync::atomic::{ AtomicU8, Ordering }
+};
+pub use crate::ffi::{ Sink, sys };
+
+
+/// The base API version implemented by this crate
+pub const API_VERSION: u16 = 0x01_00;
+/// The API version with machine-readable error codes implemented by this crate
+pub const API_VERSION_V2: u16 = 0x02_00;
+
+
+/// The log level set during `init`
+static LOG_LEVEL: AtomicU8 = AtomicU8::new(0);
+
+
+/// Logs `s` to stderr if the log level set during `init` is not `0`
+pub fn log(s: impl AsRef<str>) {
+	if LOG_LEVEL.load(Ordering::SeqCst) > 0 {
+		eprintln!("{}", s.as_ref())
+	}
+}
+
+
+/// Creates a new `Error` from a string literal and an optional `ErrorCode` variant
+///
+/// Every invocation stores the description together with the code in its own static, so that equal
+/// descriptions with different codes remain distinguishable by their pointers.
+#[macro_export]
+macro_rules! error {
+	($desc:expr) => ($crate::error!(Unknown, $desc));
+	($code:ident, $desc:expr) => ({
+		const DESC: &str = concat!($desc, "\0");
+		static ERROR_DESC: $crate::ErrorDesc<{ DESC.len() }> =
+			$crate::ErrorDesc::new($crate::ErrorCode::$code, DESC);
+		$crate::Error::from_desc(&ERROR_DESC)
+	});
+}
+
+
+/// A machine-readable error code (see `KYNC_ERROR_*`; only reported with API v2)
+#[repr(u8)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ErrorCode {
+	/// An unknown error
+	Unknown = sys::KYNC_ERROR_UNKNOWN as u8,
+	/// An authentication is required
+	AuthRequired = sys::KYNC_ERROR_AUTH_REQUIRED as u8,
+	/// The authentication failed (e.g. a wrong PIN)
+	AuthFailed = sys::KYNC_ERROR_AUTH_FAILED as u8,
+	/// There are no authentication retries left
+	RetriesExhausted = sys::KYNC_ERROR_RETRIES_EXHAUSTED as u8,
+	/// The config is invalid or unsupported
+	InvalidConfig = sys::KYNC_ERROR_INVALID_CONFIG as u8,
+	/// The capsule is invalid or corrupt
+	InvalidCapsule = sys::KYNC_ERROR_INVALID_CAPSULE as u8,
+	/// The device or token is not available
+	DeviceUnavailable = sys::KYNC_ERROR_DEVICE_UNAVAILABLE as u8,
+	/// The operation has been cancelled (e.g. by the user)
+	Cancelled = sys::KYNC_ERROR_CANCELLED as u8,
+	/// An internal plugin error
+	Internal = sys::KYNC_ERROR_INTERNAL as u8
+}
+
+
+/// A static `\0`-terminated error description with its error code stored right in front of it
+/// (created by `error!`)
+#[doc(hidden)]
+#[repr(C)]
+pub struct ErrorDesc<const N: usize> {
+	code: ErrorCode,
+	desc: [u8; N]
+}
+impl<const N: usize> ErrorDesc<N> {
+	/// Creates a new description with `code` from the `\0`-terminated `desc`
+	pub const fn new(code: ErrorCode, desc: &str) -> Self {
+		let bytes = desc.as_bytes();
+		assert!(bytes.len() == N && N > 0 && bytes[N - 1] == 0, "Missing `\\0`-terminator");
+		let (mut copy, mut i) = ([0; N], 0);
+		while i < N {
+			copy[i] = bytes[i];
+			i += 1;
+		}
+		Self { code, desc: copy }
+	}
+}
+
+
+/// A plugin error that is returned as pointer to a static error description together with a
+/// machine-readable error code
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub struct Error {
+	ptr: *const c_char,
+	code: ErrorCode,
+	/// Whether the description is an `ErrorDesc` (i.e. the code is stored in front of it)
+	has_desc: bool
+}
+impl Error {
+	/// An error string indicating a NULL pointer error
+	pub const NULLPTR: Self = error!(Internal, "Unexpected NULL pointer");
+	/// An error string indicating that the plugin has not been initialized
+	pub const UNINITIALIZED: Self = error!(Internal, "The plugin has not been initialized");
+	/// An error string indicating an unsupported API version
+	pub const UNSUPPORTED_API: Self = error!("Unsupported API version");
+	/// An error string indicating that the plugin panicked
+	pub const PANIC: Self = error!(Internal, "The plugin panicked");
+	
+	/// Creates a new error from a static `\0`-terminated description
+	///
+	/// _Note: Such errors are reported as `ErrorCode::Unknown`; use `error!` to attach a code._
+	pub const fn new(desc: &'static str) -> Self {
+		let bytes = desc.as_bytes();
+		assert!(!bytes.is_empty() && bytes[bytes.len() - 1] == 0, "Missing `\\0`-terminator");
+		Self { ptr: bytes.as_ptr().cast(), code: ErrorCode::Unknown, has_desc: false }
+	}
+	/// Creates a new error from a static description with code (used by `error!`)
+	#[doc(hidden)]
+	pub const fn from_desc<const N: usize>(desc: &'static ErrorDesc<N>) -> Self {
+		Self { ptr: desc.desc.as_ptr().cast(), code: desc.code, has_desc: true }
+	}
+	/// Wraps an error pointer returned by a callback
+	pub(crate) fn from_ptr(ptr: *const c_char) -> Self {
+		Self { ptr, code: ErrorCode::Unknown, has_desc: false }
+	}
+	/// Whether the code is stored in front of the description
+	pub(crate) fn has_desc(&self) -> bool {
+		self.has_desc
+	}
+	
+	/// The error description
+	pub fn description(&self) -> &'static CStr {
+		unsafe{ CStr::from_ptr(self.ptr) }
+	}
+	/// The machine-readable error code
+	pub fn code(&self) -> ErrorCode {
+		self.code
+	}
+	/// The raw error pointer
+	pub fn as_ptr(&self) -> *const c_char {
+		self.ptr
+	}
+}
+impl Debug for Error {
+	fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+		f.debug_struct("Error").field("description", &self.description())
+			.field("code", &self.code).finish()
+	}
+}
+
+
+/// A KyNc plugin implementation (see "Kync.asciidoc" for further API documentation)
+///
+/// Since the C API has no handle, the instance is created during `init` and stored globally by
+/// `export_plugin!`; use interior mutability if your plugin needs to keep state.
+pub trait Plugin: Sized + Send + Sync + 'static {
+	/// Initializes the plugin with the stderr `log_level` to use (the API version has already been
+	/// checked; both `API_VERSION` and `API_VERSION_V2` are supported)
+	fn init(log_level: u8) -> Result<Self, Error>;
+	
+	/// The plugin/format ID (written using a single `write`-call)
+	fn id(&self) -> &[u8];
+	
+	/// Writes the plugin/format ID to `sink`
+	///
+	/// _Note: Only override this to test hosts against plugins that violate the specification; the
+	/// default implementation writes `id` using a single `write`-call._
+	#[doc(hidden)]
+	fn write_id(&self, sink: &mut Sink) -> Result<(), Error> {
+		sink.write(self.id())
+	}
+	
+	/// All possible configs (each config is written using a separate `write`-call)
+	fn configs(&self) -> Result<Vec<Vec<u8>>, Error>;
+	
+	/// Sets an optional application specific context if supported (useful to assign better names
+	/// etc.)
+	fn set_context(&self, _context: &[u8]) -> Result<(), Error> {
+		Ok(())
+	}
+	
+	/// Checks if an authentication is required to protect a secret and gets the number of retries
+	/// left (or `u64::MAX` if there is no known limit)
+	fn auth_info_protect(&self, config: &[u8]) -> Result<(bool, u64), Error>;
+	
+	/// Checks if an authentication is required to recover a secret and gets the number of retries
+	/// left (or `u64::MAX` if there is no known limit)
+	fn auth_info_recover(&self, config: &[u8]) -> Result<(bool, u64), Error>;
+	
+	/// Protects `data` and writes the public recovery information to `sink`
+	fn protect(&self, sink: &mut Sink, data: &[u8], config: &[u8], auth: Option<&[u8]>)
+		-> Result<(), Error>;
+	
+	/// Recovers the secret from the recovery information `data` and writes it to `sink`
+	fn recover(&self, sink: &mut Sink, data: &[u8], auth: Option<&[u8]>) -> Result<(), Error>;
+}
+
+
+/// Generates the exported C API for a type that implements `Plugin`
+///
+/// This macro must be called exactly once in the root of a `cdylib`-crate.
+#[macro_export]
+macro_rules! export_plugin {
+	($plugin:ty) => {
+		/// The global plugin instance
+		static KYNC_PLUGIN_INSTANCE: $crate::export::Instance<$plugin> =
+			$crate::export::Instance::new();
+		
+		/// Initializes the library with a specific API version and a logging level
+		#[no_mangle]
+		pub extern "C" fn init(api: u16, log_level: u8) -> *const ::std::os::raw::c_char {
+			$crate::export::init(&KYNC_PLUGIN_INSTANCE, api, log_level)
+		}
+		
+		/// Queries the plugin/format ID
+		#[no_mangle]
+		pub extern "C" fn id(sink: *mut $crate::sys::write_t) -> *const ::std::os::raw::c_char {
+			$crate::export::id(&KYNC_PLUGIN_INSTANCE, sink)
+		}
+		
+		/// Queries all possible configs and writes them as separate segments
+		#[no_mangle]
+		pub extern "C" fn configs(sink: *mut $crate::sys::write_t)
+			-> *const ::std::os::raw::c_char
+		{
+			$crate::export::configs(&KYNC_PLUGIN_INSTANCE, sink)
+		}
+		
+		/// Sets an optional application specific context if supported
+		#[no_mangle]
+		pub extern "C" fn set_context(context: *const $crate::sys::slice_t)
+			-> *const ::std::os::raw::c_char
+		{
+			$crate::export::set_context(&KYNC_PLUGIN_INSTANCE, context)
+		}
+		
+		/// Queries the authentication requirements to protect a secret for a specific config
+		#[no_mangle]
+		pub extern "C" fn auth_info_protect(is_required: *mut u8, retries: *mut u64,
+			config: *const $crate::sys::slice_t) -> *const ::std::os::raw::c_char
+		{
+			$crate::export::auth_info_protect(&KYNC_PLUGIN_INSTANCE, is_required, retries, config)
+		}
+		
+		/// Queries the authentication requirements to recover a secret for a specific config
+		#[no_mangle]
+		pub extern "C" fn auth_info_recover(is_required: *mut u8, retries: *mut u64,
+			config: *const $crate::sys::slice_t) -> *const ::std::os::raw::c_char
+		{
+			$crate::export::auth_info_recover(&KYNC_PLUGIN_INSTANCE, is_required, retries, config)
+		}
+		
+		/// Protects some data
+		#[no_mangle]
+		pub extern "C" fn protect(sink: *mut $crate::sys::write_t,
+			data: *const $crate::sys::slice_t, config: *const $crate::sys::slice_t,
+			auth: *const $crate::sys::slice_t) -> *const ::std::os::raw::c_char
+		{
+			$crate::export::protect(&KYNC_PLUGIN_INSTANCE, sink, data, config, auth)
+		}
+		
+		/// Opens `data` to `sink` using `auth`
+		#[no_mangle]
+		pub extern "C" fn recover(sink: *mut $crate::sys::write_t,
+			data: *const $crate::sys::slice_t, auth: *const $crate::sys::slice_t)
+			-> *const ::std::os::raw::c_char
+		{
+			$crate::export::recover(&KYNC_PLUGIN_INSTANCE, sink, data, auth)
+		}
+		
+		/// Maps an error pointer returned by this plugin to a machine-readable error code
+		#[no_mangle]
+		pub extern "C" fn error_code(error: *const ::std::os::raw::c_char) -> u8 {
+			$crate::export::error_code(error)
+		}
+	};
+}
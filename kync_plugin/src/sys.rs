@@ -1,5 +1,14 @@
 /* automatically generated by rust-bindgen */
 
+pub const KYNC_ERROR_UNKNOWN: u32 = 0;
+pub const KYNC_ERROR_AUTH_REQUIRED: u32 = 1;
+pub const KYNC_ERROR_AUTH_FAILED: u32 = 2;
+pub const KYNC_ERROR_RETRIES_EXHAUSTED: u32 = 3;
+pub const KYNC_ERROR_INVALID_CONFIG: u32 = 4;
+pub const KYNC_ERROR_INVALID_CAPSULE: u32 = 5;
+pub const KYNC_ERROR_DEVICE_UNAVAILABLE: u32 = 6;
+pub const KYNC_ERROR_CANCELLED: u32 = 7;
+pub const KYNC_ERROR_INTERNAL: u32 = 8;
 #[doc = " A slice over some data"]
 #[repr(C)]
 #[derive(Debug)]
@@ -107,4 +116,12 @@ pub type recover = ::core::option::Option<
 		data: *const slice_t,
 		auth: *const slice_t,
 	) -> *const ::std::os::raw::c_char,
+>;
+#[doc = " Maps an error pointer returned by an API call to an error code (API v2 only)"]
+#[doc = ""]
+#[doc = " \\param error The error pointer returned by an API call"]
+#[doc = " \\return The error code or `KYNC_ERROR_UNKNOWN` if the pointer was not created by the plugin (e.g."]
+#[doc = "         because it is a propagated callback error)"]
+pub type error_code = ::core::option::Option<
+	unsafe extern "C" fn(error: *const ::std::os::raw::c_char) -> u8,
 >;
\ No newline at end of file
@@ -0,0 +1,98 @@
+use kync_plugin::{ Error, ErrorCode, Plugin, Sink, error, export_plugin, sys };
+
+
+/// A dummy plugin
+struct DummyPlugin;
+impl Plugin for DummyPlugin {
+	fn init(_log_level: u8) -> Result<Self, Error> {
+		Ok(Self)
+	}
+	fn id(&self) -> &[u8] {
+		b"DummyPlugin"
+	}
+	fn configs(&self) -> Result<Vec<Vec<u8>>, Error> {
+		Ok(vec![b"Default".to_vec()])
+	}
+	fn auth_info_protect(&self, _config: &[u8]) -> Result<(bool, u64), Error> {
+		Ok((false, u64::MAX))
+	}
+	fn auth_info_recover(&self, _config: &[u8]) -> Result<(bool, u64), Error> {
+		Ok((false, u64::MAX))
+	}
+	fn protect(&self, sink: &mut Sink, data: &[u8], _config: &[u8], _auth: Option<&[u8]>)
+		-> Result<(), Error>
+	{
+		sink.write(data)
+	}
+	fn recover(&self, _sink: &mut Sink, _data: &[u8], _auth: Option<&[u8]>) -> Result<(), Error> {
+		panic!("Dummy panic")
+	}
+}
+export_plugin!(DummyPlugin);
+
+
+/// A write callback that collects the segments
+unsafe extern "C" fn write(handle: *mut std::os::raw::c_void, data: *const sys::slice_t)
+	-> *const std::os::raw::c_char
+{
+	let (handle, data) = (&mut *handle.cast::<Vec<Vec<u8>>>(), &*data);
+	handle.push(std::slice::from_raw_parts(data.ptr, data.len).to_vec());
+	std::ptr::null()
+}
+
+
+#[test]
+fn test_types() {
+	struct Fns {
+		_init: sys::init,
+		_id: sys::id,
+		_configs: sys::configs,
+		_set_context: sys::set_context,
+		_auth_info_protect: sys::auth_info_protect,
+		_auth_info_recover: sys::auth_info_recover,
+		_protect: sys::protect,
+		_recover: sys::recover,
+		_error_code: sys::error_code
+	}
+	let _fns = Fns {
+		_init: Some(init),
+		_id: Some(id),
+		_configs: Some(configs),
+		_set_context: Some(set_context),
+		_auth_info_protect: Some(auth_info_protect),
+		_auth_info_recover: Some(auth_info_recover),
+		_protect: Some(protect),
+		_recover: Some(recover),
+		_error_code: Some(error_code)
+	};
+}
+
+
+#[test]
+fn test_export() {
+	let mut segments: Vec<Vec<u8>> = Vec::new();
+	let mut sink = sys::write_t{ handle: (&mut segments as *mut Vec<Vec<u8>>).cast(), write: Some(write) };
+	
+	// Calls before `init` and with an unsupported API version must fail
+	assert_eq!(id(&mut sink), Error::UNINITIALIZED.as_ptr());
+	assert_eq!(init(0x07_00, 0), Error::UNSUPPORTED_API.as_ptr());
+	assert!(init(kync_plugin::API_VERSION_V2, 0).is_null());
+	assert!(init(kync_plugin::API_VERSION, 0).is_null());
+	
+	// Test the calls
+	assert!(id(&mut sink).is_null());
+	assert!(configs(&mut sink).is_null());
+	assert_eq!(segments, [b"DummyPlugin".to_vec(), b"Default".to_vec()]);
+	
+	// Test NULL-pointer checks and panic catching
+	assert_eq!(protect(&mut sink, std::ptr::null(), std::ptr::null(), std::ptr::null()),
+		Error::NULLPTR.as_ptr());
+	let data = sys::slice_t{ ptr: b"".as_ptr(), len: 0 };
+	assert_eq!(recover(&mut sink, &data, std::ptr::null()), Error::PANIC.as_ptr());
+	assert_eq!(error!("Test").description().to_bytes(), b"Test");
+	
+	// Test the error codes
+	assert_eq!(error_code(Error::PANIC.as_ptr()), sys::KYNC_ERROR_INTERNAL as u8);
+	assert_eq!(error_code(b"Unknown\0".as_ptr().cast()), sys::KYNC_ERROR_UNKNOWN as u8);
+	assert_eq!(error!(AuthFailed, "Test").code(), ErrorCode::AuthFailed);
+}
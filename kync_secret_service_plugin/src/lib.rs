@@ -0,0 +1,154 @@
+/// A minimal client for the Secret Service D-Bus API
+mod service;
+
+use crate::service::SecretService;
+use kync_plugin::{ Error, Plugin, Sink, error, export_plugin };
+use std::{ collections::HashMap, sync::Mutex, convert::TryFrom };
+
+
+const UID: &[u8] = b"SecretServiceCapsuleFormat.42C50C2F-4B5C-41F2-9C72-68F7085A6068";
+/// The supported collection aliases
+const ALIASES: &[&str] = &["default", "session"];
+
+/// The capsule format version
+const VERSION: u8 = 1;
+/// The attributes that identify the secrets stored by this plugin
+const SCHEMA_ATTRIBUTE: (&str, &str) = ("xdg:schema", "org.kync.Secret");
+const CONTEXT_ATTRIBUTE: &str = "kync:context";
+const ID_ATTRIBUTE: &str = "kync:id";
+
+const ID_LEN: usize = 16;
+
+
+/// Validates the config and returns the collection alias
+fn alias(config: &[u8]) -> Result<&str, Error> {
+	ALIASES.iter().copied().find(|alias| alias.as_bytes() == config)
+		.ok_or(error!(InvalidConfig, "Invalid collection alias"))
+}
+/// Builds the attributes for `context` and the hex-encoded `id`
+fn attributes<'a>(context: &'a str, id: &'a str) -> HashMap<&'a str, &'a str> {
+	[SCHEMA_ATTRIBUTE, (CONTEXT_ATTRIBUTE, context), (ID_ATTRIBUTE, id)].iter().copied().collect()
+}
+
+
+/// The parsed capsule (the lookup handle of the stored secret)
+struct Handle<'a> {
+	context: &'a str,
+	id: &'a [u8]
+}
+impl<'a> Handle<'a> {
+	/// Parses a capsule
+	fn parse(capsule: &'a [u8]) -> Result<Self, Error> {
+		const INVALID: Error = error!(InvalidCapsule, "Invalid or truncated capsule");
+		if capsule.len() < 2 || capsule[0] != VERSION {
+			return Err(INVALID)
+		}
+		let context_len = capsule[1] as usize;
+		if capsule.len() != 2 + context_len + ID_LEN {
+			return Err(INVALID)
+		}
+		let (context, id) = capsule[2..].split_at(context_len);
+		let context = std::str::from_utf8(context).map_err(|_| INVALID)?;
+		Ok(Self { context, id })
+	}
+	/// Serializes the handle
+	fn serialize(&self) -> Result<Vec<u8>, Error> {
+		let context_len = u8::try_from(self.context.len())
+			.map_err(|_| error!(InvalidConfig, "The context is too long"))?;
+		let mut bytes = vec![VERSION, context_len];
+		bytes.extend_from_slice(self.context.as_bytes());
+		bytes.extend_from_slice(self.id);
+		Ok(bytes)
+	}
+	/// The hex-encoded ID
+	fn hex_id(&self) -> String {
+		self.id.iter().map(|b| format!("{:02x}", b)).collect()
+	}
+}
+
+
+/// A plugin that stores secrets in the user's keyring via the Freedesktop Secret Service API
+///
+/// The configs are the collection aliases (`default`, `session`) that are available. Every
+/// `protect`-call stores the secret as new item whose attributes contain the context set with
+/// `set_context` and a random ID; the capsule is only the handle to look the item up again. The
+/// Secret Service is reached over the session bus (`$DBUS_SESSION_BUS_ADDRESS`) and shows its own
+/// prompts if a collection is locked.
+pub struct SecretServicePlugin {
+	context: Mutex<String>
+}
+impl SecretServicePlugin {
+	/// The context set by `set_context`
+	fn context(&self) -> String {
+		self.context.lock().unwrap_or_else(|e| e.into_inner()).clone()
+	}
+}
+impl Plugin for SecretServicePlugin {
+	fn init(_log_level: u8) -> Result<Self, Error> {
+		Ok(Self { context: Mutex::new(String::new()) })
+	}
+	
+	fn id(&self) -> &[u8] {
+		UID
+	}
+	
+	fn configs(&self) -> Result<Vec<Vec<u8>>, Error> {
+		let service = SecretService::connect()?;
+		let mut configs = Vec::new();
+		for alias in ALIASES {
+			if service.collection(alias)?.is_some() {
+				configs.push(alias.as_bytes().to_vec());
+			}
+		}
+		Ok(configs)
+	}
+	
+	fn set_context(&self, context: &[u8]) -> Result<(), Error> {
+		let context = std::str::from_utf8(context)
+			.map_err(|_| error!(InvalidConfig, "The context is not UTF-8"))?;
+		if context.len() > u8::MAX as usize {
+			return Err(error!(InvalidConfig, "The context is too long"))
+		}
+		*self.context.lock().unwrap_or_else(|e| e.into_inner()) = context.to_string();
+		Ok(())
+	}
+	
+	fn auth_info_protect(&self, config: &[u8]) -> Result<(bool, u64), Error> {
+		// The Secret Service asks for the authentication itself
+		alias(config)?;
+		Ok((false, u64::MAX))
+	}
+	
+	fn auth_info_recover(&self, config: &[u8]) -> Result<(bool, u64), Error> {
+		alias(config)?;
+		Ok((false, u64::MAX))
+	}
+	
+	fn protect(&self, sink: &mut Sink, data: &[u8], config: &[u8], _auth: Option<&[u8]>)
+		-> Result<(), Error>
+	{
+		let alias = alias(config)?;
+		let mut id = [0; ID_LEN];
+		getrandom::getrandom(&mut id).map_err(|_| error!(Internal, "Failed to gather randomness"))?;
+		
+		// Store the secret
+		let context = self.context();
+		let handle = Handle { context: &context, id: &id };
+		let (capsule, hex_id) = (handle.serialize()?, handle.hex_id());
+		let service = SecretService::connect()?;
+		let collection = service.collection(alias)?
+			.ok_or(error!(DeviceUnavailable, "The collection does not exist"))?;
+		let label = format!("KyNc secret ({})", if context.is_empty() { alias } else { &context });
+		service.store(&collection, &label, &attributes(&context, &hex_id), data)?;
+		sink.write(capsule)
+	}
+	
+	fn recover(&self, sink: &mut Sink, data: &[u8], _auth: Option<&[u8]>) -> Result<(), Error> {
+		let handle = Handle::parse(data)?;
+		let hex_id = handle.hex_id();
+		let secret = SecretService::connect()?.load(&attributes(handle.context, &hex_id))?
+			.ok_or(error!(DeviceUnavailable, "The secret does not exist in the keyring"))?;
+		sink.write(secret.as_slice())
+	}
+}
+export_plugin!(SecretServicePlugin);
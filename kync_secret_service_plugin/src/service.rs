@@ -0,0 +1,153 @@
+use kync_plugin::{ Error, error };
+use std::{ collections::HashMap, slice };
+use zbus::{
+	blocking::{ Connection, Proxy },
+	export::serde::Serialize,
+	zvariant::{ DynamicDeserialize, DynamicType, ObjectPath, OwnedObjectPath, OwnedValue, Value }
+};
+use zeroize::Zeroizing;
+
+
+/// The bus name, object path and interfaces of the Secret Service
+const SERVICE: &str = "org.freedesktop.secrets";
+const SERVICE_PATH: &str = "/org/freedesktop/secrets";
+const SERVICE_IFACE: &str = "org.freedesktop.Secret.Service";
+const COLLECTION_IFACE: &str = "org.freedesktop.Secret.Collection";
+const ITEM_IFACE: &str = "org.freedesktop.Secret.Item";
+const PROMPT_IFACE: &str = "org.freedesktop.Secret.Prompt";
+
+/// The item properties
+const LABEL_PROPERTY: &str = "org.freedesktop.Secret.Item.Label";
+const ATTRIBUTES_PROPERTY: &str = "org.freedesktop.Secret.Item.Attributes";
+/// The content type of the stored secrets
+const CONTENT_TYPE: &str = "application/octet-stream";
+/// The object path that indicates "no object" (e.g. if no prompt is necessary)
+const NO_OBJECT: &str = "/";
+
+
+/// Maps a D-Bus error to a plugin error
+fn dbus_error(error: zbus::Error) -> Error {
+	let name = match &error {
+		zbus::Error::MethodError(name, ..) => name.as_str(),
+		_ => return error!(DeviceUnavailable, "Failed to communicate with the Secret Service")
+	};
+	match name {
+		"org.freedesktop.DBus.Error.ServiceUnknown" =>
+			error!(DeviceUnavailable, "No Secret Service is running"),
+		"org.freedesktop.Secret.Error.IsLocked" => error!(AuthRequired, "The collection is locked"),
+		"org.freedesktop.Secret.Error.NoSuchObject" =>
+			error!(DeviceUnavailable, "The collection or secret does not exist"),
+		_ => error!(DeviceUnavailable, "The Secret Service rejected the request")
+	}
+}
+/// Calls `method` on the Secret Service object `path`
+fn call<B, R>(connection: &Connection, path: &str, iface: &str, method: &str, body: &B)
+	-> Result<R, Error> where B: Serialize + DynamicType, R: for<'d> DynamicDeserialize<'d>
+{
+	let reply = connection.call_method(Some(SERVICE), path, Some(iface), method, body)
+		.map_err(dbus_error)?;
+	reply.body().deserialize()
+		.map_err(|_| error!(DeviceUnavailable, "The Secret Service sent an invalid reply"))
+}
+
+
+/// A session with the Secret Service
+///
+/// _Note: The session uses the `plain` algorithm, so the secrets are transferred unencrypted over
+/// the (private) session bus, like most Secret Service clients do._
+pub struct SecretService {
+	connection: Connection,
+	session: OwnedObjectPath
+}
+impl SecretService {
+	/// Connects to the Secret Service on the session bus and opens a session
+	pub fn connect() -> Result<Self, Error> {
+		let connection = Connection::session()
+			.map_err(|_| error!(DeviceUnavailable, "Failed to connect to the session bus"))?;
+		let (_, session): (OwnedValue, OwnedObjectPath) = call(&connection, SERVICE_PATH,
+			SERVICE_IFACE, "OpenSession", &("plain", Value::from("")))?;
+		Ok(Self { connection, session })
+	}
+	/// Runs the prompt `path` (if any) and waits until it is completed
+	fn prompt(&self, path: &ObjectPath<'_>) -> Result<(), Error> {
+		if path.as_str() == NO_OBJECT {
+			return Ok(())
+		}
+		
+		// Subscribe to the completion before the prompt is started
+		let proxy = Proxy::new(&self.connection, SERVICE, path.clone(), PROMPT_IFACE)
+			.map_err(dbus_error)?;
+		let mut completed = proxy.receive_signal("Completed").map_err(dbus_error)?;
+		proxy.call::<_, _, ()>("Prompt", &("",)).map_err(dbus_error)?;
+		
+		// Wait for the result
+		const INVALID: Error = error!(DeviceUnavailable, "The Secret Service prompt failed");
+		let message = completed.next().ok_or(INVALID)?;
+		let (dismissed, _): (bool, OwnedValue) =
+			message.body().deserialize().map_err(|_| INVALID)?;
+		match dismissed {
+			true => Err(error!(Cancelled, "The Secret Service prompt has been dismissed")),
+			false => Ok(())
+		}
+	}
+	/// Unlocks `object` if necessary
+	fn unlock(&self, object: &OwnedObjectPath) -> Result<(), Error> {
+		let objects = slice::from_ref(object);
+		let (_, prompt): (Vec<OwnedObjectPath>, OwnedObjectPath) =
+			call(&self.connection, SERVICE_PATH, SERVICE_IFACE, "Unlock", &(objects,))?;
+		self.prompt(&prompt)
+	}
+	
+	/// Resolves the collection `alias` (or returns `None` if the alias is not set)
+	pub fn collection(&self, alias: &str) -> Result<Option<OwnedObjectPath>, Error> {
+		let collection: OwnedObjectPath =
+			call(&self.connection, SERVICE_PATH, SERVICE_IFACE, "ReadAlias", &(alias,))?;
+		match collection.as_str() {
+			NO_OBJECT => Ok(None),
+			_ => Ok(Some(collection))
+		}
+	}
+	
+	/// Stores `secret` with `label` and `attributes` in `collection`
+	pub fn store(&self, collection: &OwnedObjectPath, label: &str,
+		attributes: &HashMap<&str, &str>, secret: &[u8]) -> Result<(), Error>
+	{
+		self.unlock(collection)?;
+		let properties: HashMap<&str, Value> = [
+			(LABEL_PROPERTY, Value::from(label)),
+			(ATTRIBUTES_PROPERTY, Value::from(attributes.clone()))
+		].iter().cloned().collect();
+		let secret = (&self.session, b"".as_ref(), secret, CONTENT_TYPE);
+		let (_, prompt): (OwnedObjectPath, OwnedObjectPath) = call(&self.connection,
+			collection.as_str(), COLLECTION_IFACE, "CreateItem", &(properties, secret, false))?;
+		self.prompt(&prompt)
+	}
+	
+	/// Loads the secret with `attributes` (or returns `None` if there is no such secret)
+	pub fn load(&self, attributes: &HashMap<&str, &str>)
+		-> Result<Option<Zeroizing<Vec<u8>>>, Error>
+	{
+		// Search the item and unlock it if necessary
+		let (unlocked, locked): (Vec<OwnedObjectPath>, Vec<OwnedObjectPath>) =
+			call(&self.connection, SERVICE_PATH, SERVICE_IFACE, "SearchItems", &(attributes,))?;
+		let item = match (unlocked.first(), locked.first()) {
+			(Some(item), _) => item.clone(),
+			(None, Some(item)) => {
+				self.unlock(item)?;
+				item.clone()
+			},
+			(None, None) => return Ok(None)
+		};
+		
+		// Get the secret
+		let (_, _, secret, _): (OwnedObjectPath, Vec<u8>, Vec<u8>, String) =
+			call(&self.connection, item.as_str(), ITEM_IFACE, "GetSecret", &(&self.session,))?;
+		Ok(Some(Zeroizing::new(secret)))
+	}
+}
+impl Drop for SecretService {
+	fn drop(&mut self) {
+		let _ = self.connection.call_method(Some(SERVICE), self.session.as_str(),
+			Some("org.freedesktop.Secret.Session"), "Close", &());
+	}
+}
@@ -0,0 +1,138 @@
+use kync_plugin::{ Error, error };
+use std::{
+	io::{ Read, Write },
+	os::unix::net::UnixStream,
+	path::Path, convert::TryFrom
+};
+use zeroize::Zeroizing;
+
+
+/// The agent protocol message types (see draft-miller-ssh-agent)
+const SSH_AGENT_FAILURE: u8 = 5;
+const SSH_AGENTC_REQUEST_IDENTITIES: u8 = 11;
+const SSH_AGENT_IDENTITIES_ANSWER: u8 = 12;
+const SSH_AGENTC_SIGN_REQUEST: u8 = 13;
+const SSH_AGENT_SIGN_RESPONSE: u8 = 14;
+
+/// The maximum accepted length of an agent message
+const MAX_MESSAGE_LEN: usize = 256 * 1024;
+/// The key type of Ed25519 keys
+pub const ED25519: &[u8] = b"ssh-ed25519";
+
+
+/// The error returned if the agent sends a malformed message
+const MALFORMED: Error = error!(DeviceUnavailable, "The ssh-agent sent an invalid message");
+
+
+/// Reads SSH wire-format values from a message
+struct Reader<'a>(&'a [u8]);
+impl<'a> Reader<'a> {
+	/// Reads `len` bytes
+	fn bytes(&mut self, len: usize) -> Result<&'a [u8], Error> {
+		if self.0.len() < len {
+			return Err(MALFORMED)
+		}
+		let (bytes, rest) = self.0.split_at(len);
+		self.0 = rest;
+		Ok(bytes)
+	}
+	/// Reads a big-endian `u32`
+	fn u32(&mut self) -> Result<u32, Error> {
+		let bytes = self.bytes(4)?;
+		Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+	}
+	/// Reads a length-prefixed string
+	fn string(&mut self) -> Result<&'a [u8], Error> {
+		let len = self.u32()? as usize;
+		self.bytes(len)
+	}
+}
+/// Appends `bytes` as length-prefixed string to `buf`
+fn put_string(buf: &mut Vec<u8>, bytes: &[u8]) -> Result<(), Error> {
+	let len = u32::try_from(bytes.len())
+		.map_err(|_| error!(Internal, "The agent request is too long"))?;
+	buf.extend_from_slice(&len.to_be_bytes());
+	buf.extend_from_slice(bytes);
+	Ok(())
+}
+
+
+/// Returns the key type of the public key `blob`
+pub fn key_type(blob: &[u8]) -> Option<&[u8]> {
+	Reader(blob).string().ok()
+}
+
+
+/// A connection to an ssh-agent
+pub struct Agent {
+	stream: UnixStream
+}
+impl Agent {
+	/// Connects to the agent listening on `socket`
+	pub fn connect(socket: &Path) -> Result<Self, Error> {
+		let stream = UnixStream::connect(socket)
+			.map_err(|_| error!(DeviceUnavailable, "Failed to connect to the ssh-agent"))?;
+		Ok(Self { stream })
+	}
+	
+	/// Sends `request` and returns the response
+	fn request(&mut self, request: &[u8]) -> Result<Zeroizing<Vec<u8>>, Error> {
+		const IO_ERROR: Error =
+			error!(DeviceUnavailable, "Failed to communicate with the ssh-agent");
+		
+		// Send the request
+		let mut message = Zeroizing::new(Vec::new());
+		put_string(&mut message, request)?;
+		self.stream.write_all(&message).map_err(|_| IO_ERROR)?;
+		
+		// Read the response
+		let mut len = [0; 4];
+		self.stream.read_exact(&mut len).map_err(|_| IO_ERROR)?;
+		let len = u32::from_be_bytes(len) as usize;
+		if len == 0 || len > MAX_MESSAGE_LEN {
+			return Err(MALFORMED)
+		}
+		let mut response = Zeroizing::new(vec![0; len]);
+		self.stream.read_exact(&mut response).map_err(|_| IO_ERROR)?;
+		Ok(response)
+	}
+	
+	/// Lists the public key blobs of all identities
+	pub fn identities(&mut self) -> Result<Vec<Vec<u8>>, Error> {
+		let response = self.request(&[SSH_AGENTC_REQUEST_IDENTITIES])?;
+		let mut reader = Reader(&response);
+		if reader.bytes(1)? != [SSH_AGENT_IDENTITIES_ANSWER] {
+			return Err(error!(DeviceUnavailable, "The ssh-agent refused to list its identities"))
+		}
+		
+		// Read the key blobs and skip the comments
+		let count = reader.u32()?;
+		let mut blobs = Vec::new();
+		for _ in 0..count {
+			blobs.push(reader.string()?.to_vec());
+			reader.string()?;
+		}
+		Ok(blobs)
+	}
+	
+	/// Signs `data` with the key `blob` and returns the raw signature
+	pub fn sign(&mut self, blob: &[u8], data: &[u8]) -> Result<Zeroizing<Vec<u8>>, Error> {
+		let mut request = vec![SSH_AGENTC_SIGN_REQUEST];
+		put_string(&mut request, blob)?;
+		put_string(&mut request, data)?;
+		request.extend_from_slice(&0u32.to_be_bytes());
+		
+		// Parse the response
+		let response = self.request(&request)?;
+		let mut reader = Reader(&response);
+		match reader.bytes(1)?[0] {
+			SSH_AGENT_SIGN_RESPONSE => (),
+			SSH_AGENT_FAILURE =>
+				return Err(error!(AuthFailed, "The ssh-agent refused to sign the challenge")),
+			_ => return Err(MALFORMED)
+		}
+		let mut signature = Reader(reader.string()?);
+		signature.string()?;
+		Ok(Zeroizing::new(signature.string()?.to_vec()))
+	}
+}
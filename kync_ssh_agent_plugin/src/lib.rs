@@ -0,0 +1,187 @@
+//! A KyNc plugin that protects secrets with Ed25519 keys held by a running ssh-agent
+//!
+//! _Note: The agent is reached over a Unix domain socket; on other platforms this library is
+//! empty._
+#![cfg(unix)]
+
+/// A minimal client for the ssh-agent protocol
+mod agent;
+
+use crate::agent::{ Agent, ED25519 };
+use base64::{ Engine, engine::general_purpose::STANDARD_NO_PAD };
+use blake2::{ Blake2b, Digest, digest::consts::U32 };
+use chacha20poly1305::{
+	Key, XChaCha20Poly1305, XNonce,
+	aead::{ Aead, KeyInit, Payload }
+};
+use kync_plugin::{ Error, Plugin, Sink, error, export_plugin };
+use sha2::Sha256;
+use std::{ env, path::PathBuf, sync::Mutex };
+use zeroize::Zeroizing;
+
+
+const UID: &[u8] = b"SshAgentCapsuleFormat.74EEA87F-313E-4ECE-AF08-4291C50B6C40";
+/// The environment variable that contains the path of the agent socket
+const SOCKET_ENV: &str = "SSH_AUTH_SOCK";
+
+/// The capsule format version
+const VERSION: u8 = 1;
+/// The domain separators for the signed challenge and the derived key
+const CHALLENGE_DOMAIN: &[u8] = b"KyNc ssh-agent challenge\0";
+const KEY_DOMAIN: &[u8] = b"KyNc ssh-agent key\0";
+/// The prefix of the key fingerprints
+const FINGERPRINT_PREFIX: &str = "SHA256:";
+
+const DIGEST_LEN: usize = 32;
+const CHALLENGE_LEN: usize = 32;
+const NONCE_LEN: usize = 24;
+const HEADER_LEN: usize = 1 + DIGEST_LEN + CHALLENGE_LEN + NONCE_LEN;
+
+
+/// Converts the raw path `bytes` into a path
+fn to_path(bytes: &[u8]) -> PathBuf {
+	<std::ffi::OsStr as std::os::unix::ffi::OsStrExt>::from_bytes(bytes).into()
+}
+/// Computes the SHA-256 digest of the public key `blob` (as used by OpenSSH fingerprints)
+fn digest(blob: &[u8]) -> [u8; DIGEST_LEN] {
+	Sha256::digest(blob).into()
+}
+/// Formats `digest` as OpenSSH fingerprint (`SHA256:<base64>`)
+fn fingerprint(digest: &[u8]) -> Vec<u8> {
+	(FINGERPRINT_PREFIX.to_string() + &STANDARD_NO_PAD.encode(digest)).into_bytes()
+}
+/// Parses an OpenSSH fingerprint into the digest
+fn parse_fingerprint(config: &[u8]) -> Result<[u8; DIGEST_LEN], Error> {
+	const INVALID: Error =
+		error!(InvalidConfig, "Invalid configuration (expected a SHA256 fingerprint)");
+	let encoded = config.strip_prefix(FINGERPRINT_PREFIX.as_bytes()).ok_or(INVALID)?;
+	let digest = STANDARD_NO_PAD.decode(encoded).map_err(|_| INVALID)?;
+	let mut bytes = [0; DIGEST_LEN];
+	match digest.len() {
+		DIGEST_LEN => bytes.copy_from_slice(&digest),
+		_ => return Err(INVALID)
+	}
+	Ok(bytes)
+}
+
+
+/// A plugin that protects secrets with Ed25519 keys held by a running ssh-agent
+///
+/// The configs are the OpenSSH fingerprints (`SHA256:...`) of the agent's Ed25519 identities. Every
+/// `protect`-call creates a random challenge and asks the agent to sign it; since Ed25519
+/// signatures are deterministic, the signature can be recreated during recovery and is used to
+/// derive the wrapping key. The agent socket is set with `set_context` or taken from
+/// `$SSH_AUTH_SOCK`.
+pub struct SshAgentPlugin {
+	socket: Mutex<Option<PathBuf>>
+}
+impl SshAgentPlugin {
+	/// Connects to the agent
+	fn agent(&self) -> Result<Agent, Error> {
+		let socket = self.socket.lock().unwrap_or_else(|e| e.into_inner()).clone()
+			.or_else(|| env::var_os(SOCKET_ENV).map(PathBuf::from))
+			.ok_or(error!(DeviceUnavailable, "No ssh-agent is available"))?;
+		Agent::connect(&socket)
+	}
+	/// Finds the Ed25519 identity with the fingerprint `digest`
+	fn identity(agent: &mut Agent, digest: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+		let mut identities = agent.identities()?.into_iter()
+			.filter(|blob| agent::key_type(blob) == Some(ED25519));
+		Ok(identities.find(|blob| self::digest(blob) == digest))
+	}
+	/// Signs `challenge` with `identity` and derives the cipher from the signature
+	fn cipher(agent: &mut Agent, identity: &[u8], challenge: &[u8])
+		-> Result<XChaCha20Poly1305, Error>
+	{
+		let signature = agent.sign(identity, &[CHALLENGE_DOMAIN, challenge].concat())?;
+		let key = Blake2b::<U32>::new_with_prefix(KEY_DOMAIN).chain_update(signature.as_slice())
+			.finalize();
+		let key = Zeroizing::new(key.to_vec());
+		Ok(XChaCha20Poly1305::new(Key::from_slice(&key)))
+	}
+}
+impl Plugin for SshAgentPlugin {
+	fn init(_log_level: u8) -> Result<Self, Error> {
+		Ok(Self { socket: Mutex::new(None) })
+	}
+	
+	fn id(&self) -> &[u8] {
+		UID
+	}
+	
+	fn configs(&self) -> Result<Vec<Vec<u8>>, Error> {
+		let identities = self.agent()?.identities()?;
+		Ok(identities.iter()
+			.filter(|blob| agent::key_type(blob) == Some(ED25519))
+			.map(|blob| fingerprint(&digest(blob)))
+			.collect())
+	}
+	
+	fn set_context(&self, context: &[u8]) -> Result<(), Error> {
+		let socket = match context.is_empty() {
+			true => None,
+			false => Some(to_path(context))
+		};
+		*self.socket.lock().unwrap_or_else(|e| e.into_inner()) = socket;
+		Ok(())
+	}
+	
+	fn auth_info_protect(&self, config: &[u8]) -> Result<(bool, u64), Error> {
+		parse_fingerprint(config)?;
+		Ok((false, u64::MAX))
+	}
+	
+	fn auth_info_recover(&self, config: &[u8]) -> Result<(bool, u64), Error> {
+		parse_fingerprint(config)?;
+		Ok((false, u64::MAX))
+	}
+	
+	fn protect(&self, sink: &mut Sink, data: &[u8], config: &[u8], _auth: Option<&[u8]>)
+		-> Result<(), Error>
+	{
+		// Find the identity
+		let digest = parse_fingerprint(config)?;
+		let mut agent = self.agent()?;
+		let identity = Self::identity(&mut agent, &digest)?
+			.ok_or(error!(InvalidConfig, "The ssh-agent has no such Ed25519 key"))?;
+		
+		// Create the header
+		let (mut challenge, mut nonce) = ([0; CHALLENGE_LEN], [0; NONCE_LEN]);
+		getrandom::getrandom(&mut challenge)
+			.and_then(|_| getrandom::getrandom(&mut nonce))
+			.map_err(|_| error!(Internal, "Failed to gather randomness"))?;
+		let mut capsule = vec![VERSION];
+		capsule.extend_from_slice(&digest);
+		capsule.extend_from_slice(&challenge);
+		capsule.extend_from_slice(&nonce);
+		
+		// Seal the data
+		let payload = Payload { msg: data, aad: &capsule };
+		let ciphertext = Self::cipher(&mut agent, &identity, &challenge)?
+			.encrypt(XNonce::from_slice(&nonce), payload)
+			.map_err(|_| error!(Internal, "Failed to seal the secret"))?;
+		capsule.extend_from_slice(&ciphertext);
+		sink.write(capsule)
+	}
+	
+	fn recover(&self, sink: &mut Sink, data: &[u8], _auth: Option<&[u8]>) -> Result<(), Error> {
+		// Parse the header
+		if data.len() < HEADER_LEN || data[0] != VERSION {
+			return Err(error!(InvalidCapsule, "Invalid or truncated capsule"))
+		}
+		let (header, ciphertext) = data.split_at(HEADER_LEN);
+		let (digest, rest) = header[1..].split_at(DIGEST_LEN);
+		let (challenge, nonce) = rest.split_at(CHALLENGE_LEN);
+		
+		// Recreate the signature and open the data
+		let mut agent = self.agent()?;
+		let identity = Self::identity(&mut agent, digest)?
+			.ok_or(error!(DeviceUnavailable, "The key is not available in the ssh-agent"))?;
+		let payload = Payload { msg: ciphertext, aad: header };
+		let plaintext = Self::cipher(&mut agent, &identity, challenge)?
+			.decrypt(XNonce::from_slice(nonce), payload)
+			.map_err(|_| error!(InvalidCapsule, "The capsule is corrupt"))?;
+		sink.write(Zeroizing::new(plaintext).as_slice())
+	}
+}
+export_plugin!(SshAgentPlugin);
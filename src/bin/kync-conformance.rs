@@ -0,0 +1,46 @@
+//! A helper binary that checks if a KyNc plugin follows "Kync.asciidoc" (see
+//! `kync::conformance`)
+
+use kync::conformance::Harness;
+use std::{ env, process, time::Duration };
+
+
+/// Prints the usage and exits
+fn usage() -> ! {
+	eprintln!("Usage: kync-conformance [--json] [--auth <secret>] [--timeout <seconds>] <plugin>");
+	process::exit(2)
+}
+
+
+fn main() {
+	// Parse the arguments
+	let (mut harness, mut json, mut path) = (Harness::new(), false, None);
+	let mut args = env::args_os().skip(1);
+	while let Some(arg) = args.next() {
+		match arg.to_str() {
+			Some("--json") => json = true,
+			Some("--auth") => {
+				let auth = args.next().and_then(|a| a.into_string().ok()).unwrap_or_else(|| usage());
+				harness.set_auth(Some(auth.as_bytes()));
+			},
+			Some("--timeout") => {
+				let timeout = args.next().and_then(|t| t.into_string().ok())
+					.and_then(|t| t.parse().ok()).unwrap_or_else(|| usage());
+				harness.set_timeout(Duration::from_secs(timeout));
+			},
+			_ if path.is_none() => path = Some(arg),
+			_ => usage()
+		}
+	}
+	let path = path.unwrap_or_else(|| usage());
+	
+	// Run the checks and print the report
+	let report = harness.run(&path);
+	match json {
+		true => println!("{}", report.to_json()),
+		false => println!("{}", report)
+	}
+	if !report.passed() {
+		process::exit(1)
+	}
+}
@@ -0,0 +1,68 @@
+//! A helper binary that loads a KyNc plugin and serves its API calls over stdin/stdout so that a
+//! crashing plugin only takes down this process (see `kync::RemotePlugin`)
+
+use kync::{ Plugin, remote };
+use std::{ env, io, process };
+
+
+/// Gets unbuffered handles to stdin and stdout so that no secrets linger in the std buffers
+#[cfg(unix)]
+fn stdio() -> (impl io::Read, impl io::Write) {
+	use std::{ fs::File, mem::ManuallyDrop, os::unix::io::FromRawFd };
+	
+	// The descriptors are owned by the process, so we must never close them
+	let stdin = ManuallyDrop::new(unsafe{ File::from_raw_fd(0) });
+	let stdout = ManuallyDrop::new(unsafe{ File::from_raw_fd(1) });
+	(Stdio(stdin), Stdio(stdout))
+}
+/// Gets handles to stdin and stdout
+///
+/// _Note: std buffers stdin and stdout internally, so this is only a fallback for platforms without
+/// raw file descriptors._
+#[cfg(not(unix))]
+fn stdio() -> (impl io::Read, impl io::Write) {
+	(io::stdin(), io::stdout())
+}
+
+
+/// A borrowed stdio file that is never closed
+#[cfg(unix)]
+struct Stdio(std::mem::ManuallyDrop<std::fs::File>);
+#[cfg(unix)]
+impl io::Read for Stdio {
+	fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+		self.0.read(buf)
+	}
+}
+#[cfg(unix)]
+impl io::Write for Stdio {
+	fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+		self.0.write(buf)
+	}
+	fn flush(&mut self) -> io::Result<()> {
+		self.0.flush()
+	}
+}
+
+
+fn main() {
+	// Get the plugin path
+	let path = match env::args_os().nth(1) {
+		Some(path) => path,
+		None => {
+			eprintln!("Usage: kync-host <plugin>");
+			process::exit(2)
+		}
+	};
+	
+	// Load the plugin and serve the calls; load errors are reported via the protocol
+	let (stdin, stdout) = stdio();
+	let result = match Plugin::load(&path) {
+		Ok(plugin) => remote::serve(&plugin, stdin, stdout),
+		Err(e) => remote::report(e, stdout)
+	};
+	if let Err(e) = result {
+		eprintln!("kync-host: {}", e);
+		process::exit(1)
+	}
+}
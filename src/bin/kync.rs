@@ -0,0 +1,446 @@
+//! A command-line tool to protect and recover secrets with KyNc plugins
+//!
+//! The authentication is read from an environment variable, a file descriptor or a TTY prompt but
+//! never from the command line.
+
+use kync::{ Capsule, KeyCapsule, Plugin, PluginRegistry, SecretBytes };
+use std::{
+	env, fs, process, error::Error, ffi::OsString, fs::{ File, OpenOptions },
+	io::{ self, Read, Write },
+	fmt::{ self, Display, Formatter },
+	path::{ Path, PathBuf },
+	process::{ Command, Stdio }
+};
+
+
+/// The usage text
+const USAGE: &str = "Usage:
+    kync info <plugin>
+    kync list [<plugin-dir>]
+    kync protect --plugin <plugin> [--config <config>] [--in <file>] [--out <file>]
+        [<auth-options>]
+    kync recover (--plugin <plugin> | --plugin-dir <dir>) [--in <file>] [--out <file>]
+        [<auth-options>]
+
+Authentication options (<auth-options>):
+    --auth-env <var>    Read the authentication from the environment variable <var>
+    --auth-fd <fd>      Read the authentication from the file descriptor <fd>
+    --auth-prompt       Prompt for the authentication on the TTY (the default if the plugin
+                        requires an authentication)
+
+If neither --plugin nor --plugin-dir is given, `recover` and `list` use the directories in
+KYNC_PLUGIN_PATH.";
+/// The options that take a value
+const VALUE_OPTIONS: &[&str] =
+	&["--plugin", "--plugin-dir", "--config", "--in", "--out", "--auth-env", "--auth-fd"];
+/// The options that don't take a value
+const FLAG_OPTIONS: &[&str] = &["--auth-prompt"];
+
+
+/// An invalid command line
+#[derive(Debug)]
+struct UsageError(String);
+impl Display for UsageError {
+	fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+		write!(f, "{}", self.0)
+	}
+}
+impl Error for UsageError {}
+
+
+/// The parsed command line
+struct Args {
+	positional: Vec<OsString>,
+	options: Vec<(&'static str, Option<OsString>)>
+}
+impl Args {
+	/// Parses the command line arguments (without the program name)
+	fn parse(args: impl IntoIterator<Item = OsString>) -> Result<Self, UsageError> {
+		let (mut positional, mut options) = (Vec::new(), Vec::new());
+		let mut args = args.into_iter();
+		while let Some(arg) = args.next() {
+			let name = arg.to_str().unwrap_or_default();
+			if let Some(option) = VALUE_OPTIONS.iter().find(|o| **o == name) {
+				let value = args.next()
+					.ok_or_else(|| UsageError(format!("Missing value for `{}`", option)))?;
+				options.push((*option, Some(value)));
+			} else if let Some(option) = FLAG_OPTIONS.iter().find(|o| **o == name) {
+				options.push((*option, None));
+			} else if name.starts_with("--") {
+				return Err(UsageError(format!("Unknown option `{}`", name)))
+			} else {
+				positional.push(arg);
+			}
+		}
+		Ok(Self { positional, options })
+	}
+	
+	/// Gets the value of the option `name`
+	fn value(&self, name: &str) -> Option<&OsString> {
+		self.options.iter().rev().find(|(n, _)| *n == name).and_then(|(_, v)| v.as_ref())
+	}
+	/// Checks if the flag `name` is set
+	fn flag(&self, name: &str) -> bool {
+		self.options.iter().any(|(n, _)| *n == name)
+	}
+	/// Gets the positional argument at `index` (the subcommand is at index `0`)
+	fn positional(&self, index: usize) -> Option<&OsString> {
+		self.positional.get(index)
+	}
+	/// Fails if there are more than `max` positional arguments (including the subcommand)
+	///
+	/// _Note: The error does not echo the argument since it may be a misplaced secret._
+	fn check_positionals(&self, max: usize) -> Result<(), UsageError> {
+		match self.positional.len() > max {
+			true => Err(UsageError("Unexpected argument (the authentication is never read from the \
+				command line)".to_string())),
+			false => Ok(())
+		}
+	}
+}
+
+
+/// A temporary file next to `path` that only replaces `path` once it is complete
+///
+/// The file is only accessible by the owner; if it is dropped before [`TempFile::persist`] is
+/// called, it is removed and `path` remains untouched.
+struct TempFile {
+	file: File,
+	temp: PathBuf,
+	path: PathBuf,
+	persisted: bool
+}
+impl TempFile {
+	/// Creates a new temporary file for `path`
+	fn create(path: &Path) -> io::Result<Self> {
+		let name = path.file_name()
+			.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Invalid output path"))?;
+		let mut attempt = 0;
+		loop {
+			// Create a new file with an unused name
+			let mut temp = OsString::from(".");
+			temp.push(name);
+			temp.push(format!(".{}-{}.tmp", process::id(), attempt));
+			let temp = path.with_file_name(temp);
+			
+			let mut options = OpenOptions::new();
+			options.write(true).create_new(true);
+			#[cfg(unix)]
+			std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+			let file = match options.open(&temp) {
+				Err(e) if e.kind() == io::ErrorKind::AlreadyExists && attempt < 16 => {
+					attempt += 1;
+					continue
+				},
+				result => result?
+			};
+			
+			// Don't rely on the creation mode since it is subject to the umask and the platform
+			let this = Self { file, temp, path: path.to_path_buf(), persisted: false };
+			#[cfg(unix)]
+			this.file.set_permissions(std::os::unix::fs::PermissionsExt::from_mode(0o600))?;
+			return Ok(this)
+		}
+	}
+	/// Flushes the file to disk and replaces `path` with it
+	fn persist(mut self) -> io::Result<()> {
+		self.file.flush()?;
+		self.file.sync_all()?;
+		fs::rename(&self.temp, &self.path)?;
+		self.persisted = true;
+		Ok(())
+	}
+}
+impl Write for TempFile {
+	fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+		self.file.write(buf)
+	}
+	fn flush(&mut self) -> io::Result<()> {
+		self.file.flush()
+	}
+}
+impl Drop for TempFile {
+	fn drop(&mut self) {
+		if !self.persisted {
+			let _ = fs::remove_file(&self.temp);
+		}
+	}
+}
+
+
+/// The output of a subcommand
+enum Output {
+	Stdout(io::Stdout),
+	File(TempFile)
+}
+impl Output {
+	/// Completes the output; a file is only created or replaced if this succeeds
+	fn finish(self) -> io::Result<()> {
+		match self {
+			Output::Stdout(mut stdout) => stdout.flush(),
+			Output::File(file) => file.persist()
+		}
+	}
+}
+impl Write for Output {
+	fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+		match self {
+			Output::Stdout(stdout) => stdout.write(buf),
+			Output::File(file) => file.write(buf)
+		}
+	}
+	fn flush(&mut self) -> io::Result<()> {
+		match self {
+			Output::Stdout(stdout) => stdout.flush(),
+			Output::File(file) => file.flush()
+		}
+	}
+}
+
+
+/// Reads everything from `source` into a zeroizing buffer
+fn read_secret(mut source: impl Read) -> io::Result<SecretBytes> {
+	let (mut secret, mut chunk) = (SecretBytes::new(), SecretBytes::from(vec![0; 4096]));
+	loop {
+		match source.read(&mut chunk) {
+			Ok(0) => return Ok(secret),
+			Ok(len) => secret.extend_from_slice(&chunk[..len]),
+			Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+			Err(e) => return Err(e)
+		}
+	}
+}
+/// Removes a single trailing line break from `secret`
+fn trim_line_break(secret: SecretBytes) -> SecretBytes {
+	let len = match secret.as_ref() {
+		[rest @ .., b'\r', b'\n'] | [rest @ .., b'\n'] => rest.len(),
+		secret => secret.len()
+	};
+	SecretBytes::from(&secret[..len])
+}
+
+
+/// Reads the authentication from the TTY without echoing it
+fn prompt(message: &str) -> Result<SecretBytes, Box<dyn Error>> {
+	let mut tty = OpenOptions::new().read(true).write(true).open("/dev/tty")?;
+	let stty = |arg: &str, tty: &File| -> io::Result<()> {
+		let status = Command::new("stty").arg(arg).stdin(Stdio::from(tty.try_clone()?)).status()?;
+		match status.success() {
+			true => Ok(()),
+			false => Err(io::Error::other("Failed to configure the TTY"))
+		}
+	};
+	
+	// Disable the echo, read a single line and restore the echo
+	write!(tty, "{}", message)?;
+	stty("-echo", &tty)?;
+	let (mut secret, mut byte) = (SecretBytes::new(), SecretBytes::from(vec![0; 1]));
+	let result = loop {
+		match tty.read(&mut byte) {
+			Ok(0) => break Ok(()),
+			Ok(_) if byte[0] == b'\n' => break Ok(()),
+			Ok(_) => secret.extend_from_slice(&byte),
+			Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+			Err(e) => break Err(e)
+		}
+	};
+	stty("echo", &tty)?;
+	writeln!(tty)?;
+	result?;
+	Ok(trim_line_break(secret))
+}
+/// Reads the authentication from the file descriptor `fd`
+#[cfg(unix)]
+fn read_fd(fd: &OsString) -> Result<SecretBytes, Box<dyn Error>> {
+	use std::os::unix::io::FromRawFd;
+	// Reject invalid descriptors and stdio which we must not consume and close
+	let fd = fd.to_str().and_then(|fd| fd.parse().ok()).filter(|fd| *fd > 2)
+		.ok_or_else(|| UsageError(format!("Invalid file descriptor {:?}", fd)))?;
+	let file = unsafe{ File::from_raw_fd(fd) };
+	Ok(trim_line_break(read_secret(file)?))
+}
+/// Reads the authentication from the file descriptor `fd`
+#[cfg(not(unix))]
+fn read_fd(_fd: &OsString) -> Result<SecretBytes, Box<dyn Error>> {
+	Err(UsageError("`--auth-fd` is not supported on this platform".to_string()))?
+}
+/// Reads the authentication from the environment variable `var`
+fn read_env(var: &OsString) -> Result<SecretBytes, Box<dyn Error>> {
+	let value = env::var_os(var)
+		.ok_or_else(|| UsageError(format!("The environment variable {:?} is not set", var)))?;
+	#[cfg(unix)]
+	let value = std::os::unix::ffi::OsStringExt::into_vec(value);
+	#[cfg(not(unix))]
+	let value = value.into_string()
+		.map_err(|_| UsageError(format!("The environment variable {:?} is not UTF-8", var)))?
+		.into_bytes();
+	Ok(SecretBytes::from(value))
+}
+/// Gets the authentication from the source selected in `args`
+///
+/// If no source is selected, the user is prompted if `required` is `true`.
+fn auth(args: &Args, required: bool) -> Result<Option<SecretBytes>, Box<dyn Error>> {
+	match (args.value("--auth-env"), args.value("--auth-fd")) {
+		(Some(var), _) => Ok(Some(read_env(var)?)),
+		(None, Some(fd)) => Ok(Some(read_fd(fd)?)),
+		_ if args.flag("--auth-prompt") || required => Ok(Some(prompt("Authentication: ")?)),
+		_ => Ok(None)
+	}
+}
+
+
+/// Opens the input selected in `args` (stdin by default)
+fn input(args: &Args) -> io::Result<Box<dyn Read>> {
+	match args.value("--in") {
+		Some(path) => Ok(Box::new(File::open(path)?)),
+		None => Ok(Box::new(io::stdin()))
+	}
+}
+/// Opens the output selected in `args` (stdout by default)
+fn output(args: &Args) -> io::Result<Output> {
+	match args.value("--out") {
+		Some(path) => Ok(Output::File(TempFile::create(path.as_ref())?)),
+		None => Ok(Output::Stdout(io::stdout()))
+	}
+}
+/// Loads the plugin selected with `--plugin`
+fn plugin(args: &Args) -> Result<Plugin, Box<dyn Error>> {
+	let path = args.value("--plugin").ok_or_else(|| UsageError("Missing `--plugin`".to_string()))?;
+	Ok(Plugin::load(path)?)
+}
+/// Creates a registry for the directory `dir` or the directories in `KYNC_PLUGIN_PATH`
+fn registry(dir: Option<&OsString>) -> PluginRegistry {
+	let mut registry = match dir {
+		Some(dir) => {
+			let mut registry = PluginRegistry::new();
+			registry.add_search_path(dir);
+			registry
+		},
+		None => PluginRegistry::from_env()
+	};
+	registry.scan();
+	registry
+}
+
+
+/// Describes the authentication requirements
+fn describe_auth((required, retries): (bool, u64)) -> String {
+	let required = if required { "auth required" } else { "no auth" };
+	match retries {
+		u64::MAX => format!("{}, no known retry limit", required),
+		retries => format!("{}, {} retries left", required, retries)
+	}
+}
+
+
+/// `kync info <plugin>`
+fn info(args: &Args) -> Result<(), Box<dyn Error>> {
+	args.check_positionals(2)?;
+	let path = args.positional(1).ok_or_else(|| UsageError("Missing plugin path".to_string()))?;
+	let plugin = Plugin::load(path)?;
+	
+	println!("ID: {}", String::from_utf8_lossy(&plugin.id()?));
+	println!("API version: {:#06x}", plugin.api_version());
+	println!("Configs:");
+	for config in plugin.configs()? {
+		let protect = describe_auth(plugin.auth_info_protect(&config)?);
+		let recover = describe_auth(plugin.auth_info_recover(&config)?);
+		println!("  {} (protect: {}; recover: {})", String::from_utf8_lossy(&config), protect,
+			recover);
+	}
+	Ok(())
+}
+/// `kync list [<plugin-dir>]`
+fn list(args: &Args) -> Result<(), Box<dyn Error>> {
+	args.check_positionals(2)?;
+	let registry = registry(args.positional(1));
+	for (id, path) in registry.list() {
+		println!("{}\t{}", String::from_utf8_lossy(id), path.display());
+	}
+	for (path, error) in registry.failures() {
+		eprintln!("warning: {}: {}", path.display(), error);
+	}
+	Ok(())
+}
+/// `kync protect`
+fn protect(args: &Args) -> Result<(), Box<dyn Error>> {
+	args.check_positionals(1)?;
+	let plugin = plugin(args)?;
+	let config = match args.value("--config") {
+		Some(config) => config.to_str().ok_or_else(|| UsageError("Invalid config".to_string()))?
+			.as_bytes().to_vec(),
+		None => plugin.configs()?.into_iter().next()
+			.ok_or_else(|| UsageError("The plugin has no configs".to_string()))?
+	};
+	
+	// Seal the secret
+	let (required, _) = plugin.auth_info_protect(&config)?;
+	let auth = auth(args, required)?;
+	let secret = read_secret(input(args)?)?;
+	let envelope = Capsule::seal(&plugin, &secret, &config, auth.as_deref())?;
+	
+	let mut output = output(args)?;
+	output.write_all(&envelope)?;
+	Ok(output.finish()?)
+}
+/// `kync recover`
+fn recover(args: &Args) -> Result<(), Box<dyn Error>> {
+	args.check_positionals(1)?;
+	let mut envelope = Vec::new();
+	input(args)?.read_to_end(&mut envelope)?;
+	let capsule = Capsule::parse(&envelope)?;
+	
+	// Find the plugin and recover the secret
+	let (plugin, registry);
+	let plugin: &Plugin = match args.value("--plugin") {
+		Some(_) => {
+			plugin = self::plugin(args)?;
+			capsule.find(Some(&plugin))?
+		},
+		None => {
+			registry = self::registry(args.value("--plugin-dir"));
+			registry.find_for_capsule(&envelope)?
+		}
+	};
+	let (required, _) = plugin.auth_info_recover(capsule.config())?;
+	let auth = auth(args, required)?;
+	
+	let mut output = output(args)?;
+	plugin.recover_to(&mut output, capsule.payload(), auth.as_deref())?;
+	Ok(output.finish()?)
+}
+
+
+fn main() {
+	let args = match Args::parse(env::args_os().skip(1)) {
+		Ok(args) => args,
+		Err(e) => {
+			eprintln!("kync: {}\n\n{}", e, USAGE);
+			process::exit(2)
+		}
+	};
+	let result = match args.positional(0).and_then(|c| c.to_str()) {
+		Some("info") => info(&args),
+		Some("list") => list(&args),
+		Some("protect") => protect(&args),
+		Some("recover") => recover(&args),
+		_ => Err(UsageError("Missing or unknown subcommand".to_string()).into())
+	};
+	
+	// Print the error and its causes
+	if let Err(e) = result {
+		eprintln!("kync: {}", e);
+		let mut source = e.source();
+		while let Some(cause) = source {
+			eprintln!("  caused by: {}", cause);
+			source = cause.source();
+		}
+		match e.is::<UsageError>() {
+			true => {
+				eprintln!("\n{}", USAGE);
+				process::exit(2)
+			},
+			false => process::exit(1)
+		}
+	}
+}
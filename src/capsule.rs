@@ -0,0 +1,137 @@
+use crate::{
+	KeyCapsule, KyncError, KyncErrorKind, SecretBytes,
+	plugin::{ API_VERSION, API_VERSION_V2 }
+};
+use std::convert::TryInto;
+
+
+/// The envelope magic bytes
+const MAGIC: &[u8; 4] = b"KYNC";
+/// The current envelope format version
+const FORMAT_VERSION: u8 = 0x01;
+
+
+/// An error message indicating an invalid envelope
+const ERR_FORMAT: &str = "Invalid or truncated capsule envelope";
+
+
+/// A self-describing capsule envelope that records the plugin ID, the config and the API version
+/// together with the plugin's payload
+///
+/// Format: `magic[4] || format_version[1] || api_version[2] || id || config || payload` where `id`,
+/// `config` and `payload` are prefixed with their length as 64 bit big endian integer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Capsule {
+	api_version: u16,
+	id: Vec<u8>,
+	config: Vec<u8>,
+	payload: Vec<u8>
+}
+impl Capsule {
+	/// Protects `data` with `capsule` and seals the result into an envelope that records the
+	/// negotiated API version
+	pub fn seal<K: KeyCapsule + ?Sized>(capsule: &K, data: &[u8], config: &[u8],
+		auth: Option<&[u8]>) -> Result<Vec<u8>, KyncError>
+	{
+		let payload = capsule.protect(data, config, auth)?;
+		let (api_version, id) = (capsule.api_version(), capsule.cached_id()?);
+		Ok(Self { api_version, id, config: config.to_vec(), payload }.to_bytes())
+	}
+	
+	/// Opens an `envelope` with the matching plugin from `capsules`
+	pub fn open<'a, K: KeyCapsule + ?Sized + 'a>(capsules: impl IntoIterator<Item = &'a K>,
+		envelope: &[u8], auth: Option<&[u8]>) -> Result<SecretBytes, KyncError>
+	{
+		let this = Self::parse(envelope)?;
+		this.find(capsules)?.recover(&this.payload, auth)
+	}
+	
+	/// Parses an envelope
+	pub fn parse(envelope: &[u8]) -> Result<Self, KyncError> {
+		// Validate the header
+		let (magic, envelope) = split(envelope, MAGIC.len())?;
+		let (format_version, envelope) = split(envelope, 1)?;
+		let (api_version, mut envelope) = split(envelope, 2)?;
+		if magic != MAGIC {
+			Err(format_error(ERR_FORMAT))?
+		}
+		if format_version != [FORMAT_VERSION] {
+			Err(format_error(format!("Unsupported capsule format version {}", format_version[0])))?
+		}
+		let api_version = u16::from_be_bytes(api_version.try_into().unwrap());
+		if ![API_VERSION >> 8, API_VERSION_V2 >> 8].contains(&(api_version >> 8)) {
+			Err(format_error(format!("Unsupported capsule API version {:#06x}", api_version)))?
+		}
+		
+		// Read the fields
+		let mut fields = Vec::with_capacity(3);
+		for _ in 0..3 {
+			let (len, rest) = split(envelope, 8)?;
+			let len = u64::from_be_bytes(len.try_into().unwrap());
+			let len = len.try_into().map_err(|_| format_error(ERR_FORMAT))?;
+			let (field, rest) = split(rest, len)?;
+			fields.push(field.to_vec());
+			envelope = rest;
+		}
+		if !envelope.is_empty() {
+			Err(format_error(ERR_FORMAT))?
+		}
+		
+		let (payload, config) = (fields.pop().unwrap(), fields.pop().unwrap());
+		Ok(Self { api_version, id: fields.pop().unwrap(), config, payload })
+	}
+	/// Serializes the envelope
+	pub fn to_bytes(&self) -> Vec<u8> {
+		let mut envelope = Vec::new();
+		envelope.extend_from_slice(MAGIC);
+		envelope.push(FORMAT_VERSION);
+		envelope.extend_from_slice(&self.api_version.to_be_bytes());
+		for field in [&self.id, &self.config, &self.payload].iter() {
+			envelope.extend_from_slice(&(field.len() as u64).to_be_bytes());
+			envelope.extend_from_slice(field);
+		}
+		envelope
+	}
+	
+	/// Selects the capsule implementation from `capsules` whose ID matches the stored plugin ID
+	pub fn find<'a, K: KeyCapsule + ?Sized + 'a>(&self, capsules: impl IntoIterator<Item = &'a K>)
+		-> Result<&'a K, KyncError>
+	{
+		// Plugins that fail to report their ID cannot be the right ones, so we skip them
+		let mut capsules = capsules.into_iter();
+		capsules.find(|c| c.cached_id().ok().as_ref() == Some(&self.id)).ok_or_else(|| {
+			let message = "No loaded plugin matches the capsule's plugin ID";
+			KyncError::new(KyncErrorKind::PluginNotFound, message).with_plugin_id(&self.id)
+		})
+	}
+	
+	/// The API version the capsule was created with
+	pub fn api_version(&self) -> u16 {
+		self.api_version
+	}
+	/// The ID of the plugin that created the capsule
+	pub fn id(&self) -> &[u8] {
+		&self.id
+	}
+	/// The config that was used to create the capsule
+	pub fn config(&self) -> &[u8] {
+		&self.config
+	}
+	/// The plugin specific payload
+	pub fn payload(&self) -> &[u8] {
+		&self.payload
+	}
+}
+
+
+/// Splits `len` bytes off `data` or fails with a format error if `data` is too short
+fn split(data: &[u8], len: usize) -> Result<(&[u8], &[u8]), KyncError> {
+	match data.len() >= len {
+		true => Ok(data.split_at(len)),
+		false => Err(format_error(ERR_FORMAT))
+	}
+}
+/// Creates a format error with `message`
+fn format_error(message: impl Into<String>) -> KyncError {
+	KyncError::new(KyncErrorKind::FormatError, message)
+}
@@ -0,0 +1,260 @@
+use crate::{
+	KeyCapsule, KyncError, KyncErrorKind, Plugin,
+	plugin::{ DEFAULT_TIMEOUT, Fault, Limits }
+};
+use std::{
+	fmt::{ self, Display, Formatter },
+	path::{ Path, PathBuf },
+	time::{ Duration, Instant }
+};
+
+
+/// An API version that no plugin is expected to support
+const UNKNOWN_API_VERSION: u16 = 0x7F_00;
+/// The secret that is used for the round-trips
+const TEST_SECRET: &[u8] = b"KyNc conformance test secret 8Xk2pQ7vN4rT9wL1";
+
+
+/// The result of a single conformance check
+#[derive(Debug, Clone)]
+pub struct Check {
+	/// The name of the check
+	pub name: &'static str,
+	/// Whether the check passed
+	pub passed: bool,
+	/// A human-readable description of the result
+	pub message: String,
+	/// How long the check took
+	pub duration: Duration,
+	/// Whether the check failed because a call exceeded the time limit
+	pub timed_out: bool
+}
+
+
+/// A conformance report for a plugin
+#[derive(Debug, Clone)]
+pub struct Report {
+	/// The path of the tested plugin
+	pub path: PathBuf,
+	/// The results of the individual checks in the order they were performed
+	pub checks: Vec<Check>
+}
+impl Report {
+	/// Whether all checks passed
+	pub fn passed(&self) -> bool {
+		self.checks.iter().all(|c| c.passed)
+	}
+	
+	/// Serializes the report as JSON
+	pub fn to_json(&self) -> String {
+		let checks: Vec<String> = self.checks.iter().map(|c| format!(
+			r#"{{"name":{},"passed":{},"message":{},"duration_ms":{}}}"#,
+			json_string(c.name), c.passed, json_string(&c.message), c.duration.as_millis()
+		)).collect();
+		format!(r#"{{"plugin":{},"passed":{},"checks":[{}]}}"#,
+			json_string(&self.path.to_string_lossy()), self.passed(), checks.join(","))
+	}
+}
+impl Display for Report {
+	fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+		writeln!(f, "Conformance report for \"{}\"", self.path.display())?;
+		for check in self.checks.iter() {
+			let status = if check.passed { "PASS" } else { "FAIL" };
+			write!(f, "  [{}] {} ({} ms)", status, check.name, check.duration.as_millis())?;
+			match check.message.is_empty() {
+				true => writeln!(f)?,
+				false => writeln!(f, ": {}", check.message)?
+			}
+		}
+		let passed = self.checks.iter().filter(|c| c.passed).count();
+		write!(f, "{} of {} checks passed", passed, self.checks.len())
+	}
+}
+
+
+/// A conformance test harness that checks if a plugin follows "Kync.asciidoc"
+///
+/// _Note: The plugin is loaded into the current process; a plugin that crashes takes the harness
+/// down with it._
+#[derive(Debug, Clone)]
+pub struct Harness {
+	auth: Option<Vec<u8>>,
+	timeout: Duration
+}
+impl Harness {
+	/// Creates a new harness without authentication and with the specification's time limit
+	pub fn new() -> Self {
+		Self { auth: None, timeout: DEFAULT_TIMEOUT }
+	}
+	
+	/// Sets the authentication that is used for the round-trips
+	pub fn set_auth(&mut self, auth: Option<&[u8]>) -> &mut Self {
+		self.auth = auth.map(|a| a.to_vec());
+		self
+	}
+	/// Sets the time limit for every call
+	pub fn set_timeout(&mut self, timeout: Duration) -> &mut Self {
+		self.timeout = timeout;
+		self
+	}
+	
+	/// Loads the plugin at `path` and runs all checks
+	pub fn run(&self, path: impl AsRef<Path>) -> Report {
+		let path = path.as_ref();
+		let mut checks = Vec::new();
+		
+		// Load the plugin in strict mode
+		let plugin = check(&mut checks, "load", || {
+			let mut plugin = Plugin::load(path)?;
+			plugin.set_timeout(Some(self.timeout)).set_strict(true).set_limits(Limits::default());
+			let message = format!("API version {:#06x}", plugin.api_version());
+			Ok((plugin, message))
+		});
+		
+		if let Some(plugin) = plugin {
+			self.run_checks(&plugin, &mut checks);
+		}
+		
+		// Summarize the time limit (a check may consist of multiple calls, so we rely on the
+		// per-call timeouts instead of the check durations)
+		let exceeded = checks.iter().filter(|c| c.timed_out).count();
+		check(&mut checks, "time_limit", || match exceeded {
+			0 => Ok(((), String::new())),
+			_ => Err(violation(format!("{} check(s) exceeded the time limit of {:?} per call",
+				exceeded, self.timeout)))
+		});
+		Report { path: path.into(), checks }
+	}
+	/// Runs the checks that require a loaded plugin
+	fn run_checks(&self, plugin: &Plugin, checks: &mut Vec<Check>) {
+		let auth = self.auth.as_deref();
+		
+		check(checks, "init_rejects_unknown_api", || {
+			let result = plugin.reinit(UNKNOWN_API_VERSION);
+			plugin.reinit(plugin.api_version())?;
+			match result {
+				Err(e) if e.kind() == KyncErrorKind::InitError => Ok(((), e.message().to_string())),
+				Err(e) => Err(e.into()),
+				Ok(_) => Err(violation(format!("`init` accepted the unknown API version {:#06x}",
+					UNKNOWN_API_VERSION)))
+			}
+		});
+		check(checks, "id_single_write", || {
+			let id = plugin.id()?;
+			Ok(((), format!("{:?}", String::from_utf8_lossy(&id))))
+		});
+		let configs = check(checks, "configs_non_empty", || {
+			let configs = plugin.configs()?;
+			let message = format!("{} config(s)", configs.len());
+			Ok((configs, message))
+		});
+		let configs = match configs {
+			Some(configs) => configs,
+			None => return
+		};
+		
+		check(checks, "auth_info", || {
+			for config in configs.iter() {
+				plugin.auth_info_protect(config)?;
+				plugin.auth_info_recover(config)?;
+			}
+			Ok(((), String::new()))
+		});
+		check(checks, "roundtrip", || {
+			for config in configs.iter() {
+				let protected = plugin.protect(TEST_SECRET, config, auth)?;
+				if plugin.recover(&protected, auth)?.as_ref() != TEST_SECRET {
+					Err(violation(format!("The recovered secret for config {:?} does not match",
+						String::from_utf8_lossy(config))))?
+				}
+			}
+			Ok(((), String::new()))
+		});
+		check(checks, "sink_errors_propagated", || {
+			// Inject a fault into the first `write`-call and check that the exact callback error is
+			// returned
+			let fault = Fault::AfterSegments(0);
+			let protected = plugin.protect(TEST_SECRET, &configs[0], auth)?;
+			if !plugin.protect_with_fault(fault, TEST_SECRET, &configs[0], auth)? {
+				Err(violation("`protect` did not write anything"))?
+			}
+			if !plugin.recover_with_fault(fault, &protected, auth)? {
+				Err(violation("`recover` did not write anything"))?
+			}
+			Ok(((), String::new()))
+		});
+		check(checks, "null_auth", || {
+			// Any result but a timeout is fine as long as the plugin does not crash
+			let protected = plugin.protect(TEST_SECRET, &configs[0], None);
+			let recovered = match protected.as_ref() {
+				Ok(protected) => plugin.recover(protected, None).map(|_| ()),
+				Err(_) => Ok(())
+			};
+			let mut errors = protected.err().into_iter().chain(recovered.err());
+			match errors.find(|e| e.kind() == KyncErrorKind::Timeout) {
+				Some(e) => Err(e.into()),
+				None => Ok(((), String::new()))
+			}
+		});
+	}
+}
+impl Default for Harness {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+
+/// The reason why a check failed and whether it was caused by a timeout
+struct Failure(String, bool);
+impl From<KyncError> for Failure {
+	fn from(error: KyncError) -> Self {
+		Self(error.to_string(), error.kind() == KyncErrorKind::Timeout)
+	}
+}
+
+
+/// Runs the check `name` and records the result in `checks`
+///
+/// `f` returns a value for subsequent checks together with a message describing the result.
+fn check<T>(checks: &mut Vec<Check>, name: &'static str,
+	f: impl FnOnce() -> Result<(T, String), Failure>) -> Option<T>
+{
+	let start = Instant::now();
+	let result = f();
+	let duration = start.elapsed();
+	match result {
+		Ok((value, message)) => {
+			checks.push(Check { name, passed: true, message, duration, timed_out: false });
+			Some(value)
+		},
+		Err(Failure(message, timed_out)) => {
+			checks.push(Check { name, passed: false, message, duration, timed_out });
+			None
+		}
+	}
+}
+/// Creates a failure for a specification violation
+fn violation(message: impl Into<String>) -> Failure {
+	Failure(message.into(), false)
+}
+
+
+/// Encodes `s` as JSON string
+fn json_string(s: &str) -> String {
+	let mut json = String::with_capacity(s.len() + 2);
+	json.push('"');
+	for c in s.chars() {
+		match c {
+			'"' => json.push_str("\\\""),
+			'\\' => json.push_str("\\\\"),
+			'\n' => json.push_str("\\n"),
+			'\r' => json.push_str("\\r"),
+			'\t' => json.push_str("\\t"),
+			c if (c as u32) < 0x20 => json.push_str(&format!("\\u{:04x}", c as u32)),
+			c => json.push(c)
+		}
+	}
+	json.push('"');
+	json
+}
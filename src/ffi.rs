@@ -1,7 +1,7 @@
 #![allow(non_camel_case_types)]
-use crate::{ KyncError, KyncErrorKind };
+use crate::{ KyncError, KyncErrorKind, SecretBytes, plugin::{ Fault, Limits } };
 use std::{
-	ptr, slice, ffi::CStr, marker::PhantomData,
+	mem, ptr, slice, ffi::CStr, marker::PhantomData,
 	os::raw::{ c_char, c_void }
 };
 
@@ -15,13 +15,14 @@ impl StaticCharPtrExt for *const c_char {
 	fn check(self, k: KyncErrorKind) -> Result<(), KyncError> {
 		match self.is_null() {
 			true => Ok(()),
-			false => Err(KyncError(k, unsafe{ CStr::from_ptr(self) }))
+			false => Err(KyncError::new(k, unsafe{ CStr::from_ptr(self) }.to_string_lossy()))
 		}
 	}
 }
 
 
 /// The sys bindings
+#[allow(dead_code)]
 pub mod sys {
 	include!("sys.rs");
 }
@@ -42,43 +43,237 @@ impl<'a> From<&'a[u8]> for Slice<'a> {
 }
 
 
-/// An idiomatic wrapper around `sys::write_t`
+/// The error message that is passed to the plugin if an output sink fails
+pub const ERR_SINK: &str = "Failed to write to the output sink";
+/// The error message if the plugin ignored a failed write to an output sink
+pub const ERR_SINK_IGNORED: &str = "The plugin ignored a failed write to the output sink";
+/// `ERR_SINK` as static C string
+const ERR_SINK_C: &[u8] = b"Failed to write to the output sink\0";
+/// The error message that is passed to the plugin if a `Fault` is injected
+const ERR_FAULT_C: &[u8] = b"Injected sink failure\0";
+/// The error message that is passed to the plugin if it exceeds an output limit
+const ERR_LIMIT_C: &[u8] = b"The output limit has been exceeded\0";
+/// The error message that is passed to the plugin if the output cannot be buffered
+const ERR_ALLOC_C: &[u8] = b"Failed to allocate secret memory\0";
+
+
+/// Tracks the output of a call against some `Limits`
+pub struct Budget {
+	limits: Limits,
+	bytes: usize,
+	segments: usize,
+	exceeded: Option<String>
+}
+impl Budget {
+	/// Creates a new budget for `limits`
+	pub fn new(limits: Limits) -> Self {
+		Self { limits, bytes: 0, segments: 0, exceeded: None }
+	}
+	
+	/// Accounts for a segment with `len` bytes and returns `false` if a limit has been exceeded
+	fn consume(&mut self, len: usize) -> bool {
+		if self.exceeded.is_none() {
+			self.bytes = self.bytes.saturating_add(len);
+			self.segments += 1;
+			if self.segments > self.limits.max_segments {
+				let message = format!("The plugin exceeded the limit of {} segments",
+					self.limits.max_segments);
+				self.exceeded = Some(message);
+			} else if self.bytes > self.limits.max_bytes {
+				let message = format!("The plugin exceeded the limit of {} bytes",
+					self.limits.max_bytes);
+				self.exceeded = Some(message);
+			}
+		}
+		self.exceeded.is_none()
+	}
+	/// Returns a `KyncErrorKind::OutputLimitExceeded` error if a limit has been exceeded or
+	/// `result` otherwise
+	pub fn check<T>(&self, result: Result<T, KyncError>) -> Result<T, KyncError> {
+		match self.exceeded.as_ref() {
+			Some(message) => Err(KyncError::new(KyncErrorKind::OutputLimitExceeded, message.as_str())),
+			None => result
+		}
+	}
+}
+
+
+/// A segment callback that returns `false` on error
+type Callback<'a> = &'a mut dyn FnMut(&[u8]) -> bool;
+/// The state behind a `StreamWriter`'s handle
+struct StreamState<'a> {
+	callback: Callback<'a>,
+	budget: Budget
+}
+
+
+/// A `sys::write_t` that passes every segment to a callback (e.g. to stream it into an `io::Write`)
+///
+/// If the callback returns `false`, the plugin gets `ERR_SINK` as callback error.
+pub struct StreamWriter<'a> {
+	write_t: sys::write_t,
+	state: Box<StreamState<'a>>
+}
+impl<'a> StreamWriter<'a> {
+	/// Creates a new writer that passes every segment to `callback` as long as `limits` are met
+	pub fn new(limits: Limits, callback: Callback<'a>) -> Self {
+		let mut state = Box::new(StreamState { callback, budget: Budget::new(limits) });
+		let handle: *mut StreamState<'a> = state.as_mut();
+		let write_t = sys::write_t{ handle: handle.cast(), write: Some(Self::write) };
+		Self { write_t, state }
+	}
+	/// A pointer to the underlying `sys::write_t`
+	pub fn write_t(&mut self) -> &mut sys::write_t {
+		&mut self.write_t
+	}
+	/// Returns an error if a limit has been exceeded or `result` otherwise
+	pub fn check<T>(&self, result: Result<T, KyncError>) -> Result<T, KyncError> {
+		self.state.budget.check(result)
+	}
+	
+	/// The write implementation
+	extern "C" fn write(handle: *mut c_void, data: *const sys::slice_t) -> *const c_char {
+		// Cast and deref the pointers
+		let state = unsafe{ handle.cast::<StreamState>().as_mut() }
+			.expect("Unexpected NULL pointer");
+		let data = unsafe{ data.as_ref() }
+			.expect("Unexpected NULL pointer");
+		
+		// Pass the slice to the callback
+		if !state.budget.consume(data.len) {
+			return ERR_LIMIT_C.as_ptr().cast()
+		}
+		match (state.callback)(unsafe{ slice::from_raw_parts(data.ptr, data.len) }) {
+			true => ptr::null(),
+			false => ERR_SINK_C.as_ptr().cast()
+		}
+	}
+}
+
+
+/// The state behind a `Writer`'s handle
+///
+/// All segments are stored back to back in a single buffer (so that many small segments don't need
+/// a locked region each) together with the offsets where each segment ends.
+struct WriterState {
+	data: SecretBytes,
+	ends: Vec<usize>,
+	budget: Budget,
+	fault: Option<Fault>,
+	fault_injected: bool
+}
+impl WriterState {
+	/// Checks if the next segment with `len` bytes triggers the fault
+	fn inject_fault(&mut self, len: usize) -> bool {
+		self.fault_injected |= match self.fault {
+			Some(Fault::AfterBytes(max)) => self.data.len().saturating_add(len) > max,
+			Some(Fault::AfterSegments(max)) => self.ends.len() >= max,
+			None => false
+		};
+		self.fault_injected
+	}
+}
+
+
+/// An idiomatic wrapper around `sys::write_t` that collects the segments in a zeroizing buffer
 pub struct Writer(sys::write_t);
 impl Writer {
-	/// Creates a new empty writer
-	pub fn new() -> Self {
-		let handle = Box::new(vec![vec![0u8; 0]; 0]);
-		Self(sys::write_t{ handle: Box::into_raw(handle).cast(), write: Some(Self::write) })
+	/// Creates a new empty writer that accepts segments as long as `limits` are met
+	pub fn new(limits: Limits) -> Self {
+		Self::with_state(WriterState {
+			data: SecretBytes::new(), ends: Vec::new(), budget: Budget::new(limits), fault: None,
+			fault_injected: false
+		})
+	}
+	/// Creates a new empty writer that fails with `fault_error` once `fault` is reached
+	pub fn with_fault(limits: Limits, fault: Fault) -> Self {
+		Self::with_state(WriterState {
+			data: SecretBytes::new(), ends: Vec::new(), budget: Budget::new(limits),
+			fault: Some(fault), fault_injected: false
+		})
+	}
+	/// Creates a new writer with `state`
+	fn with_state(state: WriterState) -> Self {
+		let handle = Box::into_raw(Box::new(state));
+		Self(sys::write_t{ handle: handle.cast(), write: Some(Self::write) })
 	}
 	/// A pointer to the underlying `sys::write_t`
 	pub fn write_t(&mut self) -> &mut sys::write_t {
 		&mut self.0
 	}
+	/// Returns an error if a limit has been exceeded or `result` otherwise
+	pub fn check<T>(&self, result: Result<T, KyncError>) -> Result<T, KyncError> {
+		self.state().budget.check(result)
+	}
+	/// Whether the fault has been injected
+	pub fn fault_injected(&self) -> bool {
+		self.state().fault_injected
+	}
+	/// The callback error that is returned if the fault is injected
+	pub fn fault_error(&self) -> *const c_char {
+		ERR_FAULT_C.as_ptr().cast()
+	}
+	/// The state behind the handle
+	fn state(&self) -> &WriterState {
+		unsafe{ self.0.handle.cast::<WriterState>().as_ref() }.expect("Unexpected NULL pointer")
+	}
 	
 	/// The write implementation
 	extern "C" fn write(handle: *mut c_void, data: *const sys::slice_t) -> *const c_char {
 		// Cast and deref the pointers
-		let handle = unsafe{ handle.cast::<Vec<Vec<u8>>>().as_mut() }
+		let state = unsafe{ handle.cast::<WriterState>().as_mut() }
 			.expect("Unexpected NULL pointer");
 		let data = unsafe{ data.as_ref() }
 			.expect("Unexpected NULL pointer");
 		
 		// Append the slice
+		if state.inject_fault(data.len) {
+			return ERR_FAULT_C.as_ptr().cast()
+		}
+		if !state.budget.consume(data.len) {
+			return ERR_LIMIT_C.as_ptr().cast()
+		}
 		let data = unsafe{ slice::from_raw_parts(data.ptr, data.len) };
-		handle.push(data.to_vec());
+		if state.data.try_extend_from_slice(data).is_err() {
+			return ERR_ALLOC_C.as_ptr().cast()
+		}
+		state.ends.push(state.data.len());
 		ptr::null()
 	}
+	
+	/// Takes the collected data and segment ends and leaves an invalidated writer
+	fn take(&mut self) -> (SecretBytes, Vec<usize>) {
+		let handle = mem::replace(&mut self.0.handle, ptr::null_mut());
+		assert!(!handle.is_null(), "Unexpected NULL pointer");
+		let write = Self::write as extern "C" fn(_, _) -> _;
+		let compatible = self.0.write.is_some_and(|w| ptr::fn_addr_eq(w, write));
+		assert!(compatible, "Incompatible implementation");
+		let state = unsafe{ Box::from_raw(handle.cast::<WriterState>()) };
+		(state.data, state.ends)
+	}
+}
+impl Drop for Writer {
+	fn drop(&mut self) {
+		// Free (and thus zero) the segments if the writer has not been converted
+		if !self.0.handle.is_null() {
+			self.take();
+		}
+	}
 }
 impl From<Writer> for Vec<Vec<u8>> {
-	fn from(writer: Writer) -> Self {
-		assert!(!writer.0.handle.is_null(), "Unexpected NULL pointer");
-		assert!(writer.0.write == Some(Writer::write), "Incompatible implementation");
-		*unsafe{ Box::from_raw(writer.0.handle.cast::<Vec<Vec<u8>>>()) }
+	fn from(mut writer: Writer) -> Self {
+		let (data, ends) = writer.take();
+		let starts = Some(0).into_iter().chain(ends.iter().copied());
+		starts.zip(ends.iter()).map(|(start, end)| data[start..*end].to_vec()).collect()
 	}
 }
 impl From<Writer> for Vec<u8> {
-	fn from(writer: Writer) -> Self {
-		let vecs: Vec<Vec<u8>> = writer.into();
-		vecs.into_iter().flatten().collect()
+	fn from(mut writer: Writer) -> Self {
+		writer.take().0.to_vec()
 	}
-}
\ No newline at end of file
+}
+impl From<Writer> for SecretBytes {
+	fn from(mut writer: Writer) -> Self {
+		writer.take().0
+	}
+}
@@ -3,19 +3,38 @@
 
 /// Some FFI helpers
 mod ffi;
+/// Allocations for secret memory
+mod memory;
 /// A key capsule plugin (see "Kync.asciidoc" for further API documentation) and some helpers
 pub mod plugin;
+/// A self-describing capsule envelope
+pub mod capsule;
+/// Multi-recipient capsule envelopes
+pub mod multi;
+/// A plugin registry that loads plugins from search paths
+pub mod registry;
+/// An out-of-process plugin host and client
+pub mod remote;
+/// A zeroizing buffer for secret data
+pub mod secret;
+/// A conformance test harness for plugins
+pub mod conformance;
 
 use std::{
-	io, error::Error, ffi::CStr, os::raw::c_char,
-	fmt::{ self, Display, Formatter }
+	error::Error,
+	io::{ self, Write },
+	fmt::{ self, Display, Formatter },
+	path::{ Path, PathBuf }
+};
+use crate::ffi::sys;
+pub use crate::{
+	capsule::Capsule, multi::MultiCapsule, plugin::Plugin, registry::PluginRegistry,
+	remote::RemotePlugin, secret::SecretBytes
 };
-use crate::ffi::StaticCharPtrExt;
-pub use plugin::Plugin;
 
 
 /// A plugin error kind
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum KyncErrorKind {
 	/// Failed to load the library
 	LoadingError,
@@ -32,20 +51,251 @@ pub enum KyncErrorKind {
 	/// The `protect`-call failed
 	ProtectError,
 	/// The `recover`-call failed
-	RecoverError
+	RecoverError,
+	/// The capsule envelope is invalid or unsupported
+	FormatError,
+	/// No plugin matches the capsule's plugin ID
+	PluginNotFound,
+	/// The out-of-process plugin host failed or died
+	HostError,
+	/// The call exceeded its deadline
+	Timeout,
+	/// The worker thread of the call died
+	WorkerDied,
+	/// The plugin rejects all calls because a previous call has been abandoned
+	Poisoned,
+	/// The plugin exceeded an output limit
+	OutputLimitExceeded
+}
+impl Display for KyncErrorKind {
+	fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+		let description = match self {
+			KyncErrorKind::LoadingError => "Failed to load the plugin",
+			KyncErrorKind::InitError => "Failed to initialize the plugin",
+			KyncErrorKind::IdError => "Failed to query the plugin ID",
+			KyncErrorKind::ConfigsError => "Failed to query the plugin configs",
+			KyncErrorKind::AuthInfoError => "Failed to query the authentication requirements",
+			KyncErrorKind::SetContextError => "Failed to set the context",
+			KyncErrorKind::ProtectError => "Failed to protect the secret",
+			KyncErrorKind::RecoverError => "Failed to recover the secret",
+			KyncErrorKind::FormatError => "Invalid capsule",
+			KyncErrorKind::PluginNotFound => "No matching plugin",
+			KyncErrorKind::HostError => "The plugin host failed",
+			KyncErrorKind::Timeout => "The call timed out",
+			KyncErrorKind::WorkerDied => "The worker thread died",
+			KyncErrorKind::Poisoned => "The plugin is unusable after an abandoned call",
+			KyncErrorKind::OutputLimitExceeded => "The plugin exceeded an output limit"
+		};
+		f.write_str(description)
+	}
 }
+
+
+/// A machine-readable error code reported by plugins that implement API v2
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorCode {
+	/// An unknown error
+	Unknown,
+	/// An authentication is required
+	AuthRequired,
+	/// The authentication failed (e.g. a wrong PIN)
+	AuthFailed,
+	/// There are no authentication retries left
+	RetriesExhausted,
+	/// The config is invalid or unsupported
+	InvalidConfig,
+	/// The capsule is invalid or corrupt
+	InvalidCapsule,
+	/// The device or token is not available
+	DeviceUnavailable,
+	/// The operation has been cancelled (e.g. by the user)
+	Cancelled,
+	/// An internal plugin error
+	Internal
+}
+impl ErrorCode {
+	/// Converts a raw `KYNC_ERROR_*` code into an error code (`None` if the code is unknown)
+	pub fn from_raw(code: u8) -> Option<Self> {
+		match code as u32 {
+			sys::KYNC_ERROR_UNKNOWN => Some(ErrorCode::Unknown),
+			sys::KYNC_ERROR_AUTH_REQUIRED => Some(ErrorCode::AuthRequired),
+			sys::KYNC_ERROR_AUTH_FAILED => Some(ErrorCode::AuthFailed),
+			sys::KYNC_ERROR_RETRIES_EXHAUSTED => Some(ErrorCode::RetriesExhausted),
+			sys::KYNC_ERROR_INVALID_CONFIG => Some(ErrorCode::InvalidConfig),
+			sys::KYNC_ERROR_INVALID_CAPSULE => Some(ErrorCode::InvalidCapsule),
+			sys::KYNC_ERROR_DEVICE_UNAVAILABLE => Some(ErrorCode::DeviceUnavailable),
+			sys::KYNC_ERROR_CANCELLED => Some(ErrorCode::Cancelled),
+			sys::KYNC_ERROR_INTERNAL => Some(ErrorCode::Internal),
+			_ => None
+		}
+	}
+	/// The raw `KYNC_ERROR_*` code
+	pub fn to_raw(self) -> u8 {
+		let code = match self {
+			ErrorCode::Unknown => sys::KYNC_ERROR_UNKNOWN,
+			ErrorCode::AuthRequired => sys::KYNC_ERROR_AUTH_REQUIRED,
+			ErrorCode::AuthFailed => sys::KYNC_ERROR_AUTH_FAILED,
+			ErrorCode::RetriesExhausted => sys::KYNC_ERROR_RETRIES_EXHAUSTED,
+			ErrorCode::InvalidConfig => sys::KYNC_ERROR_INVALID_CONFIG,
+			ErrorCode::InvalidCapsule => sys::KYNC_ERROR_INVALID_CAPSULE,
+			ErrorCode::DeviceUnavailable => sys::KYNC_ERROR_DEVICE_UNAVAILABLE,
+			ErrorCode::Cancelled => sys::KYNC_ERROR_CANCELLED,
+			ErrorCode::Internal => sys::KYNC_ERROR_INTERNAL
+		};
+		code as u8
+	}
+}
+
+
 /// A KyNc error
 #[derive(Debug)]
-pub struct KyncError(KyncErrorKind, &'static CStr);
+pub struct KyncError {
+	kind: KyncErrorKind,
+	code: Option<ErrorCode>,
+	message: String,
+	path: Option<PathBuf>,
+	id: Option<Vec<u8>>,
+	source: Option<Box<dyn Error + Send + Sync + 'static>>
+}
+impl KyncError {
+	/// Creates a new error with a `message` (useful for in-process backends)
+	pub fn new(kind: KyncErrorKind, message: impl Into<String>) -> Self {
+		Self { kind, code: None, message: message.into(), path: None, id: None, source: None }
+	}
+	/// Sets the machine-readable error code
+	pub fn with_code(mut self, code: ErrorCode) -> Self {
+		self.code = Some(code);
+		self
+	}
+	/// Sets the underlying cause
+	pub fn with_source(mut self, source: impl Into<Box<dyn Error + Send + Sync + 'static>>)
+		-> Self
+	{
+		self.source = Some(source.into());
+		self
+	}
+	/// Sets the path of the plugin that caused the error if it has not been set yet
+	pub fn with_path(mut self, path: impl AsRef<Path>) -> Self {
+		self.path = self.path.or_else(|| Some(path.as_ref().to_path_buf()));
+		self
+	}
+	/// Sets the ID of the plugin that caused the error if it has not been set yet
+	pub fn with_plugin_id(mut self, id: impl AsRef<[u8]>) -> Self {
+		self.id = self.id.or_else(|| Some(id.as_ref().to_vec()));
+		self
+	}
+	
+	/// The error kind
+	pub fn kind(&self) -> KyncErrorKind {
+		self.kind
+	}
+	/// The machine-readable error code if the plugin reported one (requires API v2)
+	pub fn code(&self) -> Option<ErrorCode> {
+		self.code
+	}
+	/// The error message (e.g. the error description returned by the plugin)
+	pub fn message(&self) -> &str {
+		&self.message
+	}
+	/// The path of the plugin that caused the error if any
+	pub fn path(&self) -> Option<&Path> {
+		self.path.as_deref()
+	}
+	/// The ID of the plugin that caused the error if any
+	pub fn plugin_id(&self) -> Option<&[u8]> {
+		self.id.as_deref()
+	}
+}
 impl From<io::Error> for KyncError {
-	fn from(_: io::Error) -> Self {
-		const DESC: *const c_char = b"Failed to load library\0".as_ptr().cast();
-		DESC.check(KyncErrorKind::LoadingError).unwrap_err()
+	fn from(error: io::Error) -> Self {
+		let message = format!("Failed to load library ({})", error);
+		Self::new(KyncErrorKind::LoadingError, message).with_source(error)
 	}
 }
 impl Display for KyncError {
 	fn fmt(&self, f: &mut Formatter) -> fmt::Result {
-		write!(f, "{:?}", self)
+		write!(f, "{}: {}", self.kind, self.message)?;
+		match (&self.id, &self.path) {
+			(Some(id), Some(path)) => {
+				let id = String::from_utf8_lossy(id);
+				write!(f, " (plugin \"{}\" at \"{}\")", id, path.display())
+			},
+			(Some(id), None) => write!(f, " (plugin \"{}\")", String::from_utf8_lossy(id)),
+			(None, Some(path)) => write!(f, " (plugin at \"{}\")", path.display()),
+			(None, None) => Ok(())
+		}
 	}
 }
-impl Error for KyncError {}
\ No newline at end of file
+impl Error for KyncError {
+	fn source(&self) -> Option<&(dyn Error + 'static)> {
+		self.source.as_ref().map(|s| s.as_ref() as &(dyn Error + 'static))
+	}
+}
+
+
+/// A key capsule implementation (see "Kync.asciidoc" for further API documentation)
+///
+/// This trait is implemented by `Plugin` and can be implemented by in-process backends so that they
+/// can be used interchangeably
+pub trait KeyCapsule {
+	/// The plugin/format ID
+	fn id(&self) -> Result<Vec<u8>, KyncError>;
+	/// The plugin/format ID as cached by the implementation (e.g. when it was loaded)
+	///
+	/// The default implementation calls `id`.
+	fn cached_id(&self) -> Result<Vec<u8>, KyncError> {
+		self.id()
+	}
+	/// The API version that is used to talk to the implementation
+	///
+	/// The default implementation returns the v1 API version.
+	fn api_version(&self) -> u16 {
+		plugin::API_VERSION
+	}
+	
+	/// All possible configs
+	fn configs(&self) -> Result<Vec<Vec<u8>>, KyncError>;
+	
+	/// Sets an optional application specific context if supported (useful to assign better names
+	/// etc.)
+	fn set_context(&self, context: &[u8]) -> Result<(), KyncError>;
+	
+	/// Checks if an authentication is required to protect a secret and gets the number of retries
+	/// left
+	fn auth_info_protect(&self, config: &[u8]) -> Result<(bool, u64), KyncError>;
+	
+	/// Checks if an authentication is required to recover a secret and gets the number of retries
+	/// left
+	fn auth_info_recover(&self, config: &[u8]) -> Result<(bool, u64), KyncError>;
+	
+	/// Protects `data`
+	fn protect(&self, data: &[u8], config: &[u8], auth: Option<&[u8]>)
+		-> Result<Vec<u8>, KyncError>;
+	
+	/// Recovers some protected `data` into a zeroizing buffer
+	fn recover(&self, data: &[u8], auth: Option<&[u8]>) -> Result<SecretBytes, KyncError>;
+	
+	/// Protects `data` and streams the result into `sink`
+	///
+	/// The default implementation buffers the result of `protect`; if `sink` fails, the error keeps
+	/// the `io::Error` as source.
+	fn protect_to(&self, sink: &mut dyn Write, data: &[u8], config: &[u8], auth: Option<&[u8]>)
+		-> Result<(), KyncError>
+	{
+		let protected = self.protect(data, config, auth)?;
+		sink.write_all(&protected)
+			.map_err(|e| KyncError::new(KyncErrorKind::ProtectError, ffi::ERR_SINK).with_source(e))
+	}
+	
+	/// Recovers some protected `data` and streams the secret into `sink`
+	///
+	/// The default implementation buffers the result of `recover`; if `sink` fails, the error keeps
+	/// the `io::Error` as source.
+	fn recover_to(&self, sink: &mut dyn Write, data: &[u8], auth: Option<&[u8]>)
+		-> Result<(), KyncError>
+	{
+		let recovered = self.recover(data, auth)?;
+		sink.write_all(&recovered)
+			.map_err(|e| KyncError::new(KyncErrorKind::RecoverError, ffi::ERR_SINK).with_source(e))
+	}
+}
\ No newline at end of file
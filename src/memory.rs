@@ -0,0 +1,153 @@
+use std::{
+	io,
+	ptr::{ self, NonNull },
+	sync::atomic::{ self, Ordering }
+};
+
+
+/// Overwrites `len` bytes at `ptr` with zeroes in a way that is not optimized away
+///
+/// _Note: `ptr` must be valid for `len` byte writes; the bytes don't need to be initialized._
+pub unsafe fn zeroize(ptr: *mut u8, len: usize) {
+	for i in 0..len {
+		ptr::write_volatile(ptr.add(i), 0);
+	}
+	atomic::compiler_fence(Ordering::SeqCst);
+}
+
+
+/// A zero-initialized allocation for secret data that is zeroed before it is freed
+///
+/// With the `locked-memory` feature, the allocation consists of whole pages that are locked into
+/// RAM and (on Linux) excluded from core dumps; with the `guarded-memory` feature, the pages are
+/// additionally surrounded by inaccessible guard pages.
+pub struct Region {
+	ptr: NonNull<u8>,
+	capacity: usize,
+	locked: bool
+}
+impl Region {
+	/// An empty region that does not allocate
+	pub fn empty() -> Self {
+		Self { ptr: NonNull::dangling(), capacity: 0, locked: false }
+	}
+	
+	/// A pointer to the region
+	pub fn as_ptr(&self) -> *mut u8 {
+		self.ptr.as_ptr()
+	}
+	/// The usable size of the region (which may be larger than requested)
+	pub fn capacity(&self) -> usize {
+		self.capacity
+	}
+	/// Whether the region has been locked into RAM
+	pub fn is_locked(&self) -> bool {
+		self.locked
+	}
+}
+unsafe impl Send for Region {}
+unsafe impl Sync for Region {}
+impl Drop for Region {
+	fn drop(&mut self) {
+		if self.capacity > 0 {
+			unsafe{ zeroize(self.ptr.as_ptr(), self.capacity) };
+			unsafe{ self.free() }
+		}
+	}
+}
+
+
+#[cfg(not(all(unix, feature = "locked-memory")))]
+impl Region {
+	/// Allocates a new zero-initialized region with at least `capacity` bytes
+	pub fn new(capacity: usize) -> io::Result<Self> {
+		use std::alloc::{ self, Layout };
+		if capacity == 0 {
+			return Ok(Self::empty())
+		}
+		
+		let layout = Layout::array::<u8>(capacity).map_err(|_| out_of_memory())?;
+		let ptr = NonNull::new(unsafe{ alloc::alloc_zeroed(layout) }).ok_or_else(out_of_memory)?;
+		Ok(Self { ptr, capacity, locked: false })
+	}
+	/// Frees the region
+	unsafe fn free(&mut self) {
+		use std::alloc::{ self, Layout };
+		alloc::dealloc(self.ptr.as_ptr(), Layout::array::<u8>(self.capacity).unwrap())
+	}
+}
+
+
+#[cfg(all(unix, feature = "locked-memory"))]
+impl Region {
+	/// The size of a guard page (or `0` if guard pages are disabled)
+	fn guard_size() -> usize {
+		match cfg!(feature = "guarded-memory") {
+			true => Self::page_size(),
+			false => 0
+		}
+	}
+	/// The OS' page size
+	fn page_size() -> usize {
+		unsafe{ libc::sysconf(libc::_SC_PAGESIZE) as usize }
+	}
+	
+	/// Allocates a new zero-initialized region with at least `capacity` bytes
+	///
+	/// _Note: If the pages cannot be locked (e.g. because `RLIMIT_MEMLOCK` is exceeded), the region
+	/// is still usable but `is_locked` returns `false`. If the pages cannot be mapped or guarded
+	/// (e.g. because `vm.max_map_count` is exceeded), the OS error is returned._
+	pub fn new(capacity: usize) -> io::Result<Self> {
+		if capacity == 0 {
+			return Ok(Self::empty())
+		}
+		
+		// Map the pages (anonymous mappings are zero-initialized)
+		let (page_size, guard_size) = (Self::page_size(), Self::guard_size());
+		let capacity = capacity.checked_add(page_size - 1).ok_or_else(out_of_memory)?
+			/ page_size * page_size;
+		let total = capacity.checked_add(2 * guard_size).ok_or_else(out_of_memory)?;
+		let base = unsafe {
+			libc::mmap(ptr::null_mut(), total, libc::PROT_READ | libc::PROT_WRITE,
+				libc::MAP_PRIVATE | libc::MAP_ANONYMOUS, -1, 0)
+		};
+		if base == libc::MAP_FAILED {
+			Err(io::Error::last_os_error())?
+		}
+		
+		// Protect the guard pages and lock the usable pages
+		let ptr = unsafe{ base.cast::<u8>().add(guard_size) };
+		if guard_size > 0 {
+			let end = unsafe{ ptr.add(capacity) }.cast();
+			let guarded = unsafe {
+				libc::mprotect(base, guard_size, libc::PROT_NONE) == 0
+					&& libc::mprotect(end, guard_size, libc::PROT_NONE) == 0
+			};
+			if !guarded {
+				let error = io::Error::last_os_error();
+				unsafe{ libc::munmap(base, total) };
+				Err(error)?
+			}
+		}
+		let locked = unsafe{ libc::mlock(ptr.cast(), capacity) } == 0;
+		#[cfg(target_os = "linux")]
+		unsafe{ libc::madvise(ptr.cast(), capacity, libc::MADV_DONTDUMP) };
+		
+		Ok(Self { ptr: NonNull::new(ptr).unwrap(), capacity, locked })
+	}
+	/// Unlocks and unmaps the region
+	unsafe fn free(&mut self) {
+		let guard_size = Self::guard_size();
+		if self.locked {
+			libc::munlock(self.ptr.as_ptr().cast(), self.capacity);
+		}
+		let base = self.ptr.as_ptr().sub(guard_size);
+		libc::munmap(base.cast(), self.capacity + 2 * guard_size);
+	}
+}
+
+
+/// Creates an error for an allocation that failed or is too large
+fn out_of_memory() -> io::Error {
+	io::Error::new(io::ErrorKind::OutOfMemory, "Failed to allocate secret memory")
+}
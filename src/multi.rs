@@ -0,0 +1,184 @@
+use crate::{ Capsule, KeyCapsule, KyncError, KyncErrorKind, SecretBytes };
+use chacha20poly1305::{
+	Key, XChaCha20Poly1305, XNonce,
+	aead::{ Aead, KeyInit, Payload }
+};
+use std::convert::TryInto;
+
+
+/// The envelope magic bytes
+const MAGIC: &[u8; 4] = b"KYNM";
+/// The current envelope format version
+const FORMAT_VERSION: u8 = 0x01;
+/// The length of the random data key
+const DATA_KEY_LEN: usize = 32;
+/// The length of the XChaCha20-Poly1305 nonce
+const NONCE_LEN: usize = 24;
+
+
+/// An error message indicating an invalid envelope
+const ERR_FORMAT: &str = "Invalid or truncated multi-recipient envelope";
+
+
+/// A multi-recipient capsule envelope: the secret is encrypted once under a random data key and the
+/// data key is protected for each recipient, so that the secret can be recovered with any of them
+///
+/// Format: `magic[4] || format_version[1] || count[8] || recipients || nonce[24] || ciphertext`
+/// where every recipient is a `Capsule` envelope (which records the plugin ID and the config)
+/// prefixed with its length as 64 bit big endian integer. The secret is sealed with
+/// XChaCha20-Poly1305; everything before the ciphertext is authenticated as associated data.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MultiCapsule {
+	recipients: Vec<Capsule>,
+	nonce: Vec<u8>,
+	ciphertext: Vec<u8>
+}
+impl MultiCapsule {
+	/// Protects `data` for all `(capsule, config, auth)`-recipients and seals the result into an
+	/// envelope
+	pub fn seal<'a, K: KeyCapsule + ?Sized + 'a>(
+		recipients: impl IntoIterator<Item = (&'a K, &'a [u8], Option<&'a [u8]>)>, data: &[u8])
+		-> Result<Vec<u8>, KyncError>
+	{
+		// Create the data key and the nonce
+		let (mut data_key, mut nonce) = (SecretBytes::from(&[0; DATA_KEY_LEN][..]), [0; NONCE_LEN]);
+		getrandom::getrandom(&mut data_key).and_then(|_| getrandom::getrandom(&mut nonce))
+			.map_err(|e| KyncError::new(KyncErrorKind::ProtectError, "Failed to gather randomness")
+				.with_source(e.to_string()))?;
+		
+		// Protect the data key for every recipient
+		let mut this =
+			Self { recipients: Vec::new(), nonce: nonce.to_vec(), ciphertext: Vec::new() };
+		for (capsule, config, auth) in recipients {
+			let envelope = Capsule::seal(capsule, &data_key, config, auth)?;
+			this.recipients.push(Capsule::parse(&envelope)?);
+		}
+		if this.recipients.is_empty() {
+			Err(KyncError::new(KyncErrorKind::ProtectError, "At least one recipient is required"))?
+		}
+		
+		// Seal the data
+		let header = this.header();
+		let payload = Payload { msg: data, aad: &header };
+		this.ciphertext = cipher(&data_key).encrypt(XNonce::from_slice(&nonce), payload)
+			.map_err(|_| KyncError::new(KyncErrorKind::ProtectError, "Failed to seal the secret"))?;
+		Ok(this.to_bytes())
+	}
+	
+	/// Opens an `envelope` with the first recipient whose plugin is in `capsules` and that can
+	/// recover a data key that opens the envelope
+	///
+	/// The recipients are tried in the order they were sealed; recipients without a matching plugin
+	/// or whose recovered data key does not open the envelope are skipped. `auth` is called with
+	/// each recipient before it is tried and returns the authentication for it (if any). If no
+	/// recipient succeeds, the error of the last attempt is returned.
+	pub fn open<'a, K: KeyCapsule + ?Sized + 'a>(capsules: impl IntoIterator<Item = &'a K>,
+		envelope: &[u8], mut auth: impl FnMut(&Capsule) -> Option<SecretBytes>)
+		-> Result<SecretBytes, KyncError>
+	{
+		let this = Self::parse(envelope)?;
+		let capsules: Vec<&K> = capsules.into_iter().collect();
+		
+		// Recover the data key with the first recipient that succeeds
+		let mut error = None;
+		for recipient in this.recipients.iter() {
+			let capsule = match recipient.find(capsules.iter().copied()) {
+				Ok(capsule) => capsule,
+				Err(e) => { error = error.or(Some(e)); continue }
+			};
+			let auth = auth(recipient);
+			match capsule.recover(recipient.payload(), auth.as_deref())
+				.and_then(|data_key| this.decrypt(&data_key))
+			{
+				Ok(secret) => return Ok(secret),
+				Err(e) => error = Some(e.with_plugin_id(recipient.id()))
+			}
+		}
+		Err(error.unwrap())
+	}
+	
+	/// Parses an envelope
+	pub fn parse(envelope: &[u8]) -> Result<Self, KyncError> {
+		// Validate the header
+		let (magic, envelope) = split(envelope, MAGIC.len())?;
+		let (format_version, envelope) = split(envelope, 1)?;
+		let (count, mut envelope) = split(envelope, 8)?;
+		if magic != MAGIC {
+			Err(format_error(ERR_FORMAT))?
+		}
+		if format_version != [FORMAT_VERSION] {
+			Err(format_error(format!("Unsupported envelope format version {}", format_version[0])))?
+		}
+		
+		// Read the recipients
+		let count = u64::from_be_bytes(count.try_into().unwrap());
+		let mut recipients = Vec::new();
+		for _ in 0..count {
+			let (len, rest) = split(envelope, 8)?;
+			let len = u64::from_be_bytes(len.try_into().unwrap());
+			let len = len.try_into().map_err(|_| format_error(ERR_FORMAT))?;
+			let (recipient, rest) = split(rest, len)?;
+			recipients.push(Capsule::parse(recipient)?);
+			envelope = rest;
+		}
+		if recipients.is_empty() {
+			Err(format_error(ERR_FORMAT))?
+		}
+		
+		let (nonce, ciphertext) = split(envelope, NONCE_LEN)?;
+		Ok(Self { recipients, nonce: nonce.to_vec(), ciphertext: ciphertext.to_vec() })
+	}
+	/// Serializes the envelope
+	pub fn to_bytes(&self) -> Vec<u8> {
+		let mut envelope = self.header();
+		envelope.extend_from_slice(&self.ciphertext);
+		envelope
+	}
+	/// Serializes everything before the ciphertext
+	fn header(&self) -> Vec<u8> {
+		let mut header = Vec::new();
+		header.extend_from_slice(MAGIC);
+		header.push(FORMAT_VERSION);
+		header.extend_from_slice(&(self.recipients.len() as u64).to_be_bytes());
+		for recipient in self.recipients.iter().map(|r| r.to_bytes()) {
+			header.extend_from_slice(&(recipient.len() as u64).to_be_bytes());
+			header.extend_from_slice(&recipient);
+		}
+		header.extend_from_slice(&self.nonce);
+		header
+	}
+	
+	/// Decrypts the secret with the recovered `data_key`
+	fn decrypt(&self, data_key: &[u8]) -> Result<SecretBytes, KyncError> {
+		if data_key.len() != DATA_KEY_LEN {
+			Err(format_error("The recovered data key is invalid"))?
+		}
+		let header = self.header();
+		let payload = Payload { msg: &self.ciphertext, aad: &header };
+		let plaintext = cipher(data_key).decrypt(XNonce::from_slice(&self.nonce), payload)
+			.map_err(|_| format_error("The envelope is corrupt"))?;
+		Ok(SecretBytes::from(plaintext))
+	}
+	
+	/// The recipients, each with the plugin ID, the config and the protected data key
+	pub fn recipients(&self) -> &[Capsule] {
+		&self.recipients
+	}
+}
+
+
+/// Creates the cipher for `data_key`
+fn cipher(data_key: &[u8]) -> XChaCha20Poly1305 {
+	XChaCha20Poly1305::new(Key::from_slice(data_key))
+}
+/// Splits `len` bytes off `data` or fails with a format error if `data` is too short
+fn split(data: &[u8], len: usize) -> Result<(&[u8], &[u8]), KyncError> {
+	match data.len() >= len {
+		true => Ok(data.split_at(len)),
+		false => Err(format_error(ERR_FORMAT))
+	}
+}
+/// Creates a format error with `message`
+fn format_error(message: impl Into<String>) -> KyncError {
+	KyncError::new(KyncErrorKind::FormatError, message)
+}
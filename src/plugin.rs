@@ -1,13 +1,63 @@
 use crate::{
-	KyncError, KyncErrorKind,
-	ffi::{ StaticCharPtrExt, Slice, Writer, sys }
+	ErrorCode, KeyCapsule, KyncError, KyncErrorKind, SecretBytes,
+	ffi::{ ERR_SINK_IGNORED, StaticCharPtrExt, Slice, StreamWriter, Writer, sys }
+};
+use std::{
+	ptr, thread, io::Write, os::raw::c_char,
+	path::{ Path, PathBuf },
+	sync::{
+		Arc, mpsc::{ self, RecvTimeoutError },
+		atomic::{ AtomicBool, Ordering }
+	},
+	time::{ Duration, Instant }
 };
-use std::{ ptr, path::Path };
 use libloading::Library;
 
 
-/// The current API version
-const API_VERSION: u16 = 0x01_00;
+/// The base API version
+pub(crate) const API_VERSION: u16 = 0x01_00;
+/// The API version with machine-readable error codes
+pub(crate) const API_VERSION_V2: u16 = 0x02_00;
+/// The maximum duration of an API call as defined by the specification
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(90);
+
+
+/// Limits for the output a plugin may write during a single call
+///
+/// The limits protect the host from buggy or hostile plugins that write without bound; if a limit
+/// is exceeded, the plugin gets a callback error and the call fails with
+/// `KyncErrorKind::OutputLimitExceeded`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+	/// The maximum total amount of bytes per call
+	pub max_bytes: usize,
+	/// The maximum amount of segments (i.e. `write`-calls) per call
+	pub max_segments: usize,
+	/// The maximum length of the plugin ID
+	pub max_id_len: usize
+}
+impl Limits {
+	/// The limits for an `id`-call (at most `max_id_len` bytes)
+	fn for_id(self) -> Self {
+		Self { max_bytes: self.max_bytes.min(self.max_id_len), ..self }
+	}
+}
+impl Default for Limits {
+	/// 64 MiB per call in at most 65536 segments and 4 KiB for the plugin ID
+	fn default() -> Self {
+		Self { max_bytes: 64 * 1024 * 1024, max_segments: 65536, max_id_len: 4096 }
+	}
+}
+
+
+/// A fault that is injected into the sink to check if a plugin propagates callback errors
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fault {
+	/// The `write`-call that would exceed the given amount of bytes fails
+	AfterBytes(usize),
+	/// The `write`-call after the given amount of segments fails
+	AfterSegments(usize)
+}
 
 
 /// The current operating system's default dynamic library prefix (e.g. `"lib"` for Linux)
@@ -31,8 +81,65 @@ pub fn os_default_suffix() -> &'static str {
 }
 
 
-/// A key capsule plugin (see "Kync.asciidoc" for further API documentation)
-pub struct Plugin {
+/// Runs `f` on a worker thread and abandons it if it takes longer than `timeout` (or runs `f` on
+/// the current thread if `timeout` is `None`)
+pub(crate) fn call_with_timeout<T: Send + 'static>(timeout: Option<Duration>,
+	f: impl FnOnce() -> Result<T, KyncError> + Send + 'static) -> Result<T, KyncError>
+{
+	let timeout = match timeout {
+		Some(timeout) => timeout,
+		None => return f()
+	};
+	
+	// Run the call on a worker thread; if the deadline is exceeded, the worker is detached and
+	// keeps everything it needs alive until it finishes
+	let (sender, receiver) = mpsc::channel();
+	thread::spawn(move || { let _ = sender.send(f()); });
+	receiver.recv_timeout(timeout).unwrap_or_else(|e| Err(recv_error(e, timeout)))
+}
+/// Converts the error of a worker channel into a `KyncErrorKind::Timeout` or
+/// `KyncErrorKind::WorkerDied` error
+fn recv_error(error: RecvTimeoutError, timeout: Duration) -> KyncError {
+	match error {
+		RecvTimeoutError::Timeout => KyncError::new(KyncErrorKind::Timeout,
+			format!("The call exceeded its deadline of {:?} and has been abandoned", timeout)),
+		RecvTimeoutError::Disconnected =>
+			KyncError::new(KyncErrorKind::WorkerDied, "The worker thread died unexpectedly")
+	}
+}
+
+
+/// An event sent by a streaming worker
+enum StreamEvent {
+	/// A segment written by the plugin
+	Segment(SecretBytes),
+	/// The call has finished
+	Done(Result<(), KyncError>)
+}
+
+
+/// The log level passed to `init` (`1` for debug builds, `0` otherwise)
+fn log_level() -> u8 {
+	match cfg!(debug_assertions) {
+		true => 1,
+		false => 0
+	}
+}
+
+
+/// Loads the symbol `name` from `library`
+fn symbol<T: Copy>(library: &Library, name: &str) -> Result<T, KyncError> {
+	let symbol = unsafe{ library.get::<T>(format!("{}\0", name).as_bytes()) }.map_err(|e| {
+		let message = format!("Missing symbol `{}`", name);
+		KyncError::new(KyncErrorKind::LoadingError, message).with_source(e)
+	})?;
+	Ok(*symbol)
+}
+
+
+/// The plugin's API functions
+struct Functions {
+	init: sys::init,
 	id: sys::id,
 	configs: sys::configs,
 	set_context: sys::set_context,
@@ -40,114 +147,466 @@ pub struct Plugin {
 	auth_info_recover: sys::auth_info_recover,
 	protect: sys::protect,
 	recover: sys::recover,
-	_library: Library
+	error_code: sys::error_code,
+	/// Whether a call has been abandoned (plugins are not required to be reentrant, so the
+	/// abandoned call may still be running inside the plugin)
+	poisoned: AtomicBool,
+	/// The library (shared with an `init`-call that may outlive a failed load)
+	_library: Arc<Library>
+}
+impl Functions {
+	/// Fails with `KyncErrorKind::Poisoned` if a call has been abandoned
+	fn check_poisoned(&self) -> Result<(), KyncError> {
+		match self.poisoned.load(Ordering::SeqCst) {
+			true => Err(KyncError::new(KyncErrorKind::Poisoned,
+				"The plugin rejects all calls because a previous call has been abandoned")),
+			false => Ok(())
+		}
+	}
+	/// Poisons the plugin if `result` is an abandoned call
+	fn poison_if_abandoned<T>(&self, result: Result<T, KyncError>) -> Result<T, KyncError> {
+		if matches!(&result, Err(e) if e.kind() == KyncErrorKind::Timeout) {
+			self.poisoned.store(true, Ordering::SeqCst);
+		}
+		result
+	}
+	
+	/// Checks if `error` is an non-`NULL` error pointer and gets the error code if supported
+	fn check(&self, error: *const c_char, kind: KyncErrorKind) -> Result<(), KyncError> {
+		error.check(kind).map_err(|e| {
+			let code = self.error_code.map(|error_code| unsafe{ error_code(error) });
+			match code.and_then(ErrorCode::from_raw) {
+				Some(code) => e.with_code(code),
+				None => e
+			}
+		})
+	}
+	
+	/// Calls `protect` and writes the result to `sink`
+	fn call_protect(&self, sink: &mut sys::write_t, data: &[u8], config: &[u8],
+		auth: Option<&[u8]>) -> Result<(), KyncError>
+	{
+		self.check(self.protect_raw(sink, data, config, auth), KyncErrorKind::ProtectError)
+	}
+	/// Calls `protect` and returns the raw error pointer
+	fn protect_raw(&self, sink: &mut sys::write_t, data: &[u8], config: &[u8],
+		auth: Option<&[u8]>) -> *const c_char
+	{
+		// Create the C structs
+		let (data, config) = (Slice::from(data), Slice::from(config));
+		let auth = auth.map(Slice::from);
+		let auth = auth.as_ref().map(|s| s.slice_t() as *const sys::slice_t)
+			.unwrap_or(ptr::null());
+		
+		// Call `protect`
+		unsafe{ self.protect.unwrap()(sink, data.slice_t(), config.slice_t(), auth) }
+	}
+	/// Calls `recover` and writes the result to `sink`
+	fn call_recover(&self, sink: &mut sys::write_t, data: &[u8], auth: Option<&[u8]>)
+		-> Result<(), KyncError>
+	{
+		self.check(self.recover_raw(sink, data, auth), KyncErrorKind::RecoverError)
+	}
+	/// Calls `recover` and returns the raw error pointer
+	fn recover_raw(&self, sink: &mut sys::write_t, data: &[u8], auth: Option<&[u8]>)
+		-> *const c_char
+	{
+		// Create the C structs
+		let data = Slice::from(data);
+		let auth = auth.map(Slice::from);
+		let auth = auth.as_ref().map(|s| s.slice_t() as *const sys::slice_t)
+			.unwrap_or(ptr::null());
+		
+		// Call `recover`
+		unsafe{ self.recover.unwrap()(sink, data.slice_t(), auth) }
+	}
+	
+	/// Checks that `error` is exactly the callback error of `sink` if `sink` injected its fault
+	///
+	/// Returns `true` if the fault has been injected and propagated, `false` if the call finished
+	/// before the fault was reached, and an error if the plugin failed otherwise or did not
+	/// propagate the callback error.
+	fn check_fault(&self, sink: &Writer, error: *const c_char, kind: KyncErrorKind)
+		-> Result<bool, KyncError>
+	{
+		match (sink.fault_injected(), error) {
+			(false, error) => self.check(error, kind).map(|_| false),
+			(true, error) if error == sink.fault_error() => Ok(true),
+			(true, error) if error.is_null() => Err(KyncError::new(kind, ERR_SINK_IGNORED)),
+			(true, error) => {
+				let returned = self.check(error, kind).unwrap_err();
+				let message = format!("The plugin returned \"{}\" instead of the callback error",
+					returned.message());
+				Err(KyncError::new(kind, message))
+			}
+		}
+	}
+}
+
+
+/// A key capsule plugin (see "Kync.asciidoc" for further API documentation)
+///
+/// Every call is bounded by a deadline (`DEFAULT_TIMEOUT` by default); a call that exceeds its
+/// deadline is abandoned on a worker thread and fails with `KyncErrorKind::Timeout`. Since plugins
+/// are not required to be reentrant, the plugin is poisoned afterwards and all further calls fail
+/// with `KyncErrorKind::Poisoned`.
+pub struct Plugin {
+	functions: Arc<Functions>,
+	api_version: u16,
+	timeout: Option<Duration>,
+	limits: Limits,
+	strict: bool,
+	path: PathBuf,
+	id: Option<Vec<u8>>
 }
 impl Plugin {
 	/// Load the library
 	pub fn load(path: impl AsRef<Path>) -> Result<Self, KyncError> {
+		let path = path.as_ref();
+		Self::load_library(path).map_err(|e| e.with_path(path))
+	}
+	/// Loads the library, resolves the symbols and initializes the plugin
+	fn load_library(path: &Path) -> Result<Self, KyncError> {
 		// Load library
 		#[cfg(target_os = "linux")]
 		let library: Library = {
 			// Load library with RTLD_NOW | RTLD_NODELETE to fix a SIGSEGV
 			// (see https://github.com/nagisa/rust_libloading/issues/41)
-			libloading::os::unix::Library::open(Some(path.as_ref()), 0x2 | 0x1000)?.into()
+			libloading::os::unix::Library::open(Some(path), 0x2 | 0x1000)?.into()
 		};
 		#[cfg(not(target_os = "linux"))]
-		let library = Library::new(path.as_ref())?;
+		let library = Library::new(path)?;
+		let library = Arc::new(library);
 		
-		// Init plugin and validate the API version
-		let log_level = match cfg!(debug_assertions) {
-			true => 1,
-			false => 0
+		// Load the functions (`error_code` is only available for API v2)
+		let init: sys::init = symbol(&library, "init")?;
+		let mut functions = Functions {
+			init,
+			id: symbol(&library, "id")?,
+			configs: symbol(&library, "configs")?,
+			set_context: symbol(&library, "set_context")?,
+			auth_info_protect: symbol(&library, "auth_info_protect")?,
+			auth_info_recover: symbol(&library, "auth_info_recover")?,
+			protect: symbol(&library, "protect")?,
+			recover: symbol(&library, "recover")?,
+			error_code: symbol(&library, "error_code").unwrap_or(None),
+			poisoned: AtomicBool::new(false),
+			_library: Arc::clone(&library)
 		};
-		let init: sys::init = *unsafe{ library.get(b"init\0")? };
-		unsafe{ init.unwrap()(API_VERSION, log_level) }.check(KyncErrorKind::InitError)?;
 		
-		// Create plugin
-		Ok(Self {
-			id: *unsafe{ library.get(b"id\0")? },
-			configs: *unsafe{ library.get(b"configs\0")? },
-			set_context: *unsafe{ library.get(b"set_context\0")? },
-			auth_info_protect: *unsafe{ library.get(b"auth_info_protect\0")? },
-			auth_info_recover: *unsafe{ library.get(b"auth_info_recover\0")? },
-			protect: *unsafe{ library.get(b"protect\0")? },
-			recover: *unsafe{ library.get(b"recover\0")? },
-			_library: library
+		// Init plugin and negotiate the API version
+		let log_level = log_level();
+		let supports_v2 = functions.error_code.is_some();
+		let api_version = call_with_timeout(Some(DEFAULT_TIMEOUT), move || {
+			// Keep the library loaded until `init` returns, even if the call has been abandoned
+			let _library = library;
+			let init = init.unwrap();
+			if supports_v2 && unsafe{ init(API_VERSION_V2, log_level) }.is_null() {
+				return Ok(API_VERSION_V2)
+			}
+			unsafe{ init(API_VERSION, log_level) }.check(KyncErrorKind::InitError)?;
+			Ok(API_VERSION)
+		})?;
+		if api_version != API_VERSION_V2 {
+			functions.error_code = None;
+		}
+		
+		let (functions, timeout) = (Arc::new(functions), Some(DEFAULT_TIMEOUT));
+		let limits = Limits::default();
+		let (path, strict) = (path.into(), true);
+		let mut this = Self { functions, api_version, timeout, limits, strict, path, id: None };
+		
+		// Query the ID to give errors some context
+		this.id = this.id().ok();
+		Ok(this)
+	}
+	
+	/// Sets the deadline for every API call (`None` disables the deadline and performs the calls on
+	/// the current thread)
+	pub fn set_timeout(&mut self, timeout: Option<Duration>) -> &mut Self {
+		self.timeout = timeout;
+		self
+	}
+	/// The deadline for every API call
+	pub fn timeout(&self) -> Option<Duration> {
+		self.timeout
+	}
+	
+	/// Sets the output limits for every API call
+	pub fn set_limits(&mut self, limits: Limits) -> &mut Self {
+		self.limits = limits;
+		self
+	}
+	/// The output limits for every API call
+	pub fn limits(&self) -> Limits {
+		self.limits
+	}
+	
+	/// Enables or disables the strict mode (enabled by default)
+	///
+	/// In strict mode, plugins that violate the specification's output rules are rejected: `id` must
+	/// be written using exactly one `write`-call and `configs` must write at least one config. The
+	/// lenient mode accepts such legacy plugins and concatenates the ID segments.
+	pub fn set_strict(&mut self, strict: bool) -> &mut Self {
+		self.strict = strict;
+		self
+	}
+	/// Whether the strict mode is enabled
+	pub fn is_strict(&self) -> bool {
+		self.strict
+	}
+	
+	/// The negotiated API version (`0x02_00` if the plugin reports machine-readable error codes)
+	pub fn api_version(&self) -> u16 {
+		self.api_version
+	}
+	
+	/// The path the plugin has been loaded from
+	pub fn path(&self) -> &Path {
+		&self.path
+	}
+	
+	/// Protects `data` into a sink that fails at `fault` and checks that the plugin returns exactly
+	/// the sink's callback error as required by the specification
+	///
+	/// Returns `true` if the fault has been injected and propagated or `false` if `protect` finished
+	/// before the fault was reached.
+	pub fn protect_with_fault(&self, fault: Fault, data: &[u8], config: &[u8],
+		auth: Option<&[u8]>) -> Result<bool, KyncError>
+	{
+		let (data, config) = (SecretBytes::from(data), config.to_vec());
+		let (auth, limits) = (auth.map(SecretBytes::from), self.limits);
+		self.call(move |f| {
+			let mut sink = Writer::with_fault(limits, fault);
+			let error = f.protect_raw(sink.write_t(), &data, &config, auth.as_deref());
+			sink.check(f.check_fault(&sink, error, KyncErrorKind::ProtectError))
+		})
+	}
+	/// Recovers `data` into a sink that fails at `fault` and checks that the plugin returns exactly
+	/// the sink's callback error as required by the specification
+	///
+	/// Returns `true` if the fault has been injected and propagated or `false` if `recover` finished
+	/// before the fault was reached.
+	pub fn recover_with_fault(&self, fault: Fault, data: &[u8], auth: Option<&[u8]>)
+		-> Result<bool, KyncError>
+	{
+		let (data, auth) = (SecretBytes::from(data), auth.map(SecretBytes::from));
+		let limits = self.limits;
+		self.call(move |f| {
+			let mut sink = Writer::with_fault(limits, fault);
+			let error = f.recover_raw(sink.write_t(), &data, auth.as_deref());
+			sink.check(f.check_fault(&sink, error, KyncErrorKind::RecoverError))
+		})
+	}
+	
+	/// Calls `init` again with `api` (e.g. to check that unknown API versions are rejected)
+	pub(crate) fn reinit(&self, api: u16) -> Result<(), KyncError> {
+		self.call(move |f| {
+			f.check(unsafe{ f.init.unwrap()(api, log_level()) }, KyncErrorKind::InitError)
 		})
 	}
 	
-	/// The plugin/format ID
-	pub fn id(&self) -> Result<Vec<u8>, KyncError> {
-		let mut sink = Writer::new();
-		unsafe{ self.id.unwrap()(sink.write_t()) }.check(KyncErrorKind::IdError)?;
-		Ok(sink.into())
+	/// Performs a call to the plugin functions with the configured deadline
+	fn call<T: Send + 'static>(&self,
+		f: impl FnOnce(&Functions) -> Result<T, KyncError> + Send + 'static) -> Result<T, KyncError>
+	{
+		self.functions.check_poisoned().map_err(|e| self.context(e))?;
+		let functions = self.functions.clone();
+		let result = call_with_timeout(self.timeout, move || f(&functions));
+		self.functions.poison_if_abandoned(result).map_err(|e| self.context(e))
+	}
+	/// Performs a call that streams every segment written by the plugin into `sink`
+	///
+	/// If `sink` fails, the plugin gets a callback error and the returned error keeps the
+	/// `io::Error` as source. With a deadline, the call runs on a worker thread and the segments are
+	/// passed to the current thread one by one; the time spent in `sink` counts towards the
+	/// deadline.
+	fn call_to(&self, sink: &mut dyn Write, kind: KyncErrorKind,
+		f: impl FnOnce(&Functions, &mut StreamWriter) -> Result<(), KyncError> + Send + 'static)
+		-> Result<(), KyncError>
+	{
+		self.functions.check_poisoned().map_err(|e| self.context(e))?;
+		let mut io_error = None;
+		let mut write = |data: &[u8]| match sink.write_all(data) {
+			Ok(_) => true,
+			Err(e) => {
+				io_error = Some(e);
+				false
+			}
+		};
+		
+		let result = match self.timeout {
+			None => {
+				let mut writer = StreamWriter::new(self.limits, &mut write);
+				let result = f(&self.functions, &mut writer);
+				writer.check(result)
+			},
+			Some(timeout) => {
+				// Run the call on a worker thread that forwards the segments and waits for the acks
+				let (functions, limits) = (self.functions.clone(), self.limits);
+				let (events, event_receiver) = mpsc::channel();
+				let (ack_sender, acks) = mpsc::channel();
+				thread::spawn(move || {
+					let mut forward = |data: &[u8]| {
+						events.send(StreamEvent::Segment(SecretBytes::from(data))).is_ok()
+							&& acks.recv().unwrap_or(false)
+					};
+					let mut writer = StreamWriter::new(limits, &mut forward);
+					let result = f(&functions, &mut writer);
+					let result = writer.check(result);
+					let _ = events.send(StreamEvent::Done(result));
+				});
+				
+				// Consume the segments until the call is done or the deadline is exceeded
+				let deadline = Instant::now() + timeout;
+				let result = loop {
+					let remaining = deadline.saturating_duration_since(Instant::now());
+					match event_receiver.recv_timeout(remaining) {
+						Ok(StreamEvent::Segment(data)) => { let _ = ack_sender.send(write(&data)); },
+						Ok(StreamEvent::Done(result)) => break result,
+						Err(e) => break Err(recv_error(e, timeout))
+					}
+				};
+				self.functions.poison_if_abandoned(result)
+			}
+		};
+		
+		// Attach the sink error if any (even if the plugin ignored the callback error)
+		let result = match (result, io_error) {
+			(result, None) => result,
+			(Ok(_), Some(io_error)) =>
+				Err(KyncError::new(kind, ERR_SINK_IGNORED).with_source(io_error)),
+			(Err(e), Some(io_error)) => Err(e.with_source(io_error))
+		};
+		result.map_err(|e| self.context(e))
+	}
+	/// Adds the plugin's path and ID to `error`
+	fn context(&self, error: KyncError) -> KyncError {
+		let error = error.with_path(&self.path);
+		match self.id.as_ref() {
+			Some(id) => error.with_plugin_id(id),
+			None => error
+		}
+	}
+}
+impl KeyCapsule for Plugin {
+	fn cached_id(&self) -> Result<Vec<u8>, KyncError> {
+		match self.id.as_ref() {
+			Some(id) => Ok(id.clone()),
+			None => self.id()
+		}
+	}
+	fn api_version(&self) -> u16 {
+		self.api_version
+	}
+	
+	fn id(&self) -> Result<Vec<u8>, KyncError> {
+		let (limits, strict) = (self.limits.for_id(), self.strict);
+		self.call(move |f| {
+			let mut sink = Writer::new(limits);
+			let result = f.check(unsafe{ f.id.unwrap()(sink.write_t()) }, KyncErrorKind::IdError);
+			sink.check(result)?;
+			
+			// Validate the segmentation
+			let segments: Vec<Vec<u8>> = sink.into();
+			if strict && segments.len() != 1 {
+				let message = format!("The plugin wrote its ID using {} instead of exactly one \
+					`write`-call", segments.len());
+				Err(KyncError::new(KyncErrorKind::IdError, message))?
+			}
+			Ok(segments.concat())
+		})
 	}
 	
-	/// All possible configs
-	pub fn configs(&self) -> Result<Vec<Vec<u8>>, KyncError> {
-		let mut sink = Writer::new();
-		unsafe{ self.configs.unwrap()(sink.write_t()) }.check(KyncErrorKind::ConfigsError)?;
-		Ok(sink.into())
+	fn configs(&self) -> Result<Vec<Vec<u8>>, KyncError> {
+		let (limits, strict) = (self.limits, self.strict);
+		self.call(move |f| {
+			let mut sink = Writer::new(limits);
+			let result = f.check(unsafe{ f.configs.unwrap()(sink.write_t()) },
+				KyncErrorKind::ConfigsError);
+			sink.check(result)?;
+			
+			// Validate the segmentation
+			let configs: Vec<Vec<u8>> = sink.into();
+			if strict && configs.is_empty() {
+				let message = "The plugin did not write any config";
+				Err(KyncError::new(KyncErrorKind::ConfigsError, message))?
+			}
+			Ok(configs)
+		})
 	}
 	
-	/// Sets an optional application specific context if supported (useful to assign better names
-	/// etc.)
-	pub fn set_context(&self, context: &[u8]) -> Result<(), KyncError> {
-		let context = Slice::from(context);
-		unsafe{ self.set_context.unwrap()(context.slice_t()) }
-			.check(KyncErrorKind::SetContextError)
+	fn set_context(&self, context: &[u8]) -> Result<(), KyncError> {
+		let context = context.to_vec();
+		self.call(move |f| {
+			let context = Slice::from(context.as_slice());
+			f.check(unsafe{ f.set_context.unwrap()(context.slice_t()) },
+				KyncErrorKind::SetContextError)
+		})
 	}
 	
-	/// Checks if an authentication is required to protect a secret and gets the number of retries
-	/// left
-	pub fn auth_info_protect(&self, config: &[u8]) -> Result<(bool, u64), KyncError> {
-		let config = Slice::from(config);
-		let (mut required, mut retries) = (0u8, 0u64);
-		unsafe{ self.auth_info_protect.unwrap()(&mut required, &mut retries, config.slice_t()) }
-			.check(KyncErrorKind::AuthInfoError)?;
-		Ok((required != 0, retries))
+	fn auth_info_protect(&self, config: &[u8]) -> Result<(bool, u64), KyncError> {
+		let config = config.to_vec();
+		self.call(move |f| {
+			let config = Slice::from(config.as_slice());
+			let (mut required, mut retries) = (0u8, 0u64);
+			f.check(unsafe{ f.auth_info_protect.unwrap()(&mut required, &mut retries, config.slice_t()) },
+				KyncErrorKind::AuthInfoError)?;
+			Ok((required != 0, retries))
+		})
 	}
 	
-	/// Checks if an authentication is required to recover a secret and gets the number of retries
-	/// left
-	pub fn auth_info_recover(&self, config: &[u8]) -> Result<(bool, u64), KyncError> {
-		let config = Slice::from(config);
-		let (mut required, mut retries) = (0u8, 0u64);
-		unsafe{ self.auth_info_recover.unwrap()(&mut required, &mut retries, config.slice_t()) }
-			.check(KyncErrorKind::AuthInfoError)?;
-		Ok((required != 0, retries))
+	fn auth_info_recover(&self, config: &[u8]) -> Result<(bool, u64), KyncError> {
+		let config = config.to_vec();
+		self.call(move |f| {
+			let config = Slice::from(config.as_slice());
+			let (mut required, mut retries) = (0u8, 0u64);
+			f.check(unsafe{ f.auth_info_recover.unwrap()(&mut required, &mut retries, config.slice_t()) },
+				KyncErrorKind::AuthInfoError)?;
+			Ok((required != 0, retries))
+		})
 	}
 	
-	/// Protects `data`
-	pub fn protect(&self, data: &[u8], config: &[u8], auth: Option<&[u8]>)
+	fn protect(&self, data: &[u8], config: &[u8], auth: Option<&[u8]>)
 		-> Result<Vec<u8>, KyncError>
 	{
-		// Create the C structs
-		let mut sink = Writer::new();
-		let data = Slice::from(data);
-		let config = Slice::from(config);
-		let auth = auth.map(|s| Slice::from(s));
-		
-		// Call `protect`
-		let auth = auth.as_ref().map(|s| s.slice_t() as *const sys::slice_t)
-			.unwrap_or(ptr::null());
-		unsafe{ self.protect.unwrap()(sink.write_t(), data.slice_t(), config.slice_t(), auth) }
-			.check(KyncErrorKind::ProtectError)?;
-		Ok(sink.into())
+		// Copy the inputs into zeroizing buffers for the worker thread
+		let (data, config) = (SecretBytes::from(data), config.to_vec());
+		let (auth, limits) = (auth.map(SecretBytes::from), self.limits);
+		self.call(move |f| {
+			let mut sink = Writer::new(limits);
+			let result = f.call_protect(sink.write_t(), &data, &config, auth.as_deref());
+			sink.check(result)?;
+			Ok(sink.into())
+		})
+	}
+	fn protect_to(&self, sink: &mut dyn Write, data: &[u8], config: &[u8], auth: Option<&[u8]>)
+		-> Result<(), KyncError>
+	{
+		let (data, config) = (SecretBytes::from(data), config.to_vec());
+		let auth = auth.map(SecretBytes::from);
+		self.call_to(sink, KyncErrorKind::ProtectError, move |f, sink| {
+			f.call_protect(sink.write_t(), &data, &config, auth.as_deref())
+		})
 	}
 	
-	/// Recovers some protected `data`
-	pub fn recover(&self, data: &[u8], auth: Option<&[u8]>) -> Result<Vec<u8>, KyncError> {
-		// Create the C structs
-		let mut sink = Writer::new();
-		let data = Slice::from(data);
-		let auth = auth.map(|s| Slice::from(s));
-		
-		// Call `recover`
-		let auth = auth.as_ref().map(|s| s.slice_t() as *const sys::slice_t)
-			.unwrap_or(ptr::null());
-		unsafe{ self.recover.unwrap()(sink.write_t(), data.slice_t(), auth) }
-			.check(KyncErrorKind::ProtectError)?;
-		Ok(sink.into())
+	fn recover(&self, data: &[u8], auth: Option<&[u8]>) -> Result<SecretBytes, KyncError> {
+		// Copy the inputs into zeroizing buffers for the worker thread
+		let (data, auth) = (SecretBytes::from(data), auth.map(SecretBytes::from));
+		let limits = self.limits;
+		self.call(move |f| {
+			let mut sink = Writer::new(limits);
+			let result = f.call_recover(sink.write_t(), &data, auth.as_deref());
+			sink.check(result)?;
+			Ok(sink.into())
+		})
 	}
-}
\ No newline at end of file
+	fn recover_to(&self, sink: &mut dyn Write, data: &[u8], auth: Option<&[u8]>)
+		-> Result<(), KyncError>
+	{
+		let (data, auth) = (SecretBytes::from(data), auth.map(SecretBytes::from));
+		self.call_to(sink, KyncErrorKind::RecoverError, move |f, sink| {
+			f.call_recover(sink.write_t(), &data, auth.as_deref())
+		})
+	}
+}
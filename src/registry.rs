@@ -0,0 +1,109 @@
+use crate::{ Capsule, KeyCapsule, KyncError, KyncErrorKind, Plugin, plugin::os_default_suffix };
+use std::{
+	env, fs, collections::BTreeMap,
+	path::{ Path, PathBuf }
+};
+
+
+/// The environment variable that contains additional plugin search paths (separated like `PATH`)
+pub const PLUGIN_PATH_ENV: &str = "KYNC_PLUGIN_PATH";
+
+
+/// A loaded plugin together with the path it was loaded from
+struct Entry {
+	path: PathBuf,
+	plugin: Plugin
+}
+
+
+/// A plugin registry that scans some directories for plugins and indexes them by their ID
+#[derive(Default)]
+pub struct PluginRegistry {
+	search_paths: Vec<PathBuf>,
+	plugins: BTreeMap<Vec<u8>, Entry>,
+	failures: Vec<(PathBuf, KyncError)>
+}
+impl PluginRegistry {
+	/// Creates a new registry without search paths
+	pub fn new() -> Self {
+		Self::default()
+	}
+	/// Creates a new registry with the search paths from `KYNC_PLUGIN_PATH`
+	pub fn from_env() -> Self {
+		let mut this = Self::new();
+		if let Some(paths) = env::var_os(PLUGIN_PATH_ENV) {
+			this.search_paths.extend(env::split_paths(&paths));
+		}
+		this
+	}
+	
+	/// Adds a directory to the search paths
+	pub fn add_search_path(&mut self, path: impl Into<PathBuf>) -> &mut Self {
+		self.search_paths.push(path.into());
+		self
+	}
+	/// The search paths
+	pub fn search_paths(&self) -> &[PathBuf] {
+		&self.search_paths
+	}
+	
+	/// Scans all search paths and loads every library that is not loaded yet
+	///
+	/// Libraries that cannot be loaded or initialized don't stop the scan; they are collected and
+	/// can be queried using `failures` until the next scan.
+	pub fn scan(&mut self) -> &mut Self {
+		self.failures.clear();
+		for library in self.libraries() {
+			// Skip libraries that have already been loaded
+			if self.plugins.values().any(|e| e.path == library) {
+				continue;
+			}
+			
+			// Load the library and index it by its ID
+			let plugin = Plugin::load(&library).and_then(|p| Ok((p.id()?, p)));
+			match plugin {
+				Ok((id, _)) if self.plugins.contains_key(&id) => {
+					let error = KyncError::new(KyncErrorKind::LoadingError,
+						format!("A plugin with the same ID has already been loaded from \"{}\"",
+							self.plugins[&id].path.display()))
+						.with_path(&library).with_plugin_id(&id);
+					self.failures.push((library, error))
+				},
+				Ok((id, plugin)) => { self.plugins.insert(id, Entry { path: library, plugin }); },
+				Err(e) => self.failures.push((library, e))
+			}
+		}
+		self
+	}
+	/// The libraries in the search paths that have the current OS' dynamic library extension
+	fn libraries(&self) -> Vec<PathBuf> {
+		let mut libraries = Vec::new();
+		for dir in self.search_paths.iter().filter_map(|p| fs::read_dir(p).ok()) {
+			let mut files: Vec<PathBuf> = dir.filter_map(|e| e.ok()).map(|e| e.path())
+				.filter(|p| p.is_file())
+				.filter(|p| p.extension().map(|e| e == os_default_suffix()).unwrap_or(false))
+				.collect();
+			files.sort();
+			libraries.extend(files);
+		}
+		libraries
+	}
+	
+	/// The libraries that failed to load or to initialize during the last scan and their errors
+	pub fn failures(&self) -> &[(PathBuf, KyncError)] {
+		&self.failures
+	}
+	
+	/// Gets a plugin by its ID
+	pub fn get_by_id(&self, id: &[u8]) -> Option<&Plugin> {
+		self.plugins.get(id).map(|e| &e.plugin)
+	}
+	/// Lists the IDs and paths of all loaded plugins
+	pub fn list(&self) -> impl Iterator<Item = (&[u8], &Path)> {
+		self.plugins.iter().map(|(id, e)| (id.as_slice(), e.path.as_path()))
+	}
+	/// Finds the plugin that created the capsule envelope `envelope`
+	pub fn find_for_capsule(&self, envelope: &[u8]) -> Result<&Plugin, KyncError> {
+		Capsule::parse(envelope)?.find(self.plugins.values().map(|e| &e.plugin))
+	}
+}
@@ -0,0 +1,371 @@
+use crate::{
+	ErrorCode, KeyCapsule, KyncError, KyncErrorKind, SecretBytes,
+	plugin::{ DEFAULT_TIMEOUT, call_with_timeout }
+};
+use std::{
+	sync::Mutex, time::Duration, convert::TryInto,
+	path::{ Path, PathBuf },
+	io::{ self, Read, Write },
+	process::{ Child, ChildStdin, ChildStdout, Command, Stdio }
+};
+
+
+/// An error message indicating that the host process died or is unreachable
+const ERR_HOST_DIED: &str = "The plugin host process died or is unreachable";
+/// An error message indicating that the host sent an invalid response
+const ERR_HOST_PROTOCOL: &str = "The plugin host sent an invalid response";
+
+
+/// The request opcodes
+mod opcode {
+	pub const ID: u8 = 0x01;
+	pub const CONFIGS: u8 = 0x02;
+	pub const SET_CONTEXT: u8 = 0x03;
+	pub const AUTH_INFO_PROTECT: u8 = 0x04;
+	pub const AUTH_INFO_RECOVER: u8 = 0x05;
+	pub const PROTECT: u8 = 0x06;
+	pub const RECOVER: u8 = 0x07;
+}
+/// The response status codes
+mod status {
+	pub const OK: u8 = 0x00;
+	pub const ERROR: u8 = 0x01;
+}
+
+
+/// A message consisting of a tag (an opcode or a status code) and some fields
+///
+/// Wire format: `tag[1] || count[8] || (len[8] || field)*` with all integers as big endian
+///
+/// Since fields may contain secrets, they are stored in zeroizing buffers and messages are not sent
+/// through intermediate `BufReader`s or `BufWriter`s (this is why `kync-host` serves over the raw
+/// stdio file descriptors instead of the buffered `io::stdin()` and `io::stdout()`).
+struct Message {
+	tag: u8,
+	fields: Vec<SecretBytes>
+}
+impl Message {
+	/// Creates a new message
+	fn new(tag: u8, fields: Vec<SecretBytes>) -> Self {
+		Self { tag, fields }
+	}
+	
+	/// Reads a message from `source`
+	fn read(source: &mut impl Read) -> io::Result<Self> {
+		let mut tag = [0; 1];
+		source.read_exact(&mut tag)?;
+		
+		let count = Self::read_u64(source)?;
+		let mut fields = Vec::new();
+		for _ in 0..count {
+			let len = Self::read_u64(source)?;
+			fields.push(Self::read_field(source, len)?);
+		}
+		Ok(Self { tag: tag[0], fields })
+	}
+	/// Reads a `len` bytes long field from `source` in chunks (to avoid huge allocations for a bogus
+	/// `len`)
+	fn read_field(source: &mut impl Read, len: u64) -> io::Result<SecretBytes> {
+		let (mut field, mut chunk) = (SecretBytes::new(), SecretBytes::from(vec![0; 4096]));
+		let mut remaining = len;
+		while remaining > 0 {
+			let chunk = &mut chunk[..remaining.min(4096) as usize];
+			source.read_exact(chunk)?;
+			field.try_extend_from_slice(chunk)?;
+			remaining -= chunk.len() as u64;
+		}
+		Ok(field)
+	}
+	/// Reads a big endian `u64` from `source`
+	fn read_u64(source: &mut impl Read) -> io::Result<u64> {
+		let mut buf = [0; 8];
+		source.read_exact(&mut buf)?;
+		Ok(u64::from_be_bytes(buf))
+	}
+	
+	/// Writes the message to `sink` with a single `write_all`-call and flushes it
+	fn write(&self, sink: &mut impl Write) -> io::Result<()> {
+		let len = 9 + self.fields.iter().map(|f| 8 + f.len()).sum::<usize>();
+		let mut message = SecretBytes::with_capacity(len);
+		message.extend_from_slice(&[self.tag]);
+		message.extend_from_slice(&(self.fields.len() as u64).to_be_bytes());
+		for field in self.fields.iter() {
+			message.extend_from_slice(&(field.len() as u64).to_be_bytes());
+			message.extend_from_slice(field);
+		}
+		sink.write_all(&message)?;
+		sink.flush()
+	}
+}
+
+
+/// Serves the API calls of `capsule` over `source` and `sink` until `source` is closed
+///
+/// This is the server side of `RemotePlugin` and is used by the `kync-host` binary.
+pub fn serve(capsule: &impl KeyCapsule, mut source: impl Read, mut sink: impl Write)
+	-> io::Result<()>
+{
+	Message::new(status::OK, Vec::new()).write(&mut sink)?;
+	
+	loop {
+		// Read the next request or stop if the source has been closed
+		let request = match Message::read(&mut source) {
+			Ok(request) => request,
+			Err(ref e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(()),
+			Err(e) => return Err(e)
+		};
+		
+		// Dispatch the request
+		let field = |i: usize| request.fields.get(i).map(|f| f.as_ref())
+			.ok_or_else(|| io::Error::from(io::ErrorKind::InvalidData));
+		let public = |fields: Vec<Vec<u8>>| fields.into_iter().map(SecretBytes::from).collect();
+		let auth_info = |(required, retries): (bool, u64)| {
+			public(vec![vec![required as u8], retries.to_be_bytes().to_vec()])
+		};
+		let result = match request.tag {
+			opcode::ID => capsule.id().map(|id| public(vec![id])),
+			opcode::CONFIGS => capsule.configs().map(public),
+			opcode::SET_CONTEXT => capsule.set_context(field(0)?).map(|_| Vec::new()),
+			opcode::AUTH_INFO_PROTECT => capsule.auth_info_protect(field(0)?).map(auth_info),
+			opcode::AUTH_INFO_RECOVER => capsule.auth_info_recover(field(0)?).map(auth_info),
+			opcode::PROTECT => capsule.protect(field(0)?, field(1)?, field(2).ok())
+				.map(|p| public(vec![p])),
+			opcode::RECOVER => capsule.recover(field(0)?, field(1).ok()).map(|r| vec![r]),
+			_ => Err(io::Error::from(io::ErrorKind::InvalidData))?
+		};
+		
+		// Send the response
+		let response = match result {
+			Ok(fields) => Message::new(status::OK, fields),
+			Err(e) => Message::new(status::ERROR, error_fields(&e))
+		};
+		response.write(&mut sink)?;
+	}
+}
+
+/// Reports an error that occurred before `serve` could be called (e.g. a loading error) over `sink`
+pub fn report(error: KyncError, mut sink: impl Write) -> io::Result<()> {
+	let response = Message::new(status::ERROR, error_fields(&error));
+	response.write(&mut sink)
+}
+/// Encodes an error as `message || code` where the code field is omitted if there is no code
+fn error_fields(error: &KyncError) -> Vec<SecretBytes> {
+	let mut fields = vec![SecretBytes::from(error.message().as_bytes())];
+	if let Some(code) = error.code() {
+		fields.push(SecretBytes::from(vec![code.to_raw()]));
+	}
+	fields
+}
+
+
+/// The pipes to the host process
+struct Pipes {
+	stdin: ChildStdin,
+	stdout: ChildStdout
+}
+impl Pipes {
+	/// Writes `request` if any and reads the response
+	fn exchange(&mut self, request: Option<Message>) -> io::Result<Message> {
+		if let Some(request) = request {
+			request.write(&mut self.stdin)?;
+		}
+		Message::read(&mut self.stdout)
+	}
+}
+
+
+/// The host process and its pipes (the pipes are `None` if the host is dead)
+struct Host {
+	child: Child,
+	pipes: Option<Pipes>
+}
+impl Host {
+	/// Sends a request and receives the response fields
+	fn call(&mut self, request: Option<Message>, kind: KyncErrorKind, timeout: Option<Duration>)
+		-> Result<Vec<SecretBytes>, KyncError>
+	{
+		match self.exchange(request, timeout)? {
+			Message { tag: status::OK, fields } => Ok(fields),
+			Message { tag: status::ERROR, fields } => match fields.as_slice() {
+				[message] => Err(KyncError::new(kind, String::from_utf8_lossy(message))),
+				[message, code] if code.len() == 1 => {
+					let error = KyncError::new(kind, String::from_utf8_lossy(message));
+					match ErrorCode::from_raw(code[0]) {
+						Some(code) => Err(error.with_code(code)),
+						None => Err(error)
+					}
+				},
+				_ => Err(KyncError::new(KyncErrorKind::HostError, ERR_HOST_PROTOCOL))
+			},
+			_ => Err(KyncError::new(KyncErrorKind::HostError, ERR_HOST_PROTOCOL))
+		}
+	}
+	/// Writes `request` if any and reads the response within `timeout`
+	fn exchange(&mut self, request: Option<Message>, timeout: Option<Duration>)
+		-> Result<Message, KyncError>
+	{
+		let mut pipes = self.pipes.take()
+			.ok_or_else(|| KyncError::new(KyncErrorKind::HostError, ERR_HOST_DIED))?;
+		let result = call_with_timeout(timeout, move || {
+			let response = pipes.exchange(request);
+			Ok((pipes, response))
+		});
+		match result {
+			Ok((pipes, Ok(response))) => {
+				self.pipes = Some(pipes);
+				Ok(response)
+			},
+			Ok((_, Err(e))) => {
+				self.kill();
+				Err(KyncError::new(KyncErrorKind::HostError, ERR_HOST_DIED).with_source(e))
+			},
+			Err(e) => {
+				// Kill the host which also unblocks the abandoned worker
+				self.kill();
+				Err(e)
+			}
+		}
+	}
+	/// Ensures that the host is really gone
+	fn kill(&mut self) {
+		self.pipes = None;
+		let _ = self.child.kill();
+		let _ = self.child.wait();
+	}
+}
+impl Drop for Host {
+	fn drop(&mut self) {
+		self.kill()
+	}
+}
+
+
+/// A plugin that is loaded into a separate `kync-host` process so that a crashing plugin cannot
+/// take down the app
+///
+/// Every call is bounded by a deadline (`DEFAULT_TIMEOUT` by default); if a call exceeds its
+/// deadline, the host is killed and the call fails with `KyncErrorKind::Timeout`.
+pub struct RemotePlugin {
+	host: Mutex<Host>,
+	timeout: Option<Duration>,
+	path: PathBuf,
+	id: Option<Vec<u8>>
+}
+impl RemotePlugin {
+	/// Spawns the `host` binary (usually `kync-host`) and loads `plugin` into it
+	pub fn spawn(host: impl AsRef<Path>, plugin: impl AsRef<Path>) -> Result<Self, KyncError> {
+		let path = plugin.as_ref();
+		Self::spawn_host(host.as_ref(), path).map_err(|e| e.with_path(path))
+	}
+	/// Spawns the host process and waits until it has loaded the plugin
+	fn spawn_host(host: &Path, path: &Path) -> Result<Self, KyncError> {
+		let mut child = Command::new(host).arg(path)
+			.stdin(Stdio::piped()).stdout(Stdio::piped()).stderr(Stdio::inherit())
+			.spawn().map_err(|e| {
+				KyncError::new(KyncErrorKind::HostError, "Failed to spawn the plugin host process")
+					.with_source(e)
+			})?;
+		let (stdin, stdout) = (child.stdin.take().unwrap(), child.stdout.take().unwrap());
+		let pipes = Pipes { stdin, stdout };
+		
+		// Wait until the host has loaded the plugin
+		let mut host = Host { child, pipes: Some(pipes) };
+		host.call(None, KyncErrorKind::LoadingError, Some(DEFAULT_TIMEOUT))?;
+		let (host, timeout) = (Mutex::new(host), Some(DEFAULT_TIMEOUT));
+		let mut this = Self { host, timeout, path: path.into(), id: None };
+		
+		// Query the ID to give errors some context
+		this.id = this.id().ok();
+		Ok(this)
+	}
+	
+	/// Sets the deadline for every API call (`None` disables the deadline)
+	pub fn set_timeout(&mut self, timeout: Option<Duration>) -> &mut Self {
+		self.timeout = timeout;
+		self
+	}
+	/// The deadline for every API call
+	pub fn timeout(&self) -> Option<Duration> {
+		self.timeout
+	}
+	
+	/// The path of the plugin loaded into the host
+	pub fn path(&self) -> &Path {
+		&self.path
+	}
+	
+	/// Performs a call
+	fn call(&self, opcode: u8, fields: Vec<SecretBytes>, kind: KyncErrorKind)
+		-> Result<Vec<SecretBytes>, KyncError>
+	{
+		let mut host = self.host.lock().unwrap_or_else(|e| e.into_inner());
+		host.call(Some(Message::new(opcode, fields)), kind, self.timeout).map_err(|e| {
+			let e = e.with_path(&self.path);
+			match self.id.as_ref() {
+				Some(id) => e.with_plugin_id(id),
+				None => e
+			}
+		})
+	}
+	/// Performs a call that returns exactly one field
+	fn call_one(&self, opcode: u8, fields: Vec<SecretBytes>, kind: KyncErrorKind)
+		-> Result<SecretBytes, KyncError>
+	{
+		match self.call(opcode, fields, kind)?.as_mut_slice() {
+			[field] => Ok(std::mem::take(field)),
+			_ => Err(KyncError::new(KyncErrorKind::HostError, ERR_HOST_PROTOCOL))
+		}
+	}
+	/// Performs an `auth_info`-call
+	fn call_auth_info(&self, opcode: u8, config: &[u8]) -> Result<(bool, u64), KyncError> {
+		let fields = self.call(opcode, vec![config.into()], KyncErrorKind::AuthInfoError)?;
+		match fields.as_slice() {
+			[required, retries] if required.len() == 1 && retries.len() == 8 =>
+				Ok((required[0] != 0, u64::from_be_bytes(retries.as_ref().try_into().unwrap()))),
+			_ => Err(KyncError::new(KyncErrorKind::HostError, ERR_HOST_PROTOCOL))
+		}
+	}
+}
+impl KeyCapsule for RemotePlugin {
+	fn id(&self) -> Result<Vec<u8>, KyncError> {
+		Ok(self.call_one(opcode::ID, Vec::new(), KyncErrorKind::IdError)?.to_vec())
+	}
+	fn cached_id(&self) -> Result<Vec<u8>, KyncError> {
+		match self.id.as_ref() {
+			Some(id) => Ok(id.clone()),
+			None => self.id()
+		}
+	}
+	
+	fn configs(&self) -> Result<Vec<Vec<u8>>, KyncError> {
+		let configs = self.call(opcode::CONFIGS, Vec::new(), KyncErrorKind::ConfigsError)?;
+		Ok(configs.iter().map(|c| c.to_vec()).collect())
+	}
+	
+	fn set_context(&self, context: &[u8]) -> Result<(), KyncError> {
+		self.call(opcode::SET_CONTEXT, vec![context.into()], KyncErrorKind::SetContextError)?;
+		Ok(())
+	}
+	
+	fn auth_info_protect(&self, config: &[u8]) -> Result<(bool, u64), KyncError> {
+		self.call_auth_info(opcode::AUTH_INFO_PROTECT, config)
+	}
+	
+	fn auth_info_recover(&self, config: &[u8]) -> Result<(bool, u64), KyncError> {
+		self.call_auth_info(opcode::AUTH_INFO_RECOVER, config)
+	}
+	
+	fn protect(&self, data: &[u8], config: &[u8], auth: Option<&[u8]>)
+		-> Result<Vec<u8>, KyncError>
+	{
+		let mut fields = vec![data.into(), config.into()];
+		fields.extend(auth.map(SecretBytes::from));
+		Ok(self.call_one(opcode::PROTECT, fields, KyncErrorKind::ProtectError)?.to_vec())
+	}
+	
+	fn recover(&self, data: &[u8], auth: Option<&[u8]>) -> Result<SecretBytes, KyncError> {
+		let mut fields = vec![data.into()];
+		fields.extend(auth.map(SecretBytes::from));
+		self.call_one(opcode::RECOVER, fields, KyncErrorKind::RecoverError)
+	}
+}
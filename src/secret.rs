@@ -0,0 +1,142 @@
+use crate::memory::{ Region, zeroize };
+use std::{
+	cmp, io, ptr, slice, fmt::{ self, Debug, Formatter },
+	ops::{ Deref, DerefMut }
+};
+
+
+/// A byte buffer for secret data that zeroes its memory on drop
+///
+/// The buffer never reallocates while holding secret data; if it needs to grow, the data is copied
+/// into a new allocation and the old allocation is zeroed before it is freed. `Debug` does not
+/// print the contents and equality is checked in constant time.
+///
+/// With the `locked-memory` feature, the memory is locked into RAM and (on Linux) excluded from
+/// core dumps; the `guarded-memory` feature additionally surrounds it with guard pages.
+pub struct SecretBytes {
+	region: Region,
+	len: usize
+}
+impl SecretBytes {
+	/// Creates a new empty buffer
+	pub fn new() -> Self {
+		Self { region: Region::empty(), len: 0 }
+	}
+	/// Creates a new empty buffer that can hold at least `capacity` bytes without growing
+	///
+	/// _Note: Panics if the memory cannot be allocated; see `try_with_capacity`._
+	pub fn with_capacity(capacity: usize) -> Self {
+		Self::try_with_capacity(capacity).expect("Failed to allocate secret memory")
+	}
+	/// Creates a new empty buffer that can hold at least `capacity` bytes without growing or
+	/// returns the OS error if the memory cannot be allocated
+	pub fn try_with_capacity(capacity: usize) -> io::Result<Self> {
+		Ok(Self { region: Region::new(capacity)?, len: 0 })
+	}
+	
+	/// Ensures that at least `additional` more bytes can be appended without growing
+	///
+	/// _Note: Panics if the memory cannot be allocated; see `try_reserve`._
+	pub fn reserve(&mut self, additional: usize) {
+		self.try_reserve(additional).expect("Failed to allocate secret memory")
+	}
+	/// Ensures that at least `additional` more bytes can be appended without growing or returns the
+	/// OS error if the memory cannot be allocated
+	pub fn try_reserve(&mut self, additional: usize) -> io::Result<()> {
+		if self.capacity() - self.len < additional {
+			let required = self.len.checked_add(additional)
+				.ok_or_else(|| io::Error::from(io::ErrorKind::OutOfMemory))?;
+			let mut grown = Self::try_with_capacity(cmp::max(required, self.capacity() * 2))?;
+			grown.extend_from_slice(self);
+			
+			// Replacing `self` drops and thus zeroes the old allocation
+			*self = grown;
+		}
+		Ok(())
+	}
+	/// Appends `data` to the buffer
+	///
+	/// _Note: Panics if the memory cannot be allocated; see `try_extend_from_slice`._
+	pub fn extend_from_slice(&mut self, data: &[u8]) {
+		self.try_extend_from_slice(data).expect("Failed to allocate secret memory")
+	}
+	/// Appends `data` to the buffer or returns the OS error if the memory cannot be allocated
+	pub fn try_extend_from_slice(&mut self, data: &[u8]) -> io::Result<()> {
+		self.try_reserve(data.len())?;
+		let end = unsafe{ self.region.as_ptr().add(self.len) };
+		unsafe{ ptr::copy_nonoverlapping(data.as_ptr(), end, data.len()) };
+		self.len += data.len();
+		Ok(())
+	}
+	/// Zeroes and removes all bytes but keeps the allocation
+	pub fn clear(&mut self) {
+		unsafe{ zeroize(self.region.as_ptr(), self.len) };
+		self.len = 0;
+	}
+	
+	/// The amount of bytes that can be stored without growing
+	pub fn capacity(&self) -> usize {
+		self.region.capacity()
+	}
+	/// Whether the memory is locked into RAM (always `false` without the `locked-memory` feature or
+	/// if the OS refused to lock the memory)
+	pub fn is_locked(&self) -> bool {
+		self.region.is_locked()
+	}
+}
+impl Default for SecretBytes {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+impl Deref for SecretBytes {
+	type Target = [u8];
+	fn deref(&self) -> &Self::Target {
+		unsafe{ slice::from_raw_parts(self.region.as_ptr(), self.len) }
+	}
+}
+impl DerefMut for SecretBytes {
+	fn deref_mut(&mut self) -> &mut Self::Target {
+		unsafe{ slice::from_raw_parts_mut(self.region.as_ptr(), self.len) }
+	}
+}
+impl AsRef<[u8]> for SecretBytes {
+	fn as_ref(&self) -> &[u8] {
+		self
+	}
+}
+impl From<Vec<u8>> for SecretBytes {
+	/// Copies `vec` into a new buffer and zeroes `vec`
+	fn from(mut vec: Vec<u8>) -> Self {
+		let this = Self::from(vec.as_slice());
+		unsafe{ zeroize(vec.as_mut_ptr(), vec.capacity()) };
+		this
+	}
+}
+impl From<&[u8]> for SecretBytes {
+	fn from(slice: &[u8]) -> Self {
+		let mut this = Self::with_capacity(slice.len());
+		this.extend_from_slice(slice);
+		this
+	}
+}
+impl Clone for SecretBytes {
+	fn clone(&self) -> Self {
+		Self::from(self.as_ref())
+	}
+}
+impl PartialEq for SecretBytes {
+	fn eq(&self, other: &Self) -> bool {
+		if self.len != other.len {
+			return false;
+		}
+		let diff = self.iter().zip(other.iter()).fold(0, |diff, (a, b)| diff | (a ^ b));
+		unsafe{ ptr::read_volatile(&diff) == 0 }
+	}
+}
+impl Eq for SecretBytes {}
+impl Debug for SecretBytes {
+	fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+		f.write_str("SecretBytes(<redacted>)")
+	}
+}
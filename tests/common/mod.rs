@@ -0,0 +1,61 @@
+//! Fixtures that are shared by the integration tests
+#![allow(dead_code)]
+
+use kync::{
+	Plugin,
+	plugin::{ os_default_prefix, os_default_suffix }
+};
+use std::{
+	env, collections::HashSet, path::PathBuf, process::Command,
+	sync::Mutex
+};
+
+
+/// The plugins that have already been built by this test binary
+static BUILT: Mutex<Option<HashSet<String>>> = Mutex::new(None);
+
+
+/// Builds the plugin crate `name` once per test binary
+///
+/// The plugins are workspace members but no dev-dependencies (a `cdylib` that is built both as
+/// workspace member and as dependency collides in the target directory), so `cargo test` does not
+/// necessarily build them.
+fn build_plugin(name: &str) {
+	let mut built = BUILT.lock().unwrap_or_else(|e| e.into_inner());
+	if built.get_or_insert_with(HashSet::new).contains(name) {
+		return
+	}
+	
+	let mut cargo = Command::new(env!("CARGO"));
+	cargo.args(["build", "--quiet", "--package", name]);
+	if !cfg!(debug_assertions) {
+		cargo.arg("--release");
+	}
+	let status = cargo.status().expect("Failed to run cargo");
+	assert!(status.success(), "Failed to build the plugin {}", name);
+	built.get_or_insert_with(HashSet::new).insert(name.to_string());
+}
+
+
+/// The path of the plugin library `name` (e.g. `kync_test_plugin`) in the target directory; the
+/// plugin is built first if necessary
+pub fn plugin_path(name: &str) -> PathBuf {
+	build_plugin(name);
+	let mut path = PathBuf::new();
+	path.push("target");
+	path.push(if cfg!(debug_assertions) { "debug" } else { "release" });
+	path.push(format!("{}{}.{}", os_default_prefix(), name, os_default_suffix()));
+	path
+}
+
+/// Loads the plugin library `name`
+pub fn load_plugin(name: &str) -> Plugin {
+	Plugin::load(plugin_path(name)).unwrap()
+}
+
+
+/// Checks if the external program `name` is installed (i.e. it can be found in `$PATH`)
+pub fn is_installed(name: &str) -> bool {
+	let paths = env::var_os("PATH").unwrap_or_default();
+	env::split_paths(&paths).any(|dir| dir.join(name).is_file())
+}
@@ -0,0 +1,54 @@
+mod common;
+
+use kync::{ Capsule, KyncErrorKind, KeyCapsule, Plugin };
+
+
+const FORMAT_UID: &[u8] = b"TestCapsuleFormat.3A0351A7-FE90-4383-9E68-FCC20033D5F1";
+const USER_SECRET: Option<&[u8]> = Some(b"Testolope");
+const KEY: &[u8] = b"2nwBK-EkfXW-yWSQv-Vkab3-USHvX-WNJxa-GeXFJ-ecsjJ-imnft";
+
+
+#[test]
+fn test_roundtrip() {
+	let plugin = common::load_plugin("kync_test_plugin");
+	let envelope = Capsule::seal(&plugin, KEY, b"Default", USER_SECRET).unwrap();
+	
+	// Validate the recorded metadata
+	let capsule = Capsule::parse(&envelope).unwrap();
+	assert_eq!(capsule.id(), FORMAT_UID);
+	assert_eq!(capsule.config(), b"Default");
+	assert_eq!(capsule.api_version(), plugin.api_version());
+	assert_eq!(capsule.api_version(), 0x02_00);
+	assert_eq!(capsule.to_bytes(), envelope);
+	
+	// Open the envelope
+	let plugins: Vec<Box<dyn KeyCapsule>> = vec![Box::new(plugin)];
+	let recovered = Capsule::open(plugins.iter().map(|p| p.as_ref()), &envelope, USER_SECRET).unwrap();
+	assert_eq!(recovered.as_ref(), KEY);
+}
+
+
+#[test]
+fn test_errors() {
+	let plugin = common::load_plugin("kync_test_plugin");
+	let envelope = Capsule::seal(&plugin, KEY, b"Default", USER_SECRET).unwrap();
+	
+	// No matching plugin
+	let err = Capsule::open(Vec::<&Plugin>::new(), &envelope, USER_SECRET).unwrap_err();
+	assert!(matches!(err.kind(), KyncErrorKind::PluginNotFound));
+	
+	// Invalid and truncated envelopes
+	let mut invalid = envelope.clone();
+	invalid[0] ^= 0xFF;
+	let err = Capsule::open(Some(&plugin), &invalid, USER_SECRET).unwrap_err();
+	assert!(matches!(err.kind(), KyncErrorKind::FormatError));
+	
+	let err = Capsule::open(Some(&plugin), &envelope[..envelope.len() - 1], USER_SECRET).unwrap_err();
+	assert!(matches!(err.kind(), KyncErrorKind::FormatError));
+	
+	// Unknown API versions
+	let mut unknown = envelope.clone();
+	unknown[5..7].copy_from_slice(&0x7F_00u16.to_be_bytes());
+	let err = Capsule::open(Some(&plugin), &unknown, USER_SECRET).unwrap_err();
+	assert!(matches!(err.kind(), KyncErrorKind::FormatError));
+}
@@ -0,0 +1,145 @@
+mod common;
+
+use kync::plugin::{ os_default_prefix, os_default_suffix };
+use std::{
+	env, fs, io::Write, path::PathBuf,
+	process::{ Command, Output, Stdio }
+};
+
+
+const FORMAT_UID: &str = "TestCapsuleFormat.3A0351A7-FE90-4383-9E68-FCC20033D5F1";
+const KEY: &[u8] = b"2nwBK-EkfXW-yWSQv-Vkab3-USHvX-WNJxa-GeXFJ-ecsjJ-imnft";
+
+
+/// Creates a plugin directory `name` that contains the test plugin and returns the directory and
+/// the plugin path
+fn plugin_dir(name: &str) -> (PathBuf, PathBuf) {
+	let library = format!("{}kync_test_plugin.{}", os_default_prefix(), os_default_suffix());
+	let source = common::plugin_path("kync_test_plugin");
+	
+	let dir = env::temp_dir().join(format!("kync_test_cli_{}_{}", name, std::process::id()));
+	fs::create_dir_all(&dir).unwrap();
+	fs::copy(source, dir.join(&library)).unwrap();
+	let plugin = dir.join(&library);
+	(dir, plugin)
+}
+
+/// Runs `kync` with `args`, `stdin` and `KYNC_TEST_AUTH` set to `auth`
+fn kync(args: &[&str], stdin: &[u8], auth: &str) -> Output {
+	let mut child = Command::new(env!("CARGO_BIN_EXE_kync"))
+		.args(args).env("KYNC_TEST_AUTH", auth)
+		.stdin(Stdio::piped()).stdout(Stdio::piped()).stderr(Stdio::piped())
+		.spawn().unwrap();
+	child.stdin.take().unwrap().write_all(stdin).unwrap();
+	child.wait_with_output().unwrap()
+}
+
+
+#[test]
+fn test() {
+	let (dir, plugin) = plugin_dir("roundtrip");
+	let (dir, plugin) = (dir.to_str().unwrap(), plugin.to_str().unwrap());
+	
+	// Protect the key from stdin to stdout
+	let output = kync(&["protect", "--plugin", plugin, "--auth-env", "KYNC_TEST_AUTH"], KEY,
+		"Testolope");
+	assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+	let envelope = output.stdout;
+	assert!(!envelope.is_empty());
+	
+	// Recover the key using the plugin directory and write it to a file
+	let out = env::temp_dir().join(format!("kync_test_cli_{}.key", std::process::id()));
+	let output = kync(&["recover", "--plugin-dir", dir, "--out", out.to_str().unwrap(),
+		"--auth-env", "KYNC_TEST_AUTH"], &envelope, "Testolope");
+	assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+	assert_eq!(fs::read(&out).unwrap(), KEY);
+	fs::remove_file(out).unwrap();
+	
+	// Recover the key using the plugin
+	let output = kync(&["recover", "--plugin", plugin, "--auth-env", "KYNC_TEST_AUTH"],
+		&envelope, "Testolope");
+	assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+	assert_eq!(output.stdout, KEY);
+	
+	// Use an invalid authentication
+	let output = kync(&["recover", "--plugin", plugin, "--auth-env", "KYNC_TEST_AUTH"],
+		&envelope, "Invalid");
+	assert_eq!(output.status.code(), Some(1));
+	assert!(String::from_utf8_lossy(&output.stderr).contains("Invalid authentication"));
+}
+
+#[test]
+fn test_out() {
+	let (dir, plugin) = plugin_dir("out");
+	let plugin = plugin.to_str().unwrap();
+	let out = dir.join("secret.key");
+	fs::write(&out, b"Existing").unwrap();
+	#[cfg(unix)]
+	fs::set_permissions(&out, std::os::unix::fs::PermissionsExt::from_mode(0o644)).unwrap();
+	
+	// A failed recovery leaves an existing output untouched
+	let output = kync(&["protect", "--plugin", plugin, "--auth-env", "KYNC_TEST_AUTH"], KEY,
+		"Testolope");
+	assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+	let envelope = output.stdout;
+	let output = kync(&["recover", "--plugin", plugin, "--out", out.to_str().unwrap(),
+		"--auth-env", "KYNC_TEST_AUTH"], &envelope, "Invalid");
+	assert_eq!(output.status.code(), Some(1));
+	assert_eq!(fs::read(&out).unwrap(), b"Existing");
+	
+	// A successful recovery replaces the output with a file that is only accessible by the owner
+	let output = kync(&["recover", "--plugin", plugin, "--out", out.to_str().unwrap(),
+		"--auth-env", "KYNC_TEST_AUTH"], &envelope, "Testolope");
+	assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+	assert_eq!(fs::read(&out).unwrap(), KEY);
+	#[cfg(unix)]
+	{
+		use std::os::unix::fs::PermissionsExt;
+		assert_eq!(fs::metadata(&out).unwrap().permissions().mode() & 0o777, 0o600);
+	}
+	
+	// No temporary files are left behind
+	assert_eq!(fs::read_dir(&dir).unwrap().count(), 2);
+	fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_info_list() {
+	let (dir, plugin) = plugin_dir("info");
+	let (dir, plugin) = (dir.to_str().unwrap(), plugin.to_str().unwrap());
+	
+	let output = kync(&["info", plugin], b"", "");
+	assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+	let info = String::from_utf8(output.stdout).unwrap();
+	assert!(info.contains(&format!("ID: {}", FORMAT_UID)));
+	assert!(info.contains("API version: 0x0200"));
+	assert!(info.contains("Default (protect: auth required, no known retry limit; recover: auth \
+		required, no known retry limit)"));
+	
+	let output = kync(&["list", dir], b"", "");
+	assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+	assert_eq!(String::from_utf8(output.stdout).unwrap(), format!("{}\t{}\n", FORMAT_UID, plugin));
+}
+
+#[test]
+fn test_usage() {
+	assert_eq!(kync(&[], b"", "").status.code(), Some(2));
+	assert_eq!(kync(&["protect"], b"", "").status.code(), Some(2));
+	assert_eq!(kync(&["recover", "--in"], b"", "").status.code(), Some(2));
+	assert_eq!(kync(&["info", "--auth", "Testolope"], b"", "").status.code(), Some(2));
+	
+	// The authentication is never taken from a positional argument
+	let (dir, plugin) = plugin_dir("usage");
+	let plugin = plugin.to_str().unwrap();
+	let output = kync(&["protect", "--plugin", plugin, "Testolope"], b"", "");
+	assert_eq!(output.status.code(), Some(2));
+	assert!(!String::from_utf8_lossy(&output.stderr).contains("Testolope"));
+	assert_eq!(kync(&["info", plugin, "Testolope"], b"", "").status.code(), Some(2));
+	
+	// Stdio and negative numbers are no valid authentication file descriptors
+	for fd in ["-1", "0", "1", "2"].iter() {
+		let output = kync(&["protect", "--plugin", plugin, "--auth-fd", fd], b"", "");
+		assert_eq!(output.status.code(), Some(2), "{}", fd);
+	}
+	fs::remove_dir_all(dir).unwrap();
+}
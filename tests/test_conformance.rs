@@ -0,0 +1,38 @@
+mod common;
+
+use kync::conformance::Harness;
+use std::process::Command;
+
+
+#[test]
+fn test_conformance() {
+	// The test plugin passes all checks
+	let path = common::plugin_path("kync_test_plugin");
+	let report = Harness::new().set_auth(Some(b"Testolope")).run(&path);
+	assert!(report.passed(), "{}", report);
+	assert!(report.checks.iter().any(|c| c.name == "sink_errors_propagated"));
+	assert!(report.to_json().starts_with(r#"{"plugin":""#));
+	assert!(report.to_json().contains(r#""passed":true,"checks":[{"name":"load","passed":true"#));
+	
+	// Round-trips fail without authentication
+	let report = Harness::new().run(&path);
+	assert!(!report.passed());
+	let roundtrip = report.checks.iter().find(|c| c.name == "roundtrip").unwrap();
+	assert!(!roundtrip.passed);
+	assert!(roundtrip.message.contains("Missing authentication parameter"));
+	
+	// Invalid plugins fail to load
+	let report = Harness::new().run("Cargo.toml");
+	assert_eq!(report.checks.len(), 2);
+	assert!(!report.checks[0].passed);
+}
+
+
+#[test]
+fn test_binary() {
+	let output = Command::new(env!("CARGO_BIN_EXE_kync-conformance"))
+		.args(["--json", "--auth", "Testolope"]).arg(common::plugin_path("kync_test_plugin"))
+		.output().unwrap();
+	assert!(output.status.success());
+	assert!(String::from_utf8(output.stdout).unwrap().contains(r#""passed":true"#));
+}
@@ -0,0 +1,103 @@
+mod common;
+
+use kync::{ ErrorCode, KeyCapsule };
+use std::{ env, fs, path::{ Path, PathBuf }, process::Command, sync::Mutex };
+
+
+/// The GnuPG home is global, so the tests must not run concurrently
+static SERIAL: Mutex<()> = Mutex::new(());
+
+
+/// Runs `gpg` in `home` and returns its stdout
+fn gpg(home: &Path, args: &[&str]) -> Vec<u8> {
+	let output = Command::new("gpg").arg("--homedir").arg(home).arg("--batch").args(args)
+		.output().expect("GnuPG is not installed");
+	assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+	output.stdout
+}
+
+/// Creates a throwaway GnuPG home with a new key and returns the home and the key fingerprint
+fn setup_gnupg() -> (PathBuf, String) {
+	let home = env::temp_dir().join(format!("kync_test_gpg_{}", std::process::id()));
+	let _ = fs::remove_dir_all(&home);
+	fs::create_dir_all(&home).unwrap();
+	#[cfg(unix)]
+	fs::set_permissions(&home, std::os::unix::fs::PermissionsExt::from_mode(0o700)).unwrap();
+	
+	// Disable the passphrase cache and make sure that pinentry can never block the tests
+	let agent_conf = "default-cache-ttl 0\nmax-cache-ttl 0\nallow-loopback-pinentry\n\
+		pinentry-program /bin/false\n";
+	fs::write(home.join("gpg-agent.conf"), agent_conf).unwrap();
+	
+	// Generate the key and get its fingerprint
+	gpg(&home, &["--pinentry-mode", "loopback", "--passphrase", PASSPHRASE, "--quick-gen-key",
+		"KyNc Test <kync@example.invalid>", "future-default", "default", "never"]);
+	let listing = gpg(&home, &["--with-colons", "--list-secret-keys"]);
+	let fingerprint = String::from_utf8(listing).unwrap().lines()
+		.find(|line| line.starts_with("fpr:"))
+		.map(|line| line.split(':').nth(9).unwrap().to_string())
+		.unwrap();
+	(home, fingerprint)
+}
+
+
+const FORMAT_UID: &[u8] = b"GpgCapsuleFormat.4783A415-199E-4388-8314-95759018DE3E";
+const PASSPHRASE: &str = "Correct Horse Battery Staple";
+const KEY: &[u8] = b"2nwBK-EkfXW-yWSQv-Vkab3-USHvX-WNJxa-GeXFJ-ecsjJ-imnft";
+
+
+#[test]
+fn test() {
+	let _serial = SERIAL.lock().unwrap_or_else(|e| e.into_inner());
+	let plugin = common::load_plugin("kync_gpg_plugin");
+	assert_eq!(plugin.id().unwrap(), FORMAT_UID);
+	let err = plugin.set_context(b"/nonexistent/.gnupg").unwrap_err();
+	assert_eq!(err.code(), Some(ErrorCode::DeviceUnavailable));
+}
+
+
+#[test]
+fn test_gnupg() {
+	let _serial = SERIAL.lock().unwrap_or_else(|e| e.into_inner());
+	if !common::is_installed("gpg") {
+		return eprintln!("GnuPG is not installed; skipping the key tests")
+	}
+	
+	let plugin = common::load_plugin("kync_gpg_plugin");
+	let (home, fingerprint) = setup_gnupg();
+	let config = fingerprint.as_bytes();
+	plugin.set_context(home.to_str().unwrap().as_bytes()).unwrap();
+	assert_eq!(plugin.configs().unwrap(), vec![config.to_vec()]);
+	assert_eq!(plugin.auth_info_protect(config).unwrap(), (false, u64::MAX));
+	assert_eq!(plugin.auth_info_recover(config).unwrap(), (false, u64::MAX));
+	
+	// Protect and recover a key with a loopback passphrase
+	let protected = plugin.protect(KEY, config, None).unwrap();
+	let recovered = plugin.recover(&protected, Some(PASSPHRASE.as_bytes())).unwrap();
+	assert_eq!(recovered.as_ref(), KEY);
+	
+	// Authentication errors (the pinentry of the test home always fails)
+	let err = plugin.recover(&protected, Some(b"Invalid passphrase")).unwrap_err();
+	assert_eq!(err.code(), Some(ErrorCode::AuthFailed));
+	let err = plugin.recover(&protected, None).unwrap_err();
+	assert_eq!(err.code(), Some(ErrorCode::AuthRequired));
+	
+	// Config and capsule errors
+	let err = plugin.protect(KEY, &[b'0'; 40], None).unwrap_err();
+	assert_eq!(err.code(), Some(ErrorCode::InvalidConfig));
+	let err = plugin.protect(KEY, b"Invalid", None).unwrap_err();
+	assert_eq!(err.code(), Some(ErrorCode::InvalidConfig));
+	let err = plugin.recover(b"Invalid capsule", None).unwrap_err();
+	assert_eq!(err.code(), Some(ErrorCode::InvalidCapsule));
+	
+	// Unencrypted messages are rejected although gpg "decrypts" them successfully
+	let literal = home.join("forged.bin");
+	fs::write(&literal, b"Forged secret").unwrap();
+	let forged = gpg(&home, &["--store", "--output", "-", literal.to_str().unwrap()]);
+	let err = plugin.recover(&forged, None).unwrap_err();
+	assert_eq!(err.code(), Some(ErrorCode::InvalidCapsule));
+	
+	// Stop the agent of the throwaway home and remove it
+	let _ = Command::new("gpgconf").args(["--kill", "gpg-agent"]).env("GNUPGHOME", &home).status();
+	fs::remove_dir_all(&home).unwrap();
+}
@@ -0,0 +1,69 @@
+mod common;
+
+use kync::{ KeyCapsule, KyncError, KyncErrorKind, SecretBytes };
+
+
+/// An in-process backend that mirrors the test plugin
+struct ReverseCapsule;
+impl ReverseCapsule {
+	/// Validates the authentication
+	fn check_auth(auth: Option<&[u8]>, kind: KyncErrorKind) -> Result<(), KyncError> {
+		match auth {
+			Some(USER_SECRET) => Ok(()),
+			_ => Err(KyncError::new(kind, "Invalid authentication"))
+		}
+	}
+}
+impl KeyCapsule for ReverseCapsule {
+	fn id(&self) -> Result<Vec<u8>, KyncError> {
+		Ok(FORMAT_UID.to_vec())
+	}
+	fn configs(&self) -> Result<Vec<Vec<u8>>, KyncError> {
+		Ok(vec![b"Default".to_vec()])
+	}
+	fn set_context(&self, _context: &[u8]) -> Result<(), KyncError> {
+		Ok(())
+	}
+	fn auth_info_protect(&self, _config: &[u8]) -> Result<(bool, u64), KyncError> {
+		Ok((true, u64::MAX))
+	}
+	fn auth_info_recover(&self, _config: &[u8]) -> Result<(bool, u64), KyncError> {
+		Ok((true, u64::MAX))
+	}
+	fn protect(&self, data: &[u8], _config: &[u8], auth: Option<&[u8]>)
+		-> Result<Vec<u8>, KyncError>
+	{
+		Self::check_auth(auth, KyncErrorKind::ProtectError)?;
+		Ok(data.iter().rev().copied().collect())
+	}
+	fn recover(&self, data: &[u8], auth: Option<&[u8]>) -> Result<SecretBytes, KyncError> {
+		Self::check_auth(auth, KyncErrorKind::RecoverError)?;
+		Ok(data.iter().rev().copied().collect::<Vec<u8>>().into())
+	}
+}
+
+
+const FORMAT_UID: &[u8] = b"TestCapsuleFormat.3A0351A7-FE90-4383-9E68-FCC20033D5F1";
+const USER_SECRET: &[u8] = b"Testolope";
+const KEY: &[u8] = b"2nwBK-EkfXW-yWSQv-Vkab3-USHvX-WNJxa-GeXFJ-ecsjJ-imnft";
+
+
+/// Performs a protect/recover round-trip with an arbitrary key capsule
+fn roundtrip(capsule: &dyn KeyCapsule) -> Vec<u8> {
+	assert_eq!(capsule.id().unwrap(), FORMAT_UID);
+	let configs = capsule.configs().unwrap();
+	
+	let protected = capsule.protect(KEY, &configs[0], Some(USER_SECRET)).unwrap();
+	assert!(capsule.recover(&protected, None).is_err());
+	assert_eq!(capsule.recover(&protected, Some(USER_SECRET)).unwrap().as_ref(), KEY);
+	protected
+}
+
+
+#[test]
+fn test_interchangeable() {
+	let capsules: Vec<Box<dyn KeyCapsule>> =
+		vec![Box::new(common::load_plugin("kync_test_plugin")), Box::new(ReverseCapsule)];
+	let protected: Vec<Vec<u8>> = capsules.iter().map(|c| roundtrip(c.as_ref())).collect();
+	assert_eq!(protected[0], protected[1]);
+}
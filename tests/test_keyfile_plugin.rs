@@ -0,0 +1,79 @@
+mod common;
+
+use kync::{ ErrorCode, KeyCapsule };
+use std::{ env, fs, path::Path };
+
+
+/// Writes a keyfile with `contents` and `mode` to `path`
+fn write_keyfile(path: &Path, contents: &[u8], mode: u32) {
+	fs::write(path, contents).unwrap();
+	#[cfg(unix)]
+	fs::set_permissions(path, std::os::unix::fs::PermissionsExt::from_mode(mode)).unwrap();
+	#[cfg(not(unix))]
+	let _ = mode;
+}
+
+
+const FORMAT_UID: &[u8] = b"KeyfileCapsuleFormat.4D3C0E1A-6B7F-4E52-9A0D-2F8C5B1E7A93";
+const KEY: &[u8] = b"2nwBK-EkfXW-yWSQv-Vkab3-USHvX-WNJxa-GeXFJ-ecsjJ-imnft";
+const KEYFILE: &[u8] = b"eWc7Hq4fLb2ZsK9vTn3XpR8mJd6GyA1uQw5EiO0h";
+const OTHER_KEYFILE: &[u8] = b"Xk2pQ7vN4rT9wL1bM6cZ3sD8fG5hJ0aY2eU7iR4o";
+
+
+#[test]
+fn test() {
+	// The plugin's context is global, so everything is tested sequentially
+	let dir = env::temp_dir().join(format!("kync_test_keyfile_{}", std::process::id()));
+	fs::create_dir_all(&dir).unwrap();
+	let (keyfile, other) = (dir.join("keyfile"), dir.join("other"));
+	write_keyfile(&keyfile, KEYFILE, 0o600);
+	write_keyfile(&other, OTHER_KEYFILE, 0o600);
+	let config = keyfile.to_str().unwrap().as_bytes();
+	
+	// The context is offered as config and no authentication is required
+	let plugin = common::load_plugin("kync_keyfile_plugin");
+	assert_eq!(plugin.id().unwrap(), FORMAT_UID);
+	plugin.set_context(config).unwrap();
+	assert_eq!(plugin.configs().unwrap(), [config.to_vec()]);
+	assert_eq!(plugin.auth_info_protect(config).unwrap(), (false, u64::MAX));
+	assert_eq!(plugin.auth_info_recover(config).unwrap(), (false, u64::MAX));
+	
+	// Protect and recover a key using the path recorded in the capsule
+	plugin.set_context(b"").unwrap();
+	let protected = plugin.protect(KEY, config, None).unwrap();
+	assert_eq!(plugin.recover(&protected, None).unwrap().as_ref(), KEY);
+	
+	// The context overrides the recorded path
+	plugin.set_context(other.to_str().unwrap().as_bytes()).unwrap();
+	let err = plugin.recover(&protected, None).unwrap_err();
+	assert_eq!(err.code(), Some(ErrorCode::AuthFailed));
+	assert_eq!(err.message(), "The keyfile does not match the capsule");
+	plugin.set_context(b"").unwrap();
+	
+	// Modified capsules are rejected
+	let mut modified = protected.clone();
+	*modified.last_mut().unwrap() ^= 0x01;
+	let err = plugin.recover(&modified, None).unwrap_err();
+	assert_eq!(err.code(), Some(ErrorCode::InvalidCapsule));
+	let err = plugin.recover(&protected[..10], None).unwrap_err();
+	assert_eq!(err.code(), Some(ErrorCode::InvalidCapsule));
+	
+	// Missing and short keyfiles
+	let missing = dir.join("missing");
+	let err = plugin.protect(KEY, missing.to_str().unwrap().as_bytes(), None).unwrap_err();
+	assert_eq!(err.code(), Some(ErrorCode::DeviceUnavailable));
+	assert_eq!(err.message(), "The keyfile does not exist");
+	write_keyfile(&other, b"Too short", 0o600);
+	let err = plugin.protect(KEY, other.to_str().unwrap().as_bytes(), None).unwrap_err();
+	assert_eq!(err.code(), Some(ErrorCode::InvalidConfig));
+	
+	// Keyfiles with loose permissions are refused
+	#[cfg(unix)]
+	{
+		write_keyfile(&keyfile, KEYFILE, 0o644);
+		let err = plugin.recover(&protected, None).unwrap_err();
+		assert_eq!(err.code(), Some(ErrorCode::InvalidConfig));
+		assert!(err.message().starts_with("The keyfile is accessible by other users"));
+	}
+	fs::remove_dir_all(dir).unwrap();
+}
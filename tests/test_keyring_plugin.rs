@@ -0,0 +1,106 @@
+#![cfg(target_os = "linux")]
+
+mod common;
+
+use kync::{ ErrorCode, KeyCapsule };
+use std::{ ptr, thread, ffi::CString, os::raw::c_long };
+
+
+/// Joins a fresh anonymous session keyring (only affects the current thread and the threads it
+/// spawns afterwards)
+fn join_fresh_session() {
+	let result = unsafe {
+		libc::syscall(libc::SYS_keyctl, libc::KEYCTL_JOIN_SESSION_KEYRING as c_long,
+			ptr::null::<libc::c_char>())
+	};
+	assert!(result > 0, "Failed to join a fresh session keyring");
+}
+/// Provisions a `user` key with `description` and `payload` in the session keyring
+fn provision(description: &str, payload: &[u8]) {
+	let description = CString::new(description).unwrap();
+	let result = unsafe {
+		libc::syscall(libc::SYS_add_key, b"user\0".as_ptr(), description.as_ptr(),
+			payload.as_ptr(), payload.len(), libc::KEY_SPEC_SESSION_KEYRING as c_long)
+	};
+	assert!(result > 0, "Failed to provision a key");
+}
+/// Counts the keys that are linked to the session keyring
+fn session_keys() -> usize {
+	let mut serials = [0i32; 64];
+	let result = unsafe {
+		libc::syscall(libc::SYS_keyctl, libc::KEYCTL_READ as c_long,
+			libc::KEY_SPEC_SESSION_KEYRING as c_long, serials.as_mut_ptr(), 4 * serials.len())
+	};
+	assert!(result >= 0, "Failed to read the session keyring");
+	result as usize / 4
+}
+
+
+const FORMAT_UID: &[u8] = b"KeyringCapsuleFormat.6D5C8187-3066-4DDB-8D33-F1567F24F9FC";
+const KEY: &[u8] = b"2nwBK-EkfXW-yWSQv-Vkab3-USHvX-WNJxa-GeXFJ-ecsjJ-imnft";
+const WRAPPING_KEY: &[u8; 32] = b"VG9JR-7wXpt-fcQ2M-kLq8S-dRzY3-Hn";
+
+
+#[test]
+fn test() {
+	join_fresh_session();
+	let plugin = common::load_plugin("kync_keyring_plugin");
+	assert_eq!(plugin.id().unwrap(), FORMAT_UID);
+	assert!(plugin.configs().unwrap().contains(&b"session".to_vec()));
+	assert_eq!(plugin.auth_info_protect(b"session").unwrap(), (false, u64::MAX));
+	
+	// Protect and recover a key with the provisioned default key in the session keyring
+	provision("kync:default", WRAPPING_KEY);
+	let protected = plugin.protect(KEY, b"session", None).unwrap();
+	assert!(protected.windows(12).any(|w| w == b"kync:default"));
+	assert_eq!(plugin.recover(&protected, None).unwrap().as_ref(), KEY);
+	
+	// Protecting does not add keys to the keyring
+	let keys = session_keys();
+	plugin.protect(KEY, b"session", None).unwrap();
+	assert_eq!(session_keys(), keys);
+	
+	// Use a key with an explicit description
+	provision("backup", WRAPPING_KEY);
+	let other = plugin.protect(KEY, b"session:backup", None).unwrap();
+	assert!(other.windows(6).any(|w| w == b"backup"));
+	assert_eq!(plugin.recover(&other, None).unwrap().as_ref(), KEY);
+	
+	// The key is not available in another session
+	let other = protected.clone();
+	let err = thread::spawn(move || {
+		join_fresh_session();
+		common::load_plugin("kync_keyring_plugin").recover(&other, None).unwrap_err()
+	}).join().unwrap();
+	assert_eq!(err.code(), Some(ErrorCode::DeviceUnavailable));
+	assert_eq!(err.message(), "The key is not available in any keyring");
+}
+
+
+#[test]
+fn test_errors() {
+	join_fresh_session();
+	let plugin = common::load_plugin("kync_keyring_plugin");
+	provision("kync:default", WRAPPING_KEY);
+	provision("short", b"Too short");
+	let protected = plugin.protect(KEY, b"session", None).unwrap();
+	
+	// Invalid configs
+	for config in [&b"Invalid"[..], b"session:", b"session:short"].iter() {
+		let err = plugin.protect(KEY, config, None).unwrap_err();
+		assert_eq!(err.code(), Some(ErrorCode::InvalidConfig));
+	}
+	
+	// Keys are never created implicitly
+	let err = plugin.protect(KEY, b"session:missing", None).unwrap_err();
+	assert_eq!(err.code(), Some(ErrorCode::DeviceUnavailable));
+	assert_eq!(err.message(), "The configured key is not in the keyring");
+	
+	// Modified or truncated capsules are rejected
+	let mut modified = protected.clone();
+	*modified.last_mut().unwrap() ^= 0x01;
+	let err = plugin.recover(&modified, None).unwrap_err();
+	assert_eq!(err.code(), Some(ErrorCode::InvalidCapsule));
+	let err = plugin.recover(&protected[..10], None).unwrap_err();
+	assert_eq!(err.code(), Some(ErrorCode::InvalidCapsule));
+}
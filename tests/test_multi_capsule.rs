@@ -0,0 +1,127 @@
+mod common;
+
+use kync::{ ErrorCode, KeyCapsule, KyncError, KyncErrorKind, MultiCapsule, Plugin, SecretBytes };
+
+
+const TEST_UID: &[u8] = b"TestCapsuleFormat.3A0351A7-FE90-4383-9E68-FCC20033D5F1";
+const PASSWORD_UID: &[u8] = b"PasswordCapsuleFormat.983A67F8-5151-480E-B5A4-3D6D07C4B993";
+const USER_SECRET: &[u8] = b"Testolope";
+const PASSPHRASE: &[u8] = b"correct horse battery staple";
+const KEY: &[u8] = b"2nwBK-EkfXW-yWSQv-Vkab3-USHvX-WNJxa-GeXFJ-ecsjJ-imnft";
+
+/// A recipient with its plugin, config and authentication
+type Recipient<'a> = (&'a dyn KeyCapsule, &'a [u8], Option<&'a [u8]>);
+
+
+/// Seals `KEY` for the test plugin and the password plugin
+fn seal(test: &Plugin, password: &Plugin) -> Vec<u8> {
+	let recipients: Vec<Recipient> = vec![
+		(test, b"Default", Some(USER_SECRET)),
+		(password, b"interactive", Some(PASSPHRASE))
+	];
+	MultiCapsule::seal(recipients, KEY).unwrap()
+}
+/// An in-process capsule that claims the test plugin's ID but recovers a wrong data key
+struct WrongKey;
+impl KeyCapsule for WrongKey {
+	fn id(&self) -> Result<Vec<u8>, KyncError> {
+		Ok(TEST_UID.to_vec())
+	}
+	fn configs(&self) -> Result<Vec<Vec<u8>>, KyncError> {
+		Ok(vec![b"Default".to_vec()])
+	}
+	fn set_context(&self, _context: &[u8]) -> Result<(), KyncError> {
+		Ok(())
+	}
+	fn auth_info_protect(&self, _config: &[u8]) -> Result<(bool, u64), KyncError> {
+		Ok((false, u64::MAX))
+	}
+	fn auth_info_recover(&self, _config: &[u8]) -> Result<(bool, u64), KyncError> {
+		Ok((false, u64::MAX))
+	}
+	fn protect(&self, data: &[u8], _config: &[u8], _auth: Option<&[u8]>)
+		-> Result<Vec<u8>, KyncError>
+	{
+		Ok(data.to_vec())
+	}
+	fn recover(&self, _data: &[u8], _auth: Option<&[u8]>) -> Result<SecretBytes, KyncError> {
+		Ok(SecretBytes::from(&[0x42; 32][..]))
+	}
+}
+
+/// Returns the authentication for `recipient`
+fn auth(recipient: &kync::Capsule) -> Option<SecretBytes> {
+	match recipient.id() {
+		TEST_UID => Some(USER_SECRET.into()),
+		PASSWORD_UID => Some(PASSPHRASE.into()),
+		_ => None
+	}
+}
+
+
+#[test]
+fn test_roundtrip() {
+	let test = common::load_plugin("kync_test_plugin");
+	let password = common::load_plugin("kync_password_plugin");
+	let envelope = seal(&test, &password);
+	assert!(!envelope.windows(KEY.len()).any(|window| window == KEY));
+	
+	// Validate the recorded recipients
+	let multi = MultiCapsule::parse(&envelope).unwrap();
+	assert_eq!(multi.to_bytes(), envelope);
+	let recipients: Vec<(&[u8], &[u8])> = multi.recipients().iter()
+		.map(|r| (r.id(), r.config())).collect();
+	assert_eq!(recipients, [(TEST_UID, b"Default".as_ref()), (PASSWORD_UID, b"interactive")]);
+	
+	// Open the envelope with all plugins or only with one of them
+	let both: [&dyn KeyCapsule; 2] = [&test, &password];
+	assert_eq!(MultiCapsule::open(both.iter().copied(), &envelope, auth).unwrap().as_ref(), KEY);
+	assert_eq!(MultiCapsule::open(Some(&test), &envelope, auth).unwrap().as_ref(), KEY);
+	assert_eq!(MultiCapsule::open(Some(&password), &envelope, auth).unwrap().as_ref(), KEY);
+	
+	// A failing recipient falls back to the next one
+	let only_passphrase = |r: &kync::Capsule| Some(r).filter(|r| r.id() == PASSWORD_UID)
+		.and_then(auth);
+	let recovered = MultiCapsule::open(both.iter().copied(), &envelope, only_passphrase).unwrap();
+	assert_eq!(recovered.as_ref(), KEY);
+	
+	// A recipient that recovers a wrong data key falls back to the next one, too
+	let wrong: [&dyn KeyCapsule; 2] = [&WrongKey, &password];
+	assert_eq!(MultiCapsule::open(wrong.iter().copied(), &envelope, auth).unwrap().as_ref(), KEY);
+	let err = MultiCapsule::open(Some(&WrongKey), &envelope, auth).unwrap_err();
+	assert!(matches!(err.kind(), KyncErrorKind::FormatError));
+	assert_eq!(err.plugin_id(), Some(TEST_UID));
+}
+
+
+#[test]
+fn test_errors() {
+	let test = common::load_plugin("kync_test_plugin");
+	let password = common::load_plugin("kync_password_plugin");
+	let envelope = seal(&test, &password);
+	
+	// No recipients or no matching plugin
+	let err = MultiCapsule::seal(Vec::<Recipient>::new(), KEY).unwrap_err();
+	assert!(matches!(err.kind(), KyncErrorKind::ProtectError));
+	let err = MultiCapsule::open(Vec::<&Plugin>::new(), &envelope, auth).unwrap_err();
+	assert!(matches!(err.kind(), KyncErrorKind::PluginNotFound));
+	
+	// All available recipients fail
+	let err = MultiCapsule::open(Some(&password), &envelope, |_| None).unwrap_err();
+	assert_eq!(err.code(), Some(ErrorCode::AuthRequired));
+	assert_eq!(err.plugin_id(), Some(PASSWORD_UID));
+	
+	// Invalid, truncated and tampered envelopes
+	let mut invalid = envelope.clone();
+	invalid[0] ^= 0xFF;
+	let err = MultiCapsule::open(Some(&test), &invalid, auth).unwrap_err();
+	assert!(matches!(err.kind(), KyncErrorKind::FormatError));
+	
+	let err = MultiCapsule::open(Some(&test), &envelope[..40], auth).unwrap_err();
+	assert!(matches!(err.kind(), KyncErrorKind::FormatError));
+	
+	let mut tampered = envelope.clone();
+	*tampered.last_mut().unwrap() ^= 0x01;
+	let err = MultiCapsule::open(Some(&test), &tampered, auth).unwrap_err();
+	assert!(matches!(err.kind(), KyncErrorKind::FormatError));
+}
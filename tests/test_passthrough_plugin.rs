@@ -0,0 +1,82 @@
+mod common;
+
+use kync::{ ErrorCode, KeyCapsule };
+use std::{ env, sync::Mutex };
+
+
+/// The plugin reads the environment which is modified by the tests, so the tests must not run
+/// concurrently
+static SERIAL: Mutex<()> = Mutex::new(());
+
+
+const FORMAT_UID: &[u8] = b"PassthroughCapsuleFormat.4CF7CA6E-B833-40D2-A64F-462FAD47FF62";
+const KEY: &[u8] = b"2nwBK-EkfXW-yWSQv-Vkab3-USHvX-WNJxa-GeXFJ-ecsjJ-imnft";
+
+
+#[test]
+fn test() {
+	let _serial = SERIAL.lock().unwrap_or_else(|e| e.into_inner());
+	let plugin = common::load_plugin("kync_passthrough_plugin");
+	assert_eq!(plugin.id().unwrap(), FORMAT_UID);
+	env::set_var("KYNC_SECRET_TEST", std::str::from_utf8(KEY).unwrap());
+	assert!(plugin.configs().unwrap().contains(&b"env:KYNC_SECRET_TEST".to_vec()));
+	assert_eq!(plugin.auth_info_protect(b"env:KYNC_SECRET_TEST").unwrap(), (false, u64::MAX));
+	assert_eq!(plugin.auth_info_recover(b"fd:3").unwrap(), (false, u64::MAX));
+	
+	// Protect and recover an injected key
+	let protected = plugin.protect(KEY, b"env:KYNC_SECRET_TEST", None).unwrap();
+	assert!(!protected.windows(KEY.len()).any(|window| window == KEY));
+	assert_eq!(plugin.recover(&protected, None).unwrap().as_ref(), KEY);
+	
+	// A different or missing injected key
+	env::set_var("KYNC_SECRET_TEST", "Another key");
+	let err = plugin.recover(&protected, None).unwrap_err();
+	assert_eq!(err.code(), Some(ErrorCode::AuthFailed));
+	let err = plugin.protect(KEY, b"env:KYNC_SECRET_TEST", None).unwrap_err();
+	assert_eq!(err.code(), Some(ErrorCode::InvalidConfig));
+	env::remove_var("KYNC_SECRET_TEST");
+	let err = plugin.recover(&protected, None).unwrap_err();
+	assert_eq!(err.code(), Some(ErrorCode::DeviceUnavailable));
+	let err = plugin.protect(KEY, b"env:KYNC_SECRET_TEST", None).unwrap_err();
+	assert_eq!(err.code(), Some(ErrorCode::DeviceUnavailable));
+	
+	// Config and capsule errors
+	for config in [b"Invalid".as_ref(), b"env:", b"fd:stdin", b"file:/etc/key"] {
+		let err = plugin.protect(KEY, config, None).unwrap_err();
+		assert_eq!(err.code(), Some(ErrorCode::InvalidConfig));
+	}
+	let err = plugin.recover(&protected[..10], None).unwrap_err();
+	assert_eq!(err.code(), Some(ErrorCode::InvalidCapsule));
+}
+
+
+#[test]
+#[cfg(unix)]
+fn test_fd() {
+	use std::{ fs, os::unix::io::AsRawFd };
+	let _serial = SERIAL.lock().unwrap_or_else(|e| e.into_inner());
+	
+	// Inject the key with a file descriptor
+	let plugin = common::load_plugin("kync_passthrough_plugin");
+	let path = env::temp_dir().join(format!("kync_test_passthrough_{}", std::process::id()));
+	fs::write(&path, KEY).unwrap();
+	let file = fs::File::open(&path).unwrap();
+	let config = format!("fd:{}", file.as_raw_fd()).into_bytes();
+	
+	// The file descriptor is read once but can be recovered from multiple times
+	let protected = plugin.protect(KEY, &config, None).unwrap();
+	assert_eq!(plugin.recover(&protected, None).unwrap().as_ref(), KEY);
+	assert_eq!(plugin.recover(&protected, None).unwrap().as_ref(), KEY);
+	drop(file);
+	
+	// A reused descriptor number refers to a different file and is read again
+	let other = path.with_extension("other");
+	fs::write(&other, b"Another key").unwrap();
+	let file = fs::File::open(&other).unwrap();
+	let config = format!("fd:{}", file.as_raw_fd()).into_bytes();
+	let protected = plugin.protect(b"Another key", &config, None).unwrap();
+	assert_eq!(plugin.recover(&protected, None).unwrap().as_ref(), b"Another key");
+	drop(file);
+	fs::remove_file(&path).unwrap();
+	fs::remove_file(&other).unwrap();
+}
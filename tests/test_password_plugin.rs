@@ -0,0 +1,65 @@
+mod common;
+
+use kync::{ ErrorCode, KeyCapsule };
+
+
+const FORMAT_UID: &[u8] = b"PasswordCapsuleFormat.983A67F8-5151-480E-B5A4-3D6D07C4B993";
+const PASSPHRASE: Option<&[u8]> = Some(b"correct horse battery staple");
+const KEY: &[u8] = b"2nwBK-EkfXW-yWSQv-Vkab3-USHvX-WNJxa-GeXFJ-ecsjJ-imnft";
+
+
+#[test]
+fn test() {
+	let plugin = common::load_plugin("kync_password_plugin");
+	assert_eq!(plugin.id().unwrap(), FORMAT_UID);
+	assert_eq!(plugin.configs().unwrap(), [b"interactive".to_vec(), b"moderate".to_vec(),
+		b"sensitive".to_vec()]);
+	assert_eq!(plugin.auth_info_recover(b"interactive").unwrap(), (true, u64::MAX));
+	
+	// Protect and recover a key with two different presets
+	for config in [b"interactive".as_ref(), b"moderate"] {
+		let protected = plugin.protect(KEY, config, PASSPHRASE).unwrap();
+		assert_ne!(&protected[protected.len() - KEY.len()..], KEY);
+		assert_eq!(plugin.recover(&protected, PASSPHRASE).unwrap().as_ref(), KEY);
+	}
+	
+	// The salt and nonce are random
+	let a = plugin.protect(KEY, b"interactive", PASSPHRASE).unwrap();
+	let b = plugin.protect(KEY, b"interactive", PASSPHRASE).unwrap();
+	assert_ne!(a, b);
+}
+
+
+#[test]
+fn test_errors() {
+	let plugin = common::load_plugin("kync_password_plugin");
+	let protected = plugin.protect(KEY, b"interactive", PASSPHRASE).unwrap();
+	
+	// Wrong or missing passphrases
+	let err = plugin.recover(&protected, Some(b"Invalid")).unwrap_err();
+	assert_eq!(err.code(), Some(ErrorCode::AuthFailed));
+	let err = plugin.recover(&protected, None).unwrap_err();
+	assert_eq!(err.code(), Some(ErrorCode::AuthRequired));
+	let err = plugin.protect(KEY, b"interactive", Some(b"")).unwrap_err();
+	assert_eq!(err.code(), Some(ErrorCode::AuthFailed));
+	
+	// Invalid configs
+	let err = plugin.protect(KEY, b"Invalid", PASSPHRASE).unwrap_err();
+	assert_eq!(err.code(), Some(ErrorCode::InvalidConfig));
+	
+	// Truncated capsules and excessive KDF parameters are rejected
+	let err = plugin.recover(&protected[..20], PASSPHRASE).unwrap_err();
+	assert_eq!(err.code(), Some(ErrorCode::InvalidCapsule));
+	for (offset, value) in [(1, 1024 * 1024 + 1), (5, 5), (5, u32::MAX), (9, 2)].iter() {
+		let mut modified = protected.clone();
+		modified[*offset..*offset + 4].copy_from_slice(&value.to_be_bytes());
+		let err = plugin.recover(&modified, PASSPHRASE).unwrap_err();
+		assert_eq!(err.code(), Some(ErrorCode::InvalidCapsule));
+	}
+	
+	// The header is authenticated
+	let mut modified = protected;
+	modified[20] ^= 0x01;
+	let err = plugin.recover(&modified, PASSPHRASE).unwrap_err();
+	assert_eq!(err.code(), Some(ErrorCode::AuthFailed));
+}
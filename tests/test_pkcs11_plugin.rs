@@ -0,0 +1,125 @@
+mod common;
+
+use kync::{ ErrorCode, KeyCapsule, Plugin };
+use std::{ env, fs, path::PathBuf, process::Command, sync::Mutex };
+
+
+/// The tests access the environment, so they must not run concurrently
+static SERIAL: Mutex<()> = Mutex::new(());
+
+
+/// Loads a private copy of the plugin so that the module set by a test does not leak into other
+/// tests (the plugin state is global per library)
+fn load_private_plugin() -> Plugin {
+	let source = common::plugin_path("kync_pkcs11_plugin");
+	let dir = env::temp_dir().join(format!("kync_test_pkcs11_plugin_{}", std::process::id()));
+	fs::create_dir_all(&dir).unwrap();
+	let path = dir.join(source.file_name().unwrap());
+	fs::copy(source, &path).unwrap();
+	Plugin::load(path).unwrap()
+}
+
+
+/// Finds the SoftHSMv2 module (`$SOFTHSM2_MODULE` or the usual install locations)
+fn softhsm_module() -> Option<PathBuf> {
+	const CANDIDATES: &[&str] = &[
+		"/usr/lib/softhsm/libsofthsm2.so",
+		"/usr/lib/x86_64-linux-gnu/softhsm/libsofthsm2.so",
+		"/usr/lib/aarch64-linux-gnu/softhsm/libsofthsm2.so",
+		"/usr/lib64/pkcs11/libsofthsm2.so",
+		"/usr/local/lib/softhsm/libsofthsm2.so",
+		"/opt/homebrew/lib/softhsm/libsofthsm2.so"
+	];
+	env::var_os("SOFTHSM2_MODULE").map(PathBuf::from)
+		.or_else(|| CANDIDATES.iter().map(PathBuf::from).find(|path| path.exists()))
+}
+
+/// Creates a fresh SoftHSMv2 token `kync` that contains the AES key `kync-key` and returns the
+/// module path
+fn setup_softhsm() -> PathBuf {
+	let module = softhsm_module().expect("SoftHSMv2 is not installed (or set $SOFTHSM2_MODULE)");
+	let dir = env::temp_dir().join(format!("kync_test_pkcs11_{}", std::process::id()));
+	fs::create_dir_all(dir.join("tokens")).unwrap();
+	let config = dir.join("softhsm2.conf");
+	fs::write(&config, format!("directories.tokendir = {}\nobjectstore.backend = file\n",
+		dir.join("tokens").display())).unwrap();
+	env::set_var("SOFTHSM2_CONF", &config);
+	
+	// Initialize the token and import the key
+	let util = |args: &[&str]| Command::new("softhsm2-util").args(args).status()
+		.map(|status| status.success()).unwrap_or(false);
+	let init = ["--init-token", "--free", "--label", "kync", "--pin", PIN, "--so-pin", "87654321"];
+	assert!(util(&init), "Failed to initialize the SoftHSMv2 token");
+	fs::write(dir.join("key.bin"), AES_KEY).unwrap();
+	let key = dir.join("key.bin");
+	assert!(util(&["--import", key.to_str().unwrap(), "--aes", "--token", "kync", "--label",
+		"kync-key", "--id", "01", "--pin", PIN]));
+	module
+}
+
+
+const FORMAT_UID: &[u8] = b"Pkcs11CapsuleFormat.0E1F0A4A-64E1-4288-B4BC-998092EA2512";
+const PIN: &str = "12345678";
+const AES_KEY: &[u8] = b"hR4nQ8vX2mT6bK9wL3cZ7sD1fG5jP0aY";
+const KEY: &[u8] = b"2nwBK-EkfXW-yWSQv-Vkab3-USHvX-WNJxa-GeXFJ-ecsjJ-imnft";
+
+
+#[test]
+fn test() {
+	let _serial = SERIAL.lock().unwrap_or_else(|e| e.into_inner());
+	let plugin = common::load_plugin("kync_pkcs11_plugin");
+	assert_eq!(plugin.id().unwrap(), FORMAT_UID);
+	if env::var_os("KYNC_PKCS11_MODULE").is_none() {
+		let err = plugin.configs().unwrap_err();
+		assert_eq!(err.code(), Some(ErrorCode::DeviceUnavailable));
+		assert_eq!(err.message(), "No PKCS#11 module has been set");
+	}
+	let err = plugin.set_context(b"/nonexistent/libpkcs11.so").unwrap_err();
+	assert_eq!(err.code(), Some(ErrorCode::DeviceUnavailable));
+}
+
+
+// This test requires SoftHSMv2 and is opt-in: run it with `cargo test -- --ignored`
+#[test]
+#[ignore]
+fn test_softhsm() {
+	let _serial = SERIAL.lock().unwrap_or_else(|e| e.into_inner());
+	let plugin = load_private_plugin();
+	let module = setup_softhsm();
+	
+	// Setting the same module again keeps it usable
+	plugin.set_context(module.to_str().unwrap().as_bytes()).unwrap();
+	plugin.set_context(module.to_str().unwrap().as_bytes()).unwrap();
+	assert!(plugin.configs().unwrap().iter().any(|c| c.starts_with(b"kync/")));
+	assert_eq!(plugin.auth_info_protect(b"kync/kync-key").unwrap(), (true, u64::MAX));
+	assert_eq!(plugin.auth_info_recover(b"kync/").unwrap(), (true, u64::MAX));
+	
+	// Protect and recover a key with an explicit and with the default key
+	let pin = Some(PIN.as_bytes());
+	for config in [b"kync/kync-key".as_ref(), b"kync/"] {
+		let protected = plugin.protect(KEY, config, pin).unwrap();
+		assert_eq!(plugin.recover(&protected, pin).unwrap().as_ref(), KEY);
+	}
+	
+	// Authentication errors
+	let protected = plugin.protect(KEY, b"kync/kync-key", pin).unwrap();
+	let err = plugin.recover(&protected, Some(b"00000000")).unwrap_err();
+	assert_eq!(err.code(), Some(ErrorCode::AuthFailed));
+	assert!(err.message().starts_with("The PIN is incorrect"));
+	let err = plugin.recover(&protected, None).unwrap_err();
+	assert_eq!(err.code(), Some(ErrorCode::AuthRequired));
+	
+	// The retry count stays unknown since PKCS#11 does not report it
+	assert_eq!(plugin.auth_info_recover(b"kync/kync-key").unwrap(), (true, u64::MAX));
+	assert_eq!(plugin.recover(&protected, pin).unwrap().as_ref(), KEY);
+	
+	// Config and capsule errors
+	let err = plugin.protect(KEY, b"missing/kync-key", pin).unwrap_err();
+	assert_eq!(err.code(), Some(ErrorCode::DeviceUnavailable));
+	let err = plugin.protect(KEY, b"kync/missing", pin).unwrap_err();
+	assert_eq!(err.code(), Some(ErrorCode::InvalidConfig));
+	let err = plugin.protect(KEY, b"Invalid", pin).unwrap_err();
+	assert_eq!(err.code(), Some(ErrorCode::InvalidConfig));
+	let err = plugin.recover(&protected[..10], pin).unwrap_err();
+	assert_eq!(err.code(), Some(ErrorCode::InvalidCapsule));
+}
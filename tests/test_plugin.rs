@@ -1,21 +1,13 @@
+mod common;
+
 use kync::{
-	Plugin,
-	plugin::{ os_default_prefix, os_default_suffix }
+	ErrorCode, KeyCapsule, KyncErrorKind, Plugin,
+	plugin::{ DEFAULT_TIMEOUT, Fault, Limits }
+};
+use std::{
+	error::Error,
+	io::{ self, Write }
 };
-use std::path::PathBuf;
-
-/// Load the test plugin
-fn load_plugin() -> Plugin {
-	// Create path
-	let mut path = PathBuf::new();
-	path.push("target");
-	path.push(if cfg!(debug_assertions) { "debug" } else { "release" });
-	path.push("deps");
-	path.push(format!("{}kync_test_plugin.{}", os_default_prefix(), os_default_suffix()));
-	
-	// Load plugin
-	Plugin::load(path).unwrap()
-}
 
 
 const FORMAT_UID: &[u8] = b"TestCapsuleFormat.3A0351A7-FE90-4383-9E68-FCC20033D5F1";
@@ -27,7 +19,7 @@ const PAYLOAD: &[u8] = b"tfnmi-Jjsce-JFXeG-axJNW-XvHSU-3bakV-vQSWy-WXfkE-KBwn2";
 #[test]
 fn test() {
 	// Load plugin and test format UID
-	let plugin = load_plugin();
+	let plugin = common::load_plugin("kync_test_plugin");
 	assert_eq!(plugin.id().unwrap(), FORMAT_UID);
 	
 	// Get the first config
@@ -40,5 +32,125 @@ fn test() {
 	
 	// Recover a key
 	let recovered = plugin.recover(&protected, USER_SECRET).unwrap();
-	assert_eq!(recovered, KEY);
-}
\ No newline at end of file
+	assert_eq!(recovered.as_ref(), KEY);
+}
+
+
+#[test]
+fn test_errors() {
+	// Plugin errors contain the plugin's error description, ID and path
+	let plugin = common::load_plugin("kync_test_plugin");
+	let err = plugin.recover(PAYLOAD, Some(b"Invalid")).unwrap_err();
+	assert_eq!(err.kind(), KyncErrorKind::RecoverError);
+	assert_eq!(err.message(), "Invalid authentication");
+	assert_eq!(err.plugin_id(), Some(FORMAT_UID));
+	assert_eq!(err.path(), Some(plugin.path()));
+	assert!(err.to_string().starts_with("Failed to recover the secret: Invalid authentication"));
+	
+	// API v2 plugins report machine-readable error codes
+	assert_eq!(plugin.api_version(), 0x02_00);
+	assert_eq!(err.code(), Some(ErrorCode::AuthFailed));
+	let err = plugin.recover(PAYLOAD, None).unwrap_err();
+	assert_eq!(err.code(), Some(ErrorCode::AuthRequired));
+	let err = plugin.auth_info_protect(b"Invalid").unwrap_err();
+	assert_eq!(err.code(), Some(ErrorCode::InvalidConfig));
+	
+	// Equal descriptions keep their own codes
+	let err = plugin.set_context(b"busy").unwrap_err();
+	assert_eq!(err.message(), "Invalid configuration");
+	assert_eq!(err.code(), Some(ErrorCode::DeviceUnavailable));
+	let err = plugin.auth_info_protect(b"Invalid").unwrap_err();
+	assert_eq!(err.code(), Some(ErrorCode::InvalidConfig));
+	
+	// Loading errors keep their underlying cause
+	let err = Plugin::load("Cargo.toml").err().unwrap();
+	assert_eq!(err.kind(), KyncErrorKind::LoadingError);
+	assert!(err.source().is_some());
+	assert!(err.path().unwrap().ends_with("Cargo.toml"));
+	
+	// The message tells a missing library from an invalid one
+	let missing = Plugin::load("./nonexistent.so").err().unwrap();
+	let invalid = Plugin::load("./Cargo.toml").err().unwrap();
+	assert_eq!(missing.kind(), KyncErrorKind::LoadingError);
+	assert!(missing.to_string().contains(missing.message()));
+	assert_ne!(missing.message(), invalid.message());
+	#[cfg(target_os = "linux")] {
+		assert!(missing.message().ends_with("No such file or directory)"));
+		assert!(invalid.message().ends_with("invalid ELF header)"));
+	}
+}
+
+/// A writer that always fails
+struct BrokenSink;
+impl Write for BrokenSink {
+	fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+		Err(io::Error::new(io::ErrorKind::BrokenPipe, "Broken sink"))
+	}
+	fn flush(&mut self) -> io::Result<()> {
+		Ok(())
+	}
+}
+
+
+#[test]
+fn test_stream() {
+	// Stream with and without a deadline
+	let mut plugin = common::load_plugin("kync_test_plugin");
+	for timeout in [Some(DEFAULT_TIMEOUT), None].iter() {
+		plugin.set_timeout(*timeout);
+		
+		let mut protected = Vec::new();
+		plugin.protect_to(&mut protected, KEY, b"Default", USER_SECRET).unwrap();
+		assert_eq!(protected, PAYLOAD);
+		
+		let mut recovered = Vec::new();
+		plugin.recover_to(&mut recovered, PAYLOAD, USER_SECRET).unwrap();
+		assert_eq!(recovered, KEY);
+		
+		// Sink errors are passed to the plugin and keep their cause
+		let err = plugin.recover_to(&mut BrokenSink, PAYLOAD, USER_SECRET).unwrap_err();
+		assert_eq!(err.kind(), KyncErrorKind::RecoverError);
+		let source = err.source().unwrap().downcast_ref::<io::Error>().unwrap();
+		assert_eq!(source.kind(), io::ErrorKind::BrokenPipe);
+	}
+}
+
+
+#[test]
+fn test_limits() {
+	let mut plugin = common::load_plugin("kync_test_plugin");
+	assert_eq!(plugin.limits(), Limits::default());
+	
+	// Exceed the byte limit, the segment limit and the ID length limit
+	plugin.set_limits(Limits { max_bytes: 8, ..Limits::default() });
+	let err = plugin.protect(KEY, b"Default", USER_SECRET).unwrap_err();
+	assert_eq!(err.kind(), KyncErrorKind::OutputLimitExceeded);
+	let err = plugin.recover_to(&mut Vec::new(), PAYLOAD, USER_SECRET).unwrap_err();
+	assert_eq!(err.kind(), KyncErrorKind::OutputLimitExceeded);
+	
+	plugin.set_limits(Limits { max_segments: 0, ..Limits::default() });
+	assert_eq!(plugin.configs().unwrap_err().kind(), KyncErrorKind::OutputLimitExceeded);
+	
+	plugin.set_limits(Limits { max_id_len: 8, ..Limits::default() });
+	assert_eq!(plugin.id().unwrap_err().kind(), KyncErrorKind::OutputLimitExceeded);
+	assert_eq!(plugin.recover(PAYLOAD, USER_SECRET).unwrap().as_ref(), KEY);
+}
+
+
+#[test]
+fn test_fault_injection() {
+	// The test plugin propagates the exact callback error
+	let plugin = common::load_plugin("kync_test_plugin");
+	let fault = Fault::AfterSegments(0);
+	assert!(plugin.protect_with_fault(fault, KEY, b"Default", USER_SECRET).unwrap());
+	assert!(plugin.recover_with_fault(Fault::AfterBytes(4), PAYLOAD, USER_SECRET).unwrap());
+	
+	// Faults that are never reached are reported as such
+	let fault = Fault::AfterBytes(1024);
+	assert!(!plugin.protect_with_fault(fault, KEY, b"Default", USER_SECRET).unwrap());
+	assert!(!plugin.recover_with_fault(Fault::AfterSegments(1), PAYLOAD, USER_SECRET).unwrap());
+	
+	// Other plugin errors are passed through
+	let err = plugin.recover_with_fault(Fault::AfterSegments(0), PAYLOAD, None).unwrap_err();
+	assert_eq!(err.message(), "Missing authentication parameter");
+}
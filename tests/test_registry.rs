@@ -0,0 +1,54 @@
+mod common;
+
+use kync::{
+	Capsule, KeyCapsule, PluginRegistry,
+	plugin::{ os_default_prefix, os_default_suffix },
+	registry::PLUGIN_PATH_ENV
+};
+use std::{ env, fs, path::PathBuf };
+
+
+const FORMAT_UID: &[u8] = b"TestCapsuleFormat.3A0351A7-FE90-4383-9E68-FCC20033D5F1";
+const USER_SECRET: Option<&[u8]> = Some(b"Testolope");
+const KEY: &[u8] = b"2nwBK-EkfXW-yWSQv-Vkab3-USHvX-WNJxa-GeXFJ-ecsjJ-imnft";
+
+
+/// Creates a plugin directory that contains the test plugin and an invalid library
+fn plugin_dir() -> PathBuf {
+	let library = format!("{}kync_test_plugin.{}", os_default_prefix(), os_default_suffix());
+	let source = common::plugin_path("kync_test_plugin");
+	
+	let dir = env::temp_dir().join(format!("kync_test_registry_{}", std::process::id()));
+	fs::create_dir_all(&dir).unwrap();
+	fs::copy(source, dir.join(&library)).unwrap();
+	fs::write(dir.join(format!("invalid.{}", os_default_suffix())), b"Not a library").unwrap();
+	fs::write(dir.join("README.md"), b"Not a library either").unwrap();
+	dir
+}
+
+
+#[test]
+fn test() {
+	let dir = plugin_dir();
+	env::set_var(PLUGIN_PATH_ENV, &dir);
+	
+	// Scan the directory
+	let mut registry = PluginRegistry::from_env();
+	registry.scan();
+	assert_eq!(registry.search_paths(), std::slice::from_ref(&dir));
+	assert_eq!(registry.failures().len(), 1);
+	assert!(registry.failures()[0].0.ends_with(format!("invalid.{}", os_default_suffix())));
+	
+	// Rescan and query the plugins
+	registry.scan();
+	assert_eq!(registry.failures().len(), 1);
+	assert_eq!(registry.list().map(|(id, _)| id).collect::<Vec<_>>(), [FORMAT_UID]);
+	assert!(registry.get_by_id(b"UnknownFormat").is_none());
+	
+	let plugin = registry.get_by_id(FORMAT_UID).unwrap();
+	let envelope = Capsule::seal(plugin, KEY, b"Default", USER_SECRET).unwrap();
+	let plugin = registry.find_for_capsule(&envelope).unwrap();
+	assert_eq!(plugin.id().unwrap(), FORMAT_UID);
+	
+	fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,59 @@
+mod common;
+
+use kync::{ ErrorCode, KeyCapsule, KyncErrorKind, RemotePlugin };
+
+
+/// Spawns a host with the test plugin
+fn spawn_plugin() -> RemotePlugin {
+	let path = common::plugin_path("kync_test_plugin");
+	RemotePlugin::spawn(env!("CARGO_BIN_EXE_kync-host"), path).unwrap()
+}
+
+
+const FORMAT_UID: &[u8] = b"TestCapsuleFormat.3A0351A7-FE90-4383-9E68-FCC20033D5F1";
+const USER_SECRET: Option<&[u8]> = Some(b"Testolope");
+const KEY: &[u8] = b"2nwBK-EkfXW-yWSQv-Vkab3-USHvX-WNJxa-GeXFJ-ecsjJ-imnft";
+const PAYLOAD: &[u8] = b"tfnmi-Jjsce-JFXeG-axJNW-XvHSU-3bakV-vQSWy-WXfkE-KBwn2";
+
+
+#[test]
+fn test_roundtrip() {
+	let plugin = spawn_plugin();
+	assert_eq!(plugin.id().unwrap(), FORMAT_UID);
+	assert_eq!(plugin.configs().unwrap(), [b"Default".to_vec()]);
+	assert_eq!(plugin.auth_info_protect(b"Default").unwrap(), (true, u64::MAX));
+	plugin.set_context(b"Test").unwrap();
+	
+	let protected = plugin.protect(KEY, b"Default", USER_SECRET).unwrap();
+	assert_eq!(protected, PAYLOAD);
+	assert_eq!(plugin.recover(&protected, USER_SECRET).unwrap().as_ref(), KEY);
+}
+
+
+#[test]
+fn test_errors() {
+	// Plugin errors are propagated
+	let plugin = spawn_plugin();
+	let err = plugin.recover(PAYLOAD, Some(b"Invalid")).unwrap_err();
+	assert!(matches!(err.kind(), KyncErrorKind::RecoverError));
+	assert_eq!(err.message(), "Invalid authentication");
+	assert_eq!(err.code(), Some(ErrorCode::AuthFailed));
+	assert_eq!(err.plugin_id(), Some(FORMAT_UID));
+	let err = plugin.recover(PAYLOAD, None).unwrap_err();
+	assert_eq!(err.message(), "Missing authentication parameter");
+	
+	// Loading errors are propagated
+	let err = RemotePlugin::spawn(env!("CARGO_BIN_EXE_kync-host"), "Cargo.toml").err().unwrap();
+	assert!(matches!(err.kind(), KyncErrorKind::LoadingError));
+}
+
+
+#[test]
+fn test_crash() {
+	// Let the plugin crash and ensure that the dead host is detected
+	let plugin = spawn_plugin();
+	let err = plugin.set_context(b"abort").unwrap_err();
+	assert!(matches!(err.kind(), KyncErrorKind::HostError));
+	let err = plugin.id().unwrap_err();
+	assert!(matches!(err.kind(), KyncErrorKind::HostError));
+}
@@ -0,0 +1,51 @@
+use kync::SecretBytes;
+
+
+#[test]
+fn test_secret_bytes() {
+	// Growing keeps the contents
+	let mut secret = SecretBytes::with_capacity(4);
+	secret.extend_from_slice(b"Test");
+	assert!(secret.capacity() >= 4);
+	secret.extend_from_slice(b"olope");
+	assert!(secret.capacity() >= 9);
+	assert_eq!(secret.as_ref(), b"Testolope");
+	
+	// Equality and cloning
+	assert_eq!(secret, SecretBytes::from(b"Testolope".as_ref()));
+	assert_ne!(secret, SecretBytes::from(b"Testolopf".as_ref()));
+	assert_ne!(secret, SecretBytes::from(b"Test".as_ref()));
+	assert_eq!(secret.clone(), secret);
+	
+	// The contents are never printed
+	assert_eq!(format!("{:?}", secret), "SecretBytes(<redacted>)");
+	
+	// Clearing keeps the allocation
+	let capacity = secret.capacity();
+	secret.clear();
+	assert!(secret.is_empty());
+	assert_eq!(secret.capacity(), capacity);
+	
+	// Allocation failures are reported instead of aborting
+	assert!(SecretBytes::try_with_capacity(usize::MAX).is_err());
+	assert!(secret.try_reserve(usize::MAX).is_err());
+	assert!(secret.is_empty());
+}
+
+
+#[test]
+#[cfg(all(target_os = "linux", feature = "locked-memory"))]
+fn test_locked() {
+	// Locked buffers consist of whole pages
+	let page_size = unsafe{ libc::sysconf(libc::_SC_PAGESIZE) } as usize;
+	let secret = SecretBytes::from(b"Testolope".as_ref());
+	assert_eq!(secret.capacity() % page_size, 0);
+	assert_eq!(secret.as_ref(), b"Testolope");
+	
+	// Locking may legitimately fail under a low `RLIMIT_MEMLOCK`
+	let mut limit = libc::rlimit { rlim_cur: 0, rlim_max: 0 };
+	assert_eq!(unsafe{ libc::getrlimit(libc::RLIMIT_MEMLOCK, &mut limit) }, 0);
+	if limit.rlim_cur >= 16 * page_size as libc::rlim_t {
+		assert!(secret.is_locked());
+	}
+}
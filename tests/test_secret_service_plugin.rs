@@ -0,0 +1,282 @@
+mod common;
+
+use kync::{ ErrorCode, KeyCapsule };
+use std::{
+	collections::HashMap, convert::TryFrom, env, fs,
+	io::{ BufRead, BufReader },
+	path::PathBuf,
+	process::{ Child, Command, Stdio },
+	sync::{ Arc, Mutex }
+};
+use zbus::{
+	fdo, interface,
+	blocking::connection::Builder,
+	object_server::{ ObjectServer, SignalEmitter },
+	zvariant::{ OwnedObjectPath, OwnedValue, Value }
+};
+
+
+/// The session bus address is global, so the tests must not run concurrently
+static SERIAL: Mutex<()> = Mutex::new(());
+
+
+/// The configuration of the private session bus
+const BUS_CONFIG: &str = r#"<busconfig>
+	<type>session</type>
+	<listen>unix:path={socket}</listen>
+	<auth>EXTERNAL</auth>
+	<policy context="default">
+		<allow send_destination="*" eavesdrop="true"/>
+		<allow eavesdrop="true"/>
+		<allow own="*"/>
+	</policy>
+</busconfig>
+"#;
+
+/// A private session bus that is stopped on drop
+struct SessionBus {
+	dir: PathBuf,
+	address: String,
+	process: Child
+}
+impl SessionBus {
+	/// Starts a new bus
+	fn start() -> Self {
+		let dir = env::temp_dir().join(format!("kync_test_secret_service_{}", std::process::id()));
+		let _ = fs::remove_dir_all(&dir);
+		fs::create_dir_all(&dir).unwrap();
+		let config = dir.join("bus.conf");
+		let socket = dir.join("bus");
+		fs::write(&config, BUS_CONFIG.replace("{socket}", &socket.to_string_lossy())).unwrap();
+		
+		// Start the daemon and read the address
+		let mut process = Command::new("dbus-daemon").arg("--config-file").arg(&config)
+			.args(["--nofork", "--print-address"]).stdout(Stdio::piped()).stderr(Stdio::null())
+			.spawn().expect("dbus-daemon is not installed");
+		let mut address = String::new();
+		BufReader::new(process.stdout.take().unwrap()).read_line(&mut address).unwrap();
+		Self { dir, address: address.trim().to_string(), process }
+	}
+}
+impl Drop for SessionBus {
+	fn drop(&mut self) {
+		let _ = self.process.kill();
+		let _ = self.process.wait();
+		let _ = fs::remove_dir_all(&self.dir);
+	}
+}
+
+
+const COLLECTION: &str = "/org/freedesktop/secrets/collection/login";
+const PROMPT: &str = "/org/freedesktop/secrets/prompt/unlock";
+
+/// A stored item of the mock Secret Service
+struct StoredItem {
+	attributes: HashMap<String, String>,
+	secret: Vec<u8>,
+	locked: bool
+}
+/// The state of the mock Secret Service
+#[derive(Default)]
+struct State {
+	/// The items (`None` if the item has been deleted)
+	items: Vec<Option<StoredItem>>,
+	/// Whether the unlock prompt is dismissed
+	dismiss: bool
+}
+type SharedState = Arc<Mutex<State>>;
+
+/// Creates an object path
+fn path(path: &str) -> OwnedObjectPath {
+	OwnedObjectPath::try_from(path.to_string()).unwrap()
+}
+
+/// The mock `org.freedesktop.Secret.Service` with the `default` alias and a single unlock prompt
+struct MockService(SharedState);
+#[interface(name = "org.freedesktop.Secret.Service")]
+impl MockService {
+	fn open_session(&self, algorithm: &str, _input: Value<'_>)
+		-> fdo::Result<(OwnedValue, OwnedObjectPath)>
+	{
+		match algorithm {
+			"plain" => Ok((OwnedValue::from(0u8), path("/org/freedesktop/secrets/session/1"))),
+			_ => Err(fdo::Error::NotSupported("Unsupported algorithm".into()))
+		}
+	}
+	fn read_alias(&self, name: &str) -> OwnedObjectPath {
+		match name {
+			"default" => path(COLLECTION),
+			_ => path("/")
+		}
+	}
+	fn search_items(&self, attributes: HashMap<String, String>)
+		-> (Vec<OwnedObjectPath>, Vec<OwnedObjectPath>)
+	{
+		let state = self.0.lock().unwrap();
+		let (mut unlocked, mut locked) = (Vec::new(), Vec::new());
+		for (index, item) in state.items.iter().enumerate() {
+			let item = match item {
+				Some(item) if attributes.iter().all(|(k, v)| item.attributes.get(k) == Some(v)) =>
+					item,
+				_ => continue
+			};
+			let list = if item.locked { &mut locked } else { &mut unlocked };
+			list.push(path(&format!("{}/{}", COLLECTION, index)));
+		}
+		(unlocked, locked)
+	}
+	fn unlock(&self, objects: Vec<OwnedObjectPath>) -> (Vec<OwnedObjectPath>, OwnedObjectPath) {
+		let state = self.0.lock().unwrap();
+		match state.items.iter().flatten().any(|item| item.locked) {
+			true => (Vec::new(), path(PROMPT)),
+			false => (objects, path("/"))
+		}
+	}
+}
+
+/// The mock `org.freedesktop.Secret.Collection` behind the `default` alias
+struct MockCollection(SharedState);
+#[interface(name = "org.freedesktop.Secret.Collection")]
+impl MockCollection {
+	async fn create_item(&self, mut properties: HashMap<String, OwnedValue>,
+		secret: (OwnedObjectPath, Vec<u8>, Vec<u8>, String), _replace: bool,
+		#[zbus(object_server)] server: &ObjectServer)
+		-> fdo::Result<(OwnedObjectPath, OwnedObjectPath)>
+	{
+		let attributes = properties.remove("org.freedesktop.Secret.Item.Attributes")
+			.and_then(|attributes| HashMap::try_from(attributes).ok())
+			.ok_or_else(|| fdo::Error::InvalidArgs("Missing attributes".into()))?;
+		let index = {
+			let mut state = self.0.lock().unwrap();
+			state.items.push(Some(StoredItem { attributes, secret: secret.2, locked: false }));
+			state.items.len() - 1
+		};
+		
+		// Register the item object
+		let item = path(&format!("{}/{}", COLLECTION, index));
+		server.at(&item, MockItem(self.0.clone(), index)).await?;
+		Ok((item, path("/")))
+	}
+}
+
+/// A mock `org.freedesktop.Secret.Item`
+struct MockItem(SharedState, usize);
+#[interface(name = "org.freedesktop.Secret.Item")]
+impl MockItem {
+	fn get_secret(&self, session: OwnedObjectPath)
+		-> fdo::Result<(OwnedObjectPath, Vec<u8>, Vec<u8>, String)>
+	{
+		let state = self.0.lock().unwrap();
+		match state.items[self.1].as_ref() {
+			Some(item) if !item.locked => Ok((session, Vec::new(), item.secret.clone(),
+				"application/octet-stream".to_string())),
+			Some(_) => Err(fdo::Error::AccessDenied("The item is locked".into())),
+			None => Err(fdo::Error::UnknownObject("The item has been deleted".into()))
+		}
+	}
+}
+
+/// The mock `org.freedesktop.Secret.Prompt` that unlocks all items unless it is dismissed
+struct MockPrompt(SharedState);
+#[interface(name = "org.freedesktop.Secret.Prompt")]
+impl MockPrompt {
+	async fn prompt(&self, _window_id: &str, #[zbus(signal_emitter)] emitter: SignalEmitter<'_>)
+		-> fdo::Result<()>
+	{
+		let dismissed = {
+			let mut state = self.0.lock().unwrap();
+			if !state.dismiss {
+				state.items.iter_mut().flatten().for_each(|item| item.locked = false);
+			}
+			state.dismiss
+		};
+		Self::completed(&emitter, dismissed, Value::from(Vec::<OwnedObjectPath>::new())).await?;
+		Ok(())
+	}
+	#[zbus(signal)]
+	async fn completed(emitter: &SignalEmitter<'_>, dismissed: bool, result: Value<'_>)
+		-> zbus::Result<()>;
+}
+
+/// Locks all items and sets whether the unlock prompt is dismissed
+fn lock_all(state: &SharedState, dismiss: bool) {
+	let mut state = state.lock().unwrap();
+	state.items.iter_mut().flatten().for_each(|item| item.locked = true);
+	state.dismiss = dismiss;
+}
+
+
+const FORMAT_UID: &[u8] = b"SecretServiceCapsuleFormat.42C50C2F-4B5C-41F2-9C72-68F7085A6068";
+const KEY: &[u8] = b"2nwBK-EkfXW-yWSQv-Vkab3-USHvX-WNJxa-GeXFJ-ecsjJ-imnft";
+
+
+#[test]
+fn test() {
+	let _serial = SERIAL.lock().unwrap_or_else(|e| e.into_inner());
+	let plugin = common::load_plugin("kync_secret_service_plugin");
+	assert_eq!(plugin.id().unwrap(), FORMAT_UID);
+	env::set_var("DBUS_SESSION_BUS_ADDRESS", "unix:path=/nonexistent/bus");
+	let err = plugin.configs().unwrap_err();
+	assert_eq!(err.code(), Some(ErrorCode::DeviceUnavailable));
+}
+
+
+#[test]
+fn test_secret_service() {
+	let _serial = SERIAL.lock().unwrap_or_else(|e| e.into_inner());
+	if !common::is_installed("dbus-daemon") {
+		return eprintln!("dbus-daemon is not installed; skipping the Secret Service tests")
+	}
+	let plugin = common::load_plugin("kync_secret_service_plugin");
+	
+	// Start a private bus without a Secret Service
+	let bus = SessionBus::start();
+	env::set_var("DBUS_SESSION_BUS_ADDRESS", &bus.address);
+	let err = plugin.configs().unwrap_err();
+	assert_eq!(err.code(), Some(ErrorCode::DeviceUnavailable));
+	
+	// Start the mock Secret Service
+	let state = SharedState::default();
+	let _service = Builder::address(bus.address.as_str()).unwrap()
+		.name("org.freedesktop.secrets").unwrap()
+		.serve_at("/org/freedesktop/secrets", MockService(state.clone())).unwrap()
+		.serve_at(COLLECTION, MockCollection(state.clone())).unwrap()
+		.serve_at(PROMPT, MockPrompt(state.clone())).unwrap()
+		.build().unwrap();
+	assert_eq!(plugin.configs().unwrap(), vec![b"default".to_vec()]);
+	assert_eq!(plugin.auth_info_protect(b"default").unwrap(), (false, u64::MAX));
+	assert_eq!(plugin.auth_info_recover(b"default").unwrap(), (false, u64::MAX));
+	
+	// Protect and recover a key; the item attributes contain the context
+	plugin.set_context(b"kync-test").unwrap();
+	let protected = plugin.protect(KEY, b"default", None).unwrap();
+	assert_eq!(plugin.recover(&protected, None).unwrap().as_ref(), KEY);
+	{
+		let state = state.lock().unwrap();
+		let item = state.items[0].as_ref().unwrap();
+		assert_eq!(item.attributes["kync:context"], "kync-test");
+		assert_eq!(item.secret, KEY);
+	}
+	
+	// Locked items are unlocked with a prompt
+	lock_all(&state, false);
+	assert_eq!(plugin.recover(&protected, None).unwrap().as_ref(), KEY);
+	lock_all(&state, true);
+	let err = plugin.recover(&protected, None).unwrap_err();
+	assert_eq!(err.code(), Some(ErrorCode::Cancelled));
+	
+	// Deleted items
+	state.lock().unwrap().items.iter_mut().for_each(|item| *item = None);
+	let err = plugin.recover(&protected, None).unwrap_err();
+	assert_eq!(err.code(), Some(ErrorCode::DeviceUnavailable));
+	
+	// Context, config and capsule errors
+	let err = plugin.set_context(b"\xff").unwrap_err();
+	assert_eq!(err.code(), Some(ErrorCode::InvalidConfig));
+	let err = plugin.protect(KEY, b"session", None).unwrap_err();
+	assert_eq!(err.code(), Some(ErrorCode::DeviceUnavailable));
+	let err = plugin.protect(KEY, b"Invalid", None).unwrap_err();
+	assert_eq!(err.code(), Some(ErrorCode::InvalidConfig));
+	let err = plugin.recover(&protected[..10], None).unwrap_err();
+	assert_eq!(err.code(), Some(ErrorCode::InvalidCapsule));
+}
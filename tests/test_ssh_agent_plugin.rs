@@ -0,0 +1,134 @@
+#![cfg(unix)]
+
+mod common;
+
+use kync::{ ErrorCode, KeyCapsule };
+use std::{
+	env, fs, thread,
+	path::{ Path, PathBuf },
+	process::{ Child, Command, Stdio },
+	sync::Mutex, time::Duration
+};
+
+
+/// The agent socket is global, so the tests must not run concurrently
+static SERIAL: Mutex<()> = Mutex::new(());
+
+
+/// A locally spawned ssh-agent that is killed on drop
+struct SshAgent {
+	dir: PathBuf,
+	socket: PathBuf,
+	process: Child
+}
+impl SshAgent {
+	/// Spawns a new agent
+	fn spawn() -> Self {
+		let dir = env::temp_dir().join(format!("kync_test_ssh_agent_{}", std::process::id()));
+		let _ = fs::remove_dir_all(&dir);
+		fs::create_dir_all(&dir).unwrap();
+		let socket = dir.join("agent.sock");
+		let process = Command::new("ssh-agent").arg("-D").arg("-a").arg(&socket)
+			.stdout(Stdio::null()).spawn().expect("OpenSSH is not installed");
+		
+		// Wait until the agent listens (the agent is killed on drop if it does not start)
+		let agent = Self { dir, socket, process };
+		for _ in 0..100 {
+			if agent.socket.exists() {
+				return agent
+			}
+			thread::sleep(Duration::from_millis(50));
+		}
+		panic!("The ssh-agent did not start")
+	}
+	/// Generates a new key of `key_type` and returns its path
+	fn keygen(&self, key_type: &str) -> PathBuf {
+		let path = self.dir.join(key_type);
+		let status = Command::new("ssh-keygen").args(["-q", "-t", key_type, "-N", "", "-f"])
+			.arg(&path).status().unwrap();
+		assert!(status.success());
+		path
+	}
+	/// Runs `ssh-add` with `args` against the agent
+	fn ssh_add(&self, args: &[&Path]) {
+		let status = Command::new("ssh-add").args(args).env("SSH_AUTH_SOCK", &self.socket)
+			.stderr(Stdio::null()).status().unwrap();
+		assert!(status.success());
+	}
+}
+impl Drop for SshAgent {
+	fn drop(&mut self) {
+		let _ = self.process.kill();
+		let _ = self.process.wait();
+		let _ = fs::remove_dir_all(&self.dir);
+	}
+}
+
+/// Gets the SHA256 fingerprint of the key at `path`
+fn fingerprint(path: &Path) -> Vec<u8> {
+	let output = Command::new("ssh-keygen").args(["-l", "-E", "sha256", "-f"]).arg(path)
+		.output().unwrap();
+	let output = String::from_utf8(output.stdout).unwrap();
+	output.split_whitespace().nth(1).unwrap().as_bytes().to_vec()
+}
+
+
+const FORMAT_UID: &[u8] = b"SshAgentCapsuleFormat.74EEA87F-313E-4ECE-AF08-4291C50B6C40";
+const KEY: &[u8] = b"2nwBK-EkfXW-yWSQv-Vkab3-USHvX-WNJxa-GeXFJ-ecsjJ-imnft";
+
+
+#[test]
+fn test() {
+	let _serial = SERIAL.lock().unwrap_or_else(|e| e.into_inner());
+	let plugin = common::load_plugin("kync_ssh_agent_plugin");
+	assert_eq!(plugin.id().unwrap(), FORMAT_UID);
+	plugin.set_context(b"/nonexistent/agent.sock").unwrap();
+	let err = plugin.configs().unwrap_err();
+	assert_eq!(err.code(), Some(ErrorCode::DeviceUnavailable));
+}
+
+
+#[test]
+fn test_agent() {
+	let _serial = SERIAL.lock().unwrap_or_else(|e| e.into_inner());
+	if !["ssh-agent", "ssh-add", "ssh-keygen"].iter().all(|name| common::is_installed(name)) {
+		return eprintln!("OpenSSH is not installed; skipping the agent tests")
+	}
+	let plugin = common::load_plugin("kync_ssh_agent_plugin");
+	
+	// Spawn an agent with an Ed25519 and an ECDSA key
+	let agent = SshAgent::spawn();
+	let (ed25519, ecdsa) = (agent.keygen("ed25519"), agent.keygen("ecdsa"));
+	agent.ssh_add(&[&ed25519, &ecdsa]);
+	plugin.set_context(agent.socket.to_str().unwrap().as_bytes()).unwrap();
+	
+	// Only the Ed25519 key is offered
+	let config = fingerprint(&ed25519);
+	assert_eq!(plugin.configs().unwrap(), vec![config.clone()]);
+	assert_eq!(plugin.auth_info_protect(&config).unwrap(), (false, u64::MAX));
+	assert_eq!(plugin.auth_info_recover(&config).unwrap(), (false, u64::MAX));
+	
+	// Protect and recover a key; every capsule uses a new challenge
+	let protected = plugin.protect(KEY, &config, None).unwrap();
+	assert_ne!(plugin.protect(KEY, &config, None).unwrap(), protected);
+	assert_eq!(plugin.recover(&protected, None).unwrap().as_ref(), KEY);
+	
+	// The key must be in the agent, but it does not matter how it got there
+	agent.ssh_add(&["-D".as_ref()]);
+	let err = plugin.recover(&protected, None).unwrap_err();
+	assert_eq!(err.code(), Some(ErrorCode::DeviceUnavailable));
+	agent.ssh_add(&[&ed25519]);
+	assert_eq!(plugin.recover(&protected, None).unwrap().as_ref(), KEY);
+	
+	// Config and capsule errors
+	let err = plugin.protect(KEY, &fingerprint(&ecdsa), None).unwrap_err();
+	assert_eq!(err.code(), Some(ErrorCode::InvalidConfig));
+	let err = plugin.protect(KEY, b"Invalid", None).unwrap_err();
+	assert_eq!(err.code(), Some(ErrorCode::InvalidConfig));
+	let err = plugin.recover(&protected[..10], None).unwrap_err();
+	assert_eq!(err.code(), Some(ErrorCode::InvalidCapsule));
+	let mut corrupt = protected.clone();
+	*corrupt.last_mut().unwrap() ^= 0x01;
+	let err = plugin.recover(&corrupt, None).unwrap_err();
+	assert_eq!(err.code(), Some(ErrorCode::InvalidCapsule));
+}
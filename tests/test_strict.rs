@@ -0,0 +1,37 @@
+mod common;
+
+use kync::{ KeyCapsule, KyncErrorKind };
+
+
+const FORMAT_UID: &[u8] = b"TestCapsuleFormat.3A0351A7-FE90-4383-9E68-FCC20033D5F1";
+
+
+// This test lives in its own binary because switching the test plugin into legacy or split-ID mode
+// affects the whole process
+#[test]
+fn test_strict() {
+	let mut plugin = common::load_plugin("kync_test_plugin");
+	assert!(plugin.is_strict());
+	assert_eq!(plugin.configs().unwrap(), [b"Default".to_vec()]);
+	
+	// Legacy plugins are rejected in strict mode
+	plugin.set_context(b"legacy").unwrap();
+	let err = plugin.configs().unwrap_err();
+	assert_eq!(err.kind(), KyncErrorKind::ConfigsError);
+	assert_eq!(err.message(), "The plugin did not write any config");
+	
+	// ... and accepted in lenient mode
+	plugin.set_strict(false);
+	assert!(plugin.configs().unwrap().is_empty());
+	
+	// IDs that are written in more than one segment are rejected in strict mode ...
+	plugin.set_strict(true);
+	plugin.set_context(b"split-id").unwrap();
+	let err = plugin.id().unwrap_err();
+	assert_eq!(err.kind(), KyncErrorKind::IdError);
+	assert_eq!(err.message(), "The plugin wrote its ID using 2 instead of exactly one `write`-call");
+	
+	// ... and concatenated in lenient mode
+	plugin.set_strict(false);
+	assert_eq!(plugin.id().unwrap(), FORMAT_UID);
+}
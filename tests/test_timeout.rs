@@ -0,0 +1,51 @@
+mod common;
+
+use kync::{
+	KeyCapsule, KyncErrorKind, RemotePlugin,
+	plugin::DEFAULT_TIMEOUT
+};
+use std::time::{ Duration, Instant };
+
+
+const FORMAT_UID: &[u8] = b"TestCapsuleFormat.3A0351A7-FE90-4383-9E68-FCC20033D5F1";
+const TIMEOUT: Duration = Duration::from_millis(200);
+
+
+#[test]
+fn test_plugin() {
+	let mut plugin = common::load_plugin("kync_test_plugin");
+	assert_eq!(plugin.timeout(), Some(DEFAULT_TIMEOUT));
+	
+	// Calls without deadline are performed on the current thread
+	plugin.set_timeout(None);
+	assert_eq!(plugin.id().unwrap(), FORMAT_UID);
+	
+	// The hanging call is abandoned and poisons the plugin
+	plugin.set_timeout(Some(TIMEOUT));
+	let start = Instant::now();
+	let err = plugin.set_context(b"hang").unwrap_err();
+	assert!(matches!(err.kind(), KyncErrorKind::Timeout));
+	assert!(start.elapsed() < DEFAULT_TIMEOUT);
+	for timeout in [Some(TIMEOUT), None] {
+		plugin.set_timeout(timeout);
+		let err = plugin.id().unwrap_err();
+		assert!(matches!(err.kind(), KyncErrorKind::Poisoned));
+		let err = plugin.recover_to(&mut Vec::new(), b"Data", None).unwrap_err();
+		assert!(matches!(err.kind(), KyncErrorKind::Poisoned));
+	}
+}
+
+
+#[test]
+fn test_remote() {
+	let path = common::plugin_path("kync_test_plugin");
+	let mut plugin = RemotePlugin::spawn(env!("CARGO_BIN_EXE_kync-host"), path).unwrap();
+	assert_eq!(plugin.timeout(), Some(DEFAULT_TIMEOUT));
+	plugin.set_timeout(Some(TIMEOUT));
+	
+	// The hanging host is killed
+	let err = plugin.set_context(b"hang").unwrap_err();
+	assert!(matches!(err.kind(), KyncErrorKind::Timeout));
+	let err = plugin.id().unwrap_err();
+	assert!(matches!(err.kind(), KyncErrorKind::HostError));
+}